@@ -0,0 +1,379 @@
+use std::io;
+use std::process::Stdio;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+
+use crate::exec;
+use crate::storage::Record;
+
+/// How long a webhook may take before the alert is given up.
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long a finishing job waits for alerts still being delivered, so the
+/// alert about its last run is not lost when the process exits.
+const DELIVERY_WAIT: Duration = Duration::from_secs(15);
+
+/// Default minimum time between two alerts of a job.
+pub const DEFAULT_COOLDOWN: Duration = Duration::from_secs(600);
+
+/// Why an alert fires.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Trigger {
+    /// The run exited with a non-zero code (or could not run at all).
+    Failed,
+    /// The run was killed by its timeout.
+    TimedOut,
+    /// The value rose above the threshold.
+    Above(f64),
+    /// The value fell below the threshold.
+    Below(f64),
+}
+
+impl Trigger {
+    /// Short name for the `reason` of the alert.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Trigger::Failed => "failed",
+            Trigger::TimedOut => "timeout",
+            Trigger::Above(_) => "above",
+            Trigger::Below(_) => "below",
+        }
+    }
+
+    fn threshold(&self) -> Option<f64> {
+        match self {
+            Trigger::Above(n) | Trigger::Below(n) => Some(*n),
+            _ => None,
+        }
+    }
+}
+
+/// When a job alerts and where the alerts go.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlertOptions {
+    /// Alert when the value of a successful run is above this.
+    pub above: Option<f64>,
+    /// Alert when the value of a successful run is below this.
+    pub below: Option<f64>,
+    /// URL the alert is POSTed to as JSON.
+    pub webhook: Option<String>,
+    /// Shell command run for each alert, with the record in `TREP_*`
+    /// environment variables.
+    pub command: Option<String>,
+    /// Minimum time between two alerts.
+    pub cooldown: Duration,
+}
+
+impl Default for AlertOptions {
+    fn default() -> Self {
+        Self {
+            above: None,
+            below: None,
+            webhook: None,
+            command: None,
+            cooldown: DEFAULT_COOLDOWN,
+        }
+    }
+}
+
+/// One alert, as sent to the webhook and the alert command.
+#[derive(Debug, Clone)]
+pub struct Alert {
+    pub job: String,
+    pub trigger: Trigger,
+    pub record: Record,
+    /// Alerts held back by the cooldown since the previous one was sent.
+    pub suppressed: u32,
+}
+
+impl Alert {
+    /// The webhook body.
+    pub fn to_json(&self) -> Value {
+        json!({
+            "job": self.job,
+            "reason": self.trigger.reason(),
+            "threshold": self.trigger.threshold(),
+            "suppressed": self.suppressed,
+            "record": self.record,
+        })
+    }
+
+    /// The alert command's environment.
+    pub fn env(&self) -> Vec<(&'static str, String)> {
+        let mut env = vec![
+            ("TREP_JOB", self.job.clone()),
+            ("TREP_REASON", self.trigger.reason().to_string()),
+            ("TREP_SUPPRESSED", self.suppressed.to_string()),
+            ("TREP_TIMESTAMP", self.record.timestamp.clone()),
+            ("TREP_VALUE", self.record.value.clone()),
+            ("TREP_EXIT_CODE", self.record.exit_code.to_string()),
+            (
+                "TREP_FIELDS",
+                Value::Object(self.record.fields.clone()).to_string(),
+            ),
+        ];
+        if let Some(threshold) = self.trigger.threshold() {
+            env.push(("TREP_THRESHOLD", threshold.to_string()));
+        }
+        env
+    }
+}
+
+/// Checks each run of a job and sends alerts, at most one per cooldown.
+pub struct Alerter {
+    job: String,
+    opts: AlertOptions,
+    agent: ureq::Agent,
+    last_sent: Option<Instant>,
+    suppressed: u32,
+    /// Alerts being delivered.
+    deliveries: Vec<JoinHandle<()>>,
+}
+
+impl Alerter {
+    pub fn new(job: &str, opts: AlertOptions) -> Self {
+        let agent = ureq::Agent::config_builder()
+            .timeout_global(Some(WEBHOOK_TIMEOUT))
+            .build()
+            .into();
+        Self {
+            job: job.to_string(),
+            opts,
+            agent,
+            last_sent: None,
+            suppressed: 0,
+            deliveries: Vec::new(),
+        }
+    }
+
+    /// Alert about `record` if it calls for one. Delivery happens on its own
+    /// thread, so a slow webhook does not hold up the next run.
+    pub fn check(&mut self, record: &Record, timed_out: bool) {
+        let Some(trigger) = trigger(record, timed_out, self.opts.above, self.opts.below) else {
+            return;
+        };
+        let Some(suppressed) = self.due(Instant::now()) else {
+            tracing::debug!(reason = trigger.reason(), "alert held back by the cooldown");
+            return;
+        };
+        let alert = Alert {
+            job: self.job.clone(),
+            trigger,
+            record: record.clone(),
+            suppressed,
+        };
+        tracing::info!(reason = trigger.reason(), suppressed, "sending alert");
+        let agent = self.agent.clone();
+        let webhook = self.opts.webhook.clone();
+        let command = self.opts.command.clone();
+        self.deliveries.retain(|d| !d.is_finished());
+        self.deliveries.push(thread::spawn(move || {
+            deliver(&alert, &agent, webhook.as_deref(), command.as_deref())
+        }));
+    }
+
+    /// Wait up to `limit` for the alerts still being delivered. The alerter
+    /// does this when dropped, as a job finishes.
+    pub fn finish(&mut self, limit: Duration) {
+        let deadline = Instant::now() + limit;
+        while self.deliveries.iter().any(|d| !d.is_finished()) {
+            if Instant::now() >= deadline {
+                tracing::warn!(
+                    job = %self.job,
+                    pending = self.deliveries.iter().filter(|d| !d.is_finished()).count(),
+                    "gave up waiting for alerts to be delivered"
+                );
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        for delivery in self.deliveries.drain(..) {
+            let _ = delivery.join();
+        }
+    }
+
+    /// Whether an alert may go out at `now`, and how many were held back
+    /// before it. Alerts within the cooldown of the last one are counted
+    /// instead.
+    fn due(&mut self, now: Instant) -> Option<u32> {
+        if let Some(last) = self.last_sent {
+            if now.saturating_duration_since(last) < self.opts.cooldown {
+                self.suppressed += 1;
+                return None;
+            }
+        }
+        self.last_sent = Some(now);
+        Some(std::mem::take(&mut self.suppressed))
+    }
+}
+
+impl Drop for Alerter {
+    fn drop(&mut self) {
+        self.finish(DELIVERY_WAIT);
+    }
+}
+
+/// Why `record` should alert, if it should: a timeout or failure first,
+/// then the thresholds for the numeric value of a successful run.
+pub fn trigger(
+    record: &Record,
+    timed_out: bool,
+    above: Option<f64>,
+    below: Option<f64>,
+) -> Option<Trigger> {
+    if timed_out {
+        return Some(Trigger::TimedOut);
+    }
+    if record.exit_code != 0 {
+        return Some(Trigger::Failed);
+    }
+    let value: f64 = record.value.trim().parse().ok()?;
+    match (above, below) {
+        (Some(max), _) if value > max => Some(Trigger::Above(max)),
+        (_, Some(min)) if value < min => Some(Trigger::Below(min)),
+        _ => None,
+    }
+}
+
+/// Send `alert` to the webhook and run the alert command. Failures are
+/// logged; neither may stop the job.
+fn deliver(alert: &Alert, agent: &ureq::Agent, webhook: Option<&str>, command: Option<&str>) {
+    if let Some(url) = webhook {
+        if let Err(e) = post(agent, url, alert) {
+            tracing::warn!(job = %alert.job, url, error = %e, "alert webhook failed");
+        }
+    }
+    if let Some(command) = command {
+        if let Err(e) = run_command(command, alert) {
+            tracing::warn!(job = %alert.job, command, error = %e, "alert command failed");
+        }
+    }
+}
+
+fn post(agent: &ureq::Agent, url: &str, alert: &Alert) -> io::Result<()> {
+    agent
+        .post(url)
+        .header("Content-Type", "application/json")
+        .send(alert.to_json().to_string())
+        .map(drop)
+        .map_err(io::Error::other)
+}
+
+fn run_command(command: &str, alert: &Alert) -> io::Result<()> {
+    let output = exec::shell_command(command)
+        .envs(alert.env())
+        .stdin(Stdio::null())
+        .output()?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    Err(io::Error::other(format!(
+        "exited with {}: {}",
+        output.status,
+        stderr.trim()
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+
+    #[test]
+    fn triggers_on_failures_and_thresholds() {
+        let ok = |value| Record::new("t", value, 0);
+        assert_eq!(
+            trigger(&ok("95"), false, Some(90.0), None),
+            Some(Trigger::Above(90.0))
+        );
+        assert_eq!(trigger(&ok("90"), false, Some(90.0), None), None);
+        assert_eq!(
+            trigger(&ok("-3"), false, None, Some(0.0)),
+            Some(Trigger::Below(0.0))
+        );
+        assert_eq!(trigger(&ok("n/a"), false, Some(90.0), Some(0.0)), None);
+        assert_eq!(trigger(&ok("95"), false, None, None), None);
+        let failed = Record::new("t", "95", 2);
+        assert_eq!(trigger(&failed, false, None, None), Some(Trigger::Failed));
+        let killed = Record::new("t", "error: command timed out after 1s", -1);
+        assert_eq!(trigger(&killed, true, None, None), Some(Trigger::TimedOut));
+    }
+
+    #[test]
+    fn holds_alerts_back_during_the_cooldown() {
+        let opts = AlertOptions {
+            cooldown: Duration::from_secs(60),
+            ..AlertOptions::default()
+        };
+        let mut alerter = Alerter::new("x", opts);
+        let start = Instant::now();
+        assert_eq!(alerter.due(start), Some(0));
+        assert_eq!(alerter.due(start + Duration::from_secs(10)), None);
+        assert_eq!(alerter.due(start + Duration::from_secs(59)), None);
+        assert_eq!(alerter.due(start + Duration::from_secs(60)), Some(2));
+        assert_eq!(alerter.due(start + Duration::from_secs(61)), None);
+    }
+
+    #[test]
+    fn posts_the_alert_and_runs_the_command() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line == "\r\n" {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        length = value.trim().parse().unwrap();
+                    }
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            reader
+                .get_mut()
+                .write_all(b"HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n")
+                .unwrap();
+            serde_json::from_slice::<Value>(&body).unwrap()
+        });
+
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("env.txt");
+        let mut record = Record::new("2025-01-01T00:00:00Z", "95", 0);
+        record.fields.insert("unit".into(), "%".into());
+        let alert = Alert {
+            job: "disk".to_string(),
+            trigger: Trigger::Above(90.0),
+            record,
+            suppressed: 3,
+        };
+        let agent = Alerter::new("disk", AlertOptions::default()).agent.clone();
+        let command = format!(
+            "echo \"$TREP_JOB $TREP_REASON $TREP_VALUE $TREP_THRESHOLD $TREP_SUPPRESSED $TREP_FIELDS\" > '{}'",
+            out.display()
+        );
+        deliver(&alert, &agent, Some(&url), Some(&command));
+
+        let body = server.join().unwrap();
+        assert_eq!(body["job"], "disk");
+        assert_eq!(body["reason"], "above");
+        assert_eq!(body["threshold"], 90.0);
+        assert_eq!(body["suppressed"], 3);
+        assert_eq!(body["record"]["value"], "95");
+        assert_eq!(body["record"]["unit"], "%");
+        assert_eq!(
+            std::fs::read_to_string(&out).unwrap().trim(),
+            "disk above 95 90 3 {\"unit\":\"%\"}"
+        );
+    }
+}
@@ -0,0 +1,54 @@
+use std::io;
+
+use clap::Parser;
+
+use crate::cli::{Cli, Commands, RunOpts};
+use crate::{
+    check, coverage, export, gaps, install, last, logging, overhead, prune, repair, report, serve,
+    sla, start, status, top, Job, Stop,
+};
+
+/// The `trep` command line: parse the arguments, run the subcommand and
+/// exit with status 1 if it fails.
+pub fn main() {
+    let cli = Cli::parse();
+    let guard = match logging::init(cli.log_target, cli.trace.as_deref()) {
+        Ok(guard) => guard,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            std::process::exit(1);
+        }
+    };
+    let result = match cli.command {
+        Commands::Run(opts) => run(*opts),
+        Commands::Start(opts) => start::start(opts),
+        Commands::List(opts) => status::list(opts),
+        Commands::Status(opts) => status::status(opts),
+        Commands::Stop(opts) => status::stop(opts),
+        Commands::Export(opts) => export::export(opts),
+        Commands::Report(opts) => report::report(opts),
+        Commands::Last(opts) => last::last(opts),
+        Commands::Top(opts) => top::top(opts),
+        Commands::Serve(opts) => serve::serve(opts),
+        Commands::Coverage(opts) => coverage::coverage(opts),
+        Commands::Gaps(opts) => gaps::gaps(opts),
+        Commands::Sla(opts) => sla::sla(opts),
+        Commands::Overhead(opts) => overhead::overhead(opts),
+        Commands::Validate(opts) => check::validate(opts),
+        Commands::Repair(opts) => repair::repair(opts),
+        Commands::Prune(opts) => prune::prune(opts),
+        Commands::Install(target) => install::install(target),
+    };
+    // Send the last spans before exiting.
+    drop(guard);
+    if let Err(e) = result {
+        eprintln!("Error: {e}");
+        std::process::exit(1);
+    }
+}
+
+fn run(opts: RunOpts) -> io::Result<()> {
+    let job = Job::from_opts(opts)?;
+    job.run(&Stop::on_signals()?)?;
+    Ok(())
+}
@@ -0,0 +1,139 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use chrono::DateTime;
+use serde::{Deserialize, Serialize};
+
+use crate::storage::Record;
+
+/// The last stored sample, as `--on-change` compares new samples with it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Last {
+    timestamp: String,
+    value: String,
+    exit_code: i32,
+}
+
+/// Decides which samples `--on-change` stores: those whose value or exit
+/// code differs from the last stored one, and with a heartbeat, one per
+/// heartbeat even if nothing changed. The last stored sample is kept in a
+/// state file, so a restarted job (or the next `--single-shot` run) goes
+/// on comparing with it.
+pub struct OnChange {
+    path: PathBuf,
+    heartbeat: Option<Duration>,
+    last: Option<Last>,
+}
+
+impl OnChange {
+    /// Pick up the last stored sample from the state file at `path`. A
+    /// missing or unreadable file means the next sample is stored.
+    pub fn load(path: PathBuf, heartbeat: Option<Duration>) -> Self {
+        let last = match fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .map_err(|e| {
+                    tracing::warn!(path = %dunce::simplified(&path).display(), error = %e, "ignoring unreadable --on-change state");
+                })
+                .ok(),
+            Err(_) => None,
+        };
+        Self {
+            path,
+            heartbeat,
+            last,
+        }
+    }
+
+    /// Whether `record` is to be stored: it differs from the last stored
+    /// sample, or a heartbeat is due.
+    pub fn changed(&self, record: &Record) -> bool {
+        match &self.last {
+            None => true,
+            Some(last) => {
+                last.value != record.value
+                    || last.exit_code != record.exit_code
+                    || self.heartbeat_due(last, record)
+            }
+        }
+    }
+
+    /// Make `record`, which has been stored, the sample the next ones are
+    /// compared with.
+    pub fn commit(&mut self, record: &Record) {
+        let last = Last {
+            timestamp: record.timestamp.clone(),
+            value: record.value.clone(),
+            exit_code: record.exit_code,
+        };
+        if let Err(e) = self.save(&last) {
+            tracing::warn!(path = %dunce::simplified(&self.path).display(), error = %e, "could not save --on-change state");
+        }
+        self.last = Some(last);
+    }
+
+    /// Whether a heartbeat has passed since `last` was stored. Timestamps
+    /// that do not parse count as due.
+    fn heartbeat_due(&self, last: &Last, record: &Record) -> bool {
+        let Some(heartbeat) = self.heartbeat else {
+            return false;
+        };
+        let parse = |t: &str| DateTime::parse_from_rfc3339(t).ok();
+        match (parse(&last.timestamp), parse(&record.timestamp)) {
+            (Some(then), Some(now)) => (now - then)
+                .to_std()
+                .is_ok_and(|elapsed| elapsed >= heartbeat),
+            _ => true,
+        }
+    }
+
+    /// Replace the state file in one step.
+    fn save(&self, last: &Last) -> io::Result<()> {
+        let tmp = self.path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_vec(last)?)?;
+        fs::rename(&tmp, &self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stores_changes_and_heartbeats() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("job.last.json");
+        let mut changes = OnChange::load(path.clone(), Some(Duration::from_secs(3600)));
+        let sample = |time: &str, value: &str, exit_code| {
+            Record::new(format!("2025-01-01T{time}:00+00:00"), value, exit_code)
+        };
+        // Store every sample that is due, as the job does.
+        let check = |changes: &mut OnChange, record: Record| {
+            let changed = changes.changed(&record);
+            if changed {
+                changes.commit(&record);
+            }
+            changed
+        };
+        assert!(check(&mut changes, sample("00:00", "1.2.3", 0)));
+        assert!(!check(&mut changes, sample("00:10", "1.2.3", 0)));
+        assert!(check(&mut changes, sample("00:20", "1.2.3", 1)));
+        assert!(check(&mut changes, sample("00:30", "1.2.4", 1)));
+        assert!(!check(&mut changes, sample("01:29", "1.2.4", 1)));
+        assert!(check(&mut changes, sample("01:30", "1.2.4", 1)));
+
+        // A sample that was not stored does not become the baseline.
+        assert!(changes.changed(&sample("01:40", "1.2.5", 1)));
+        assert!(changes.changed(&sample("01:50", "1.2.5", 1)));
+
+        // A restarted job compares with what it stored before.
+        let mut restarted = OnChange::load(path.clone(), None);
+        assert!(!check(&mut restarted, sample("09:00", "1.2.4", 1)));
+        assert!(check(&mut restarted, sample("09:10", "1.2.5", 0)));
+
+        fs::write(&path, "garbage").unwrap();
+        let fresh = OnChange::load(path, None);
+        assert!(fresh.changed(&sample("10:00", "1.2.5", 0)));
+    }
+}
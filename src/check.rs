@@ -0,0 +1,296 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, FixedOffset};
+
+use crate::cli::ValidateOpts;
+use crate::storage::{self, Record};
+
+/// Columns every CSV data file starts with.
+const CORE_COLUMNS: [&str; 3] = ["timestamp", "value", "exit_code"];
+
+/// A problem found in a data file, located by 1-based line number.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Problem {
+    pub path: PathBuf,
+    pub line: u64,
+    pub message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.path.display(), self.line, self.message)
+    }
+}
+
+/// Timestamp of the last record seen, carried across a job's files so
+/// ordering is checked over the whole history.
+#[derive(Debug, Default)]
+pub struct Previous(Option<DateTime<FixedOffset>>);
+
+pub fn validate(opts: ValidateOpts) -> io::Result<()> {
+    // A database keeps its own integrity and gzipped days are archived as
+    // they were; only plain day files are checked.
+    let files: Vec<_> = storage::job_data_files(&opts.name)?
+        .into_iter()
+        .filter(|f| !f.is_database() && !f.is_compressed())
+        .collect();
+    let mut previous = Previous::default();
+    let mut records = 0;
+    let mut problems = Vec::new();
+    for file in &files {
+        let (n, found) = check_file(&file.path, &mut previous)?;
+        records += n;
+        problems.extend(found);
+    }
+    for problem in &problems {
+        println!("{problem}");
+    }
+    println!(
+        "Checked {records} records in {} files of '{}': {} problems",
+        files.len(),
+        opts.name,
+        problems.len()
+    );
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "job '{}' has {} problems; `trep repair {}` fixes the common ones",
+                opts.name,
+                problems.len(),
+                opts.name
+            ),
+        ))
+    }
+}
+
+/// Check one CSV or JSONL data file, chosen by extension. Returns the number
+/// of records read and the problems found.
+pub fn check_file(path: &Path, previous: &mut Previous) -> io::Result<(usize, Vec<Problem>)> {
+    let bytes = fs::read(path)?;
+    let mut problems = Vec::new();
+    let mut problem = |line: u64, message: String| {
+        problems.push(Problem {
+            path: path.to_path_buf(),
+            line,
+            message,
+        })
+    };
+    let contents = match std::str::from_utf8(&bytes) {
+        Ok(s) => s,
+        Err(e) => {
+            let valid = &bytes[..e.valid_up_to()];
+            let line = valid.iter().filter(|&&b| b == b'\n').count() as u64 + 1;
+            problem(line, "invalid UTF-8".to_string());
+            return Ok((0, problems));
+        }
+    };
+    if !contents.is_empty() && !contents.ends_with('\n') {
+        problem(
+            contents.lines().count() as u64,
+            "last line has no line ending (partially written?)".to_string(),
+        );
+    }
+    let contents = contents.strip_prefix('\u{feff}').unwrap_or(contents);
+    let records = if path.extension().is_some_and(|e| e == "jsonl") {
+        check_jsonl(contents, previous, &mut problem)
+    } else {
+        check_csv(contents, previous, &mut problem)
+    };
+    problems.sort_by_key(|p| p.line);
+    Ok((records, problems))
+}
+
+fn check_jsonl(
+    contents: &str,
+    previous: &mut Previous,
+    problem: &mut impl FnMut(u64, String),
+) -> usize {
+    let mut records = 0;
+    for (i, line) in contents.lines().enumerate() {
+        let line_no = i as u64 + 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<Record>(line) {
+            Ok(record) => {
+                records += 1;
+                check_timestamp(&record.timestamp, line_no, previous, problem);
+            }
+            Err(e) => problem(line_no, format!("invalid JSON record: {e}")),
+        }
+    }
+    records
+}
+
+fn check_csv(
+    contents: &str,
+    previous: &mut Previous,
+    problem: &mut impl FnMut(u64, String),
+) -> usize {
+    let mut rdr = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(contents.as_bytes());
+    let mut header: Option<csv::StringRecord> = None;
+    let mut columns: Option<usize> = None;
+    let mut records = 0;
+    for (i, row) in rdr.records().enumerate() {
+        let row = match row {
+            Ok(row) => row,
+            Err(e) => {
+                let line = e.position().map_or(0, |p| p.line());
+                problem(line, format!("unreadable CSV row: {e}"));
+                continue;
+            }
+        };
+        let line = row.position().map_or(0, |p| p.line());
+        if row.get(0) == Some("timestamp") {
+            if i > 0 {
+                let message = if header.as_ref() == Some(&row) {
+                    "duplicate header row"
+                } else {
+                    "header row in the middle of the file"
+                };
+                problem(line, message.to_string());
+            } else if !row.iter().take(3).eq(CORE_COLUMNS) {
+                problem(
+                    line,
+                    format!("header must start with {}", CORE_COLUMNS.join(",")),
+                );
+            }
+            if i == 0 {
+                columns = Some(row.len());
+                header = Some(row);
+            }
+            continue;
+        }
+        records += 1;
+        if row.len() < CORE_COLUMNS.len() {
+            problem(
+                line,
+                format!("expected at least 3 columns, found {}", row.len()),
+            );
+            continue;
+        }
+        let expected = *columns.get_or_insert(row.len());
+        if row.len() != expected {
+            let source = if header.is_some() {
+                "the header"
+            } else {
+                "the first row"
+            };
+            problem(
+                line,
+                format!(
+                    "expected {expected} columns like {source}, found {}",
+                    row.len()
+                ),
+            );
+        }
+        if row[2].parse::<i32>().is_err() {
+            problem(line, format!("exit_code '{}' is not an integer", &row[2]));
+        }
+        check_timestamp(&row[0], line, previous, problem);
+    }
+    records
+}
+
+/// Report timestamps that do not parse or go back in time.
+fn check_timestamp(
+    timestamp: &str,
+    line: u64,
+    previous: &mut Previous,
+    problem: &mut impl FnMut(u64, String),
+) {
+    let Ok(at) = DateTime::parse_from_rfc3339(timestamp) else {
+        problem(line, format!("invalid timestamp '{timestamp}'"));
+        return;
+    };
+    if let Some(prev) = previous.0 {
+        if at < prev {
+            problem(
+                line,
+                format!(
+                    "timestamp {timestamp} is earlier than the previous record ({})",
+                    prev.to_rfc3339()
+                ),
+            );
+        }
+    }
+    previous.0 = Some(at);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(name: &str, contents: &str) -> Vec<String> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(name);
+        fs::write(&path, contents).unwrap();
+        let (_, problems) = check_file(&path, &mut Previous::default()).unwrap();
+        problems
+            .into_iter()
+            .map(|p| format!("{}: {}", p.line, p.message))
+            .collect()
+    }
+
+    #[test]
+    fn clean_files_have_no_problems() {
+        assert!(check(
+            "2025-01-01.csv",
+            "\u{feff}timestamp,value,exit_code,unit\n2025-01-01T00:00:00Z,1,0,s\n2025-01-01T00:01:00Z,\"a\nb\",0,s\n"
+        )
+        .is_empty());
+        assert!(check(
+            "2025-01-01.jsonl",
+            "{\"timestamp\":\"2025-01-01T00:00:00Z\",\"value\":\"1\",\"exit_code\":0}\n\n"
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn locates_csv_problems() {
+        let problems = check(
+            "2025-01-01.csv",
+            "timestamp,value,exit_code,unit\n\
+             timestamp,value,exit_code,unit\n\
+             2025-01-01T00:01:00Z,1,0,s\n\
+             2025-01-01T00:00:00Z,2,0\n\
+             yesterday,3,x,s\n\
+             2025-01-01T00:02:00Z,4",
+        );
+        assert_eq!(
+            problems,
+            [
+                "2: duplicate header row",
+                "4: expected 4 columns like the header, found 3",
+                "4: timestamp 2025-01-01T00:00:00Z is earlier than the previous record (2025-01-01T00:01:00+00:00)",
+                "5: exit_code 'x' is not an integer",
+                "5: invalid timestamp 'yesterday'",
+                "6: last line has no line ending (partially written?)",
+                "6: expected at least 3 columns, found 2",
+            ]
+        );
+    }
+
+    #[test]
+    fn locates_jsonl_problems() {
+        let problems = check(
+            "2025-01-01.jsonl",
+            "{\"timestamp\":\"2025-01-01T00:00:00Z\",\"value\":\"1\",\"exit_code\":0}\n\
+             {\"timestamp\":\"2025-01-01T00:01:00Z\",\"val\n",
+        );
+        assert_eq!(problems.len(), 1);
+        assert!(
+            problems[0].starts_with("2: invalid JSON record"),
+            "{problems:?}"
+        );
+    }
+}
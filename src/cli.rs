@@ -1,4 +1,7 @@
-use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+
+use chrono::NaiveDate;
+use clap::{ArgGroup, Parser, Subcommand, ValueEnum};
 
 /// A tiny reporter that periodically runs shell commands and records their output.
 #[derive(Parser)]
@@ -7,14 +10,91 @@ use clap::{Parser, Subcommand};
 #[command(version)]
 #[command(about = "Periodically run commands and record their output to CSV or JSONL", long_about = None)]
 pub struct Cli {
+    /// Where to log operational events such as warnings: "stderr" (default)
+    /// or "journald" when running as a systemd service.
+    #[arg(long, global = true, value_enum, default_value_t = LogTarget::Stderr)]
+    pub log_target: LogTarget,
+    /// Export timing spans for scheduling, command execution, processing,
+    /// storage and sinks: a file path (JSON lines) or "otlp" to send them to
+    /// an OpenTelemetry collector (OTEL_EXPORTER_OTLP_ENDPOINT, default
+    /// http://localhost:4318).
+    #[arg(long, global = true, value_name = "FILE|otlp")]
+    pub trace: Option<String>,
     #[command(subcommand)]
     pub command: Commands,
 }
 
+/// Destinations for `--log-target`.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogTarget {
+    /// Human-readable warnings on stderr.
+    Stderr,
+    /// Structured entries in the systemd journal.
+    Journald,
+}
+
 #[derive(Subcommand)]
 pub enum Commands {
     /// Run a command on a schedule and record its output
-    Run(RunOpts),
+    Run(Box<RunOpts>),
+    /// Run every job defined in ~/.tiny-reporter/jobs.toml in one process
+    Start(StartOpts),
+    /// List the running jobs
+    List(ListOpts),
+    /// Show whether a job is running, since when, and how its last run went
+    Status(StatusOpts),
+    /// Ask the process running a job to stop gracefully
+    Stop(StopOpts),
+    /// Export recorded data of one or more jobs to a file
+    Export(ExportOpts),
+    /// Print a job's records, filtered, or generate chart specs for them
+    Report(ReportOpts),
+    /// Print a job's most recent records, or one field of them
+    Last(LastOpts),
+    /// Live overview of jobs: latest value, trend, last failure and next run
+    Top(TopOpts),
+    /// Serve a web dashboard and a JSON API over the recorded data
+    Serve(ServeOpts),
+    /// Compare each day's record count with the number the interval expects
+    Coverage(CoverageOpts),
+    /// List stretches of time without records
+    Gaps(GapsOpts),
+    /// Compute availability from exit codes, with the outage windows
+    Sla(SlaOpts),
+    /// Show how much of each tick trep itself spends, from a `--trace` file
+    Overhead(OverheadOpts),
+    /// Check a job's data files for malformed or out-of-order records
+    Validate(ValidateOpts),
+    /// Fix common corruptions in a job's data files, keeping backups
+    Repair(RepairOpts),
+    /// Delete or compress a job's old data files by a retention policy
+    Prune(PruneOpts),
+    /// Schedule a job with an external scheduler instead of a long-lived trep
+    #[command(subcommand)]
+    Install(InstallTarget),
+}
+
+/// Schedulers supported by the install subcommand
+#[derive(Subcommand, Debug)]
+pub enum InstallTarget {
+    /// Generate a crontab entry that runs the job once per schedule tick
+    Cron(CronOpts),
+}
+
+/// Options for `install cron`
+#[derive(Parser, Debug)]
+pub struct CronOpts {
+    /// Cron schedule: five fields such as "*/5 * * * *", or a macro like "@hourly".
+    #[arg(long)]
+    pub schedule: String,
+    /// Add the entry to the current user's crontab, replacing an earlier
+    /// entry for the same job. Without this the entry is only printed.
+    #[arg(long)]
+    pub install: bool,
+    /// Arguments for `trep run`, given after `--`, e.g.
+    /// `-- --as cpu --format jsonl -- ./cpu.sh`. `--every` is not allowed.
+    #[arg(last = true, required = true, value_name = "RUN_ARGS")]
+    pub run_args: Vec<String>,
 }
 
 /// Options for the run subcommand
@@ -26,13 +106,650 @@ pub struct RunOpts {
     /// Interval at which to run the command (e.g. "1m", "10s"). If omitted, runs once.
     #[arg(long)]
     pub every: Option<String>,
-    /// Output format: "csv" or "jsonl". Defaults to csv.
+    /// Run exactly once and exit quietly if the previous run of this job
+    /// still holds the lock, for use from cron and other schedulers.
+    #[arg(long, conflicts_with = "every")]
+    pub single_shot: bool,
+    /// Run at the times of a cron expression in local time, e.g.
+    /// "0 9 * * 1-5" for weekdays at 09:00, instead of every interval.
+    #[arg(long, value_name = "EXPR", conflicts_with_all = ["every", "single_shot"])]
+    pub cron: Option<String>,
+    /// Run on the interval's wall clock boundaries, counted from local
+    /// midnight: with --every 1m at every whole minute.
+    #[arg(long, requires = "every")]
+    pub align: bool,
+    /// Output format: "csv" or "jsonl" day files, or "sqlite" for a single
+    /// records.db. Defaults to csv.
     #[arg(long, default_value = "csv")]
     pub format: String,
+    /// Which day's file a run spanning midnight goes to: the day it started
+    /// ("start", the default) or the day it was written ("write").
+    #[arg(long, value_enum, default_value_t = RotateBy::Start)]
+    pub rotate_by: RotateBy,
+    /// Delete day files older than this many days (e.g. "30d", today
+    /// included) whenever the day rotates; with `--format sqlite`, delete
+    /// older records from the database instead.
+    #[arg(long, value_name = "DAYS")]
+    pub retain: Option<String>,
+    /// Keep only the day files of this many most recent days, deleting
+    /// older ones whenever the day rotates.
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    pub retain_files: Option<u32>,
+    /// Gzip the day files of past days when the day rotates, so yesterday's
+    /// `<date>.csv` becomes `<date>.csv.gz`. trep reads them as before.
+    #[arg(long)]
+    pub compress: bool,
     /// Timeout for each command run (e.g. "5s"). Optional.
     #[arg(long)]
     pub timeout: Option<String>,
+    /// Expected maximum run time (e.g. "2s"). Every record gets `duration_ms`
+    /// and `slow` fields, and slower runs are logged as warnings, even when
+    /// they finish before --timeout.
+    #[arg(long, value_name = "DUR")]
+    pub max_expected: Option<String>,
+    /// Run a failed command again up to N times before its record is stored.
+    /// The record then gets `attempts` and `succeeded` fields.
+    #[arg(long, value_name = "N")]
+    pub retries: Option<u32>,
+    /// Wait before the first retry (e.g. "5s"). Defaults to 1s.
+    #[arg(long, value_name = "DUR", requires = "retries")]
+    pub retry_delay: Option<String>,
+    /// Factor the wait grows by with each further retry, e.g. "2x". Defaults
+    /// to 1x, the same wait every time.
+    #[arg(long, value_name = "FACTOR", requires = "retries")]
+    pub retry_backoff: Option<String>,
+    /// Only store a record when its value or exit code differs from the last
+    /// stored one, which is remembered across restarts.
+    #[arg(long)]
+    pub on_change: bool,
+    /// With --on-change, store an unchanged record anyway once this long
+    /// (e.g. "1h") has passed since the last stored one.
+    #[arg(long, value_name = "DUR", requires = "on_change")]
+    pub heartbeat: Option<String>,
+    /// Write CSV values that start with =, +, -, @ verbatim instead of
+    /// prefixing them with ' to stop spreadsheets evaluating them as formulas.
+    #[arg(long)]
+    pub no_formula_escape: bool,
+    /// End CSV rows with CRLF (Windows line endings).
+    #[arg(long)]
+    pub crlf: bool,
+    /// Start new CSV files with a UTF-8 byte order mark so Excel reads
+    /// non-ASCII values correctly.
+    #[arg(long)]
+    pub bom: bool,
+    /// Select the stored value from JSON output with a jq-style path
+    /// (e.g. ".data.temperature"). Only applied to successful runs.
+    #[arg(long, value_name = "PATH")]
+    pub jsonpath: Option<String>,
+    /// Split the output of successful runs into fields stored as their own
+    /// columns: "json" selects --fields from JSON output, "regex" takes the
+    /// named groups of --pattern. The first field also becomes the value.
+    #[arg(long, value_enum, value_name = "PARSER", conflicts_with_all = ["jsonpath", "collector", "check"])]
+    pub parse: Option<ParseMode>,
+    /// Fields for --parse json: top-level keys, or name=path for values
+    /// further in (e.g. "cpu,temp=.sensors[0].value").
+    #[arg(
+        long,
+        value_name = "FIELDS",
+        value_delimiter = ',',
+        requires = "parse",
+        required_if_eq("parse", "json")
+    )]
+    pub fields: Vec<String>,
+    /// Regex for --parse regex; its named groups such as (?<cpu>\d+) are
+    /// the fields.
+    #[arg(
+        long,
+        value_name = "REGEX",
+        requires = "parse",
+        required_if_eq("parse", "regex")
+    )]
+    pub pattern: Option<String>,
+    /// Parse unit suffixes such as "1.2G", "350ms" or "85%" into a plain
+    /// number (bytes, seconds or percent) and store the unit in a `unit` field.
+    #[arg(long)]
+    pub normalize_units: bool,
+    /// Validation rule for successful values: "regex:<pattern>" or
+    /// "range:<min>..<max>" (inclusive). May be repeated; adds a `valid` field.
+    #[arg(long, value_name = "RULE")]
+    pub validate: Vec<String>,
+    /// Write records that fail validation to "<date>.rejects.<ext>" instead
+    /// of the data file and sinks.
+    #[arg(long, requires = "validate")]
+    pub rejects: bool,
+    /// Store derived columns computed against the previous numeric value:
+    /// "delta" (difference) and/or "rate" (difference per second).
+    #[arg(long, value_enum, value_delimiter = ',', value_name = "COLUMNS")]
+    pub derive: Vec<Derive>,
+    /// How numeric values behave: "gauge" (default) or "counter", where a
+    /// decrease is treated as a counter reset and flagged with `reset`.
+    #[arg(long, value_enum, default_value_t = Kind::Gauge)]
+    pub kind: Kind,
+    /// Store a smoothed `avg` field over the last N numeric samples.
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    pub rolling_avg: Option<u32>,
+    /// Moving average used by --rolling-avg: "sma" (simple, default) or "ema" (exponential).
+    #[arg(long, value_enum, default_value_t = Smoothing::Sma, requires = "rolling_avg")]
+    pub rolling_mode: Smoothing,
+    /// Round numeric values to this many decimal places before storing.
+    #[arg(long, value_name = "DIGITS")]
+    pub precision: Option<usize>,
+    /// Rewrite the stored value with a template, e.g. "{value} °C".
+    /// Placeholders: {value}, {exit_code}, {timestamp} or an extra field name.
+    #[arg(long, value_name = "TEMPLATE")]
+    pub value_template: Option<String>,
+    /// Redis server to append each record to (e.g. "redis://127.0.0.1:6379").
+    #[arg(long)]
+    pub redis_url: Option<String>,
+    /// Redis stream key used with --redis-url. Defaults to "trep:<name>".
+    #[arg(long, requires = "redis_url")]
+    pub redis_stream: Option<String>,
+    /// NATS server to publish each record to (e.g. "nats://127.0.0.1:4222").
+    #[arg(long)]
+    pub nats_url: Option<String>,
+    /// NATS subject used with --nats-url. Defaults to "trep.<name>".
+    #[arg(long, requires = "nats_url")]
+    pub nats_subject: Option<String>,
+    /// Publish through JetStream and wait for the stream to acknowledge each record.
+    #[arg(long, requires = "nats_url")]
+    pub nats_jetstream: bool,
+    /// Send each record as an RFC 5424 syslog message. TARGET is "local"
+    /// (the default, /dev/log), "unix:<path>", "udp://host[:port]" or
+    /// "tcp://host[:port]" (port 514 by default).
+    #[arg(long, value_name = "TARGET", num_args = 0..=1, default_missing_value = "local")]
+    pub syslog: Option<String>,
+    /// Syslog facility used with --syslog.
+    #[arg(long, value_enum, default_value_t = SyslogFacility::User, requires = "syslog")]
+    pub syslog_facility: SyslogFacility,
+    /// Windows only: write failed runs ("failures", the default) or every
+    /// record ("all") to the Application event log under the
+    /// "tiny-reporter" source.
+    #[arg(long, value_enum, value_name = "WHAT", num_args = 0..=1, default_missing_value = "failures")]
+    pub eventlog: Option<EventLogMode>,
+    /// Shell command that receives every record as a JSON line on its stdin.
+    /// The process is kept running and restarted if it exits.
+    #[arg(long, value_name = "COMMAND")]
+    pub pipe_to: Option<String>,
+    /// Alert when the value of a successful run is above this number. Runs
+    /// that fail or time out always alert once an alert target is set.
+    #[arg(long, value_name = "N", allow_negative_numbers = true)]
+    pub alert_above: Option<f64>,
+    /// Alert when the value of a successful run is below this number.
+    #[arg(long, value_name = "N", allow_negative_numbers = true)]
+    pub alert_below: Option<f64>,
+    /// POST each alert as JSON (job, reason, threshold, suppressed, record)
+    /// to this URL.
+    #[arg(long, value_name = "URL")]
+    pub alert_webhook: Option<String>,
+    /// Shell command run for each alert, with the record in TREP_JOB,
+    /// TREP_REASON, TREP_VALUE, TREP_EXIT_CODE, TREP_TIMESTAMP, TREP_FIELDS,
+    /// TREP_THRESHOLD and TREP_SUPPRESSED.
+    #[arg(long, value_name = "COMMAND")]
+    pub alert_command: Option<String>,
+    /// Minimum time between two alerts (e.g. "30m"). Alerts in between are
+    /// counted and reported with the next one. Defaults to 10m.
+    #[arg(long, value_name = "DUR")]
+    pub alert_cooldown: Option<String>,
+    /// Keep Prometheus gauges of the job (latest numeric value, exit code,
+    /// run duration, consecutive failures) in a node_exporter textfile such
+    /// as /var/lib/node_exporter/textfile/cpu.prom, or serve them on
+    /// /metrics at an address such as 127.0.0.1:9464 (or :9464).
+    #[arg(long, value_name = "PATH|ADDR")]
+    pub export_prometheus: Option<String>,
+    /// Rhai script defining `fn transform(record)` that can rewrite, enrich
+    /// or drop each record before it is stored.
+    #[arg(long, value_name = "SCRIPT")]
+    pub transform: Option<PathBuf>,
+    /// WASM component implementing the `trep:plugin` transform and/or sink
+    /// interface (see wit/plugin.wit). May be repeated.
+    #[arg(long = "plugin", value_name = "WASM")]
+    pub plugins: Vec<PathBuf>,
+    /// Gather a host metric natively instead of running a command: "cpu"
+    /// (usage %), "mem" (used %), "disk:<path>" (used % of the file system
+    /// holding the path) or "loadavg" (one-minute load, not on Windows).
+    #[arg(
+        long,
+        value_name = "cpu|mem|disk:<path>|loadavg",
+        conflicts_with = "cmd"
+    )]
+    pub collector: Option<String>,
+    /// Check a target directly instead of running a command: an http:// or
+    /// https:// URL is requested with GET, recording the status code as
+    /// value and `latency_ms` and `body_bytes` fields; "tcp:<host>:<port>"
+    /// records 1 or 0 for whether a connection opened, with `latency_ms` and
+    /// `error` fields; "tls:<host>:<port>" records the days until the
+    /// server's certificate expires, with `expires`, `issuer`, `trusted` and
+    /// `error` fields. --timeout applies (default 10s).
+    #[arg(
+        long,
+        value_name = "URL|tcp:HOST:PORT|tls:HOST:PORT",
+        conflicts_with_all = ["cmd", "collector"]
+    )]
+    pub check: Option<String>,
+    /// With a tls: check, exit code 1 when the certificate expires in fewer
+    /// than this many days. Defaults to 30.
+    #[arg(long, value_name = "DAYS", requires = "check")]
+    pub warn_days: Option<i64>,
+    /// With a tls: check, exit code 2 when the certificate expires in fewer
+    /// than this many days or is not trusted. Defaults to 7.
+    #[arg(long, value_name = "DAYS", requires = "check")]
+    pub critical_days: Option<i64>,
+    /// Shell that runs the command. Defaults to bash on Unix and cmd on
+    /// Windows.
+    #[arg(long, value_enum, conflicts_with_all = ["no_shell", "collector", "check"])]
+    pub shell: Option<Shell>,
+    /// Start the command directly, each argument after `--` passed as is,
+    /// with no shell to expand variables, globs or pipes.
+    #[arg(long, conflicts_with_all = ["collector", "check"])]
+    pub no_shell: bool,
+    /// Directory to run the command in.
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["collector", "check"])]
+    pub cwd: Option<PathBuf>,
+    /// Set an environment variable for the command; repeat for more.
+    #[arg(long, value_name = "KEY=VALUE", conflicts_with_all = ["collector", "check"])]
+    pub env: Vec<String>,
     /// Command to execute, use after `--` to separate from options
-    #[arg(last = true, required = true)]
+    #[arg(last = true, required_unless_present_any = ["collector", "check"])]
     pub cmd: Vec<String>,
 }
+
+/// Options for the export subcommand
+#[derive(Parser, Debug)]
+pub struct ExportOpts {
+    /// Job to export. May be repeated to combine several jobs in one file.
+    #[arg(long = "as", short = 'n', value_name = "NAME", required = true)]
+    pub names: Vec<String>,
+    /// Output format.
+    #[arg(long, value_enum)]
+    pub to: ExportFormat,
+    /// Output file. Defaults to "<name>.<ext>" in the current directory.
+    #[arg(long, short = 'o')]
+    pub out: Option<PathBuf>,
+    /// First day to include (YYYY-MM-DD).
+    #[arg(long)]
+    pub since: Option<NaiveDate>,
+    /// Last day to include (YYYY-MM-DD).
+    #[arg(long)]
+    pub until: Option<NaiveDate>,
+    /// Put each day or each job on its own sheet (xlsx only).
+    #[arg(long, value_enum, default_value_t = SheetsBy::Day)]
+    pub sheets: SheetsBy,
+    /// Add a chart sheet plotting the value of every sheet over time (xlsx
+    /// only).
+    #[arg(long)]
+    pub chart: bool,
+}
+
+/// Options for the report subcommand
+#[derive(Parser, Debug)]
+pub struct ReportOpts {
+    /// Job to report on
+    #[arg(long = "as", short = 'n')]
+    pub name: String,
+    /// Write a Vega-Lite chart spec to this file.
+    #[arg(long, value_name = "FILE")]
+    pub vega: Option<PathBuf>,
+    /// Write a gnuplot script to this file.
+    #[arg(long, value_name = "FILE")]
+    pub gnuplot: Option<PathBuf>,
+    /// Data file the specs read, written as CSV. Defaults to the first spec's
+    /// path with a ".csv" extension.
+    #[arg(long, value_name = "FILE")]
+    pub data: Option<PathBuf>,
+    /// First day to include (YYYY-MM-DD).
+    #[arg(long)]
+    pub since: Option<NaiveDate>,
+    /// Last day to include (YYYY-MM-DD).
+    #[arg(long)]
+    pub until: Option<NaiveDate>,
+    /// Only include failed runs (non-zero exit code).
+    #[arg(long)]
+    pub failed_only: bool,
+    /// How to print the records when no chart is requested.
+    #[arg(long, value_enum, default_value_t = ReportFormat::Table)]
+    pub format: ReportFormat,
+}
+
+/// Output formats for `report --format`.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ReportFormat {
+    /// Aligned columns for reading in a terminal.
+    #[default]
+    Table,
+    /// CSV with a header row.
+    Csv,
+    /// A JSON array of records.
+    Json,
+}
+
+/// Options for the last subcommand
+#[derive(Parser, Debug)]
+pub struct LastOpts {
+    /// Job to read
+    pub name: String,
+    /// Number of records to print, oldest first.
+    #[arg(short = 'n', long = "count", default_value_t = 1, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    pub count: usize,
+    /// Print only this field (timestamp, value, exit_code or an extra field)
+    /// as plain text instead of whole records as JSON lines.
+    #[arg(long)]
+    pub field: Option<String>,
+}
+
+/// Options for the top subcommand
+#[derive(Parser, Debug)]
+pub struct TopOpts {
+    /// Jobs to show
+    #[arg(required_unless_present = "all", conflicts_with = "all")]
+    pub names: Vec<String>,
+    /// Show every job under ~/.tiny-reporter.
+    #[arg(long)]
+    pub all: bool,
+    /// How often to redraw (e.g. "5s").
+    #[arg(long, default_value = "2s", value_name = "DUR")]
+    pub refresh: String,
+    /// Print the table once and exit instead of redrawing it.
+    #[arg(long)]
+    pub once: bool,
+}
+
+/// Options for the list subcommand
+#[derive(Parser, Debug)]
+pub struct ListOpts {
+    /// Also list jobs with data under ~/.tiny-reporter that are not running.
+    #[arg(long)]
+    pub all: bool,
+}
+
+/// Options for the status subcommand
+#[derive(Parser, Debug)]
+pub struct StatusOpts {
+    /// Job to show
+    #[arg(long = "as", short = 'n')]
+    pub name: String,
+}
+
+/// Options for the stop subcommand
+#[derive(Parser, Debug)]
+pub struct StopOpts {
+    /// Job to stop
+    #[arg(long = "as", short = 'n')]
+    pub name: String,
+    /// How long to wait for the job to store its last record and exit
+    /// (e.g. "1m").
+    #[arg(long, default_value = "30s", value_name = "DUR")]
+    pub timeout: String,
+}
+
+/// Options for the start subcommand
+#[derive(Parser, Debug)]
+pub struct StartOpts {
+    /// Jobs file. Defaults to ~/.tiny-reporter/jobs.toml.
+    #[arg(long, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+}
+
+/// Options for the serve subcommand
+#[derive(Parser, Debug)]
+pub struct ServeOpts {
+    /// Address to listen on. Use 0.0.0.0:<port> to accept other hosts.
+    #[arg(long, default_value = "127.0.0.1:8080", value_name = "ADDR")]
+    pub listen: String,
+    /// Config file with the API tokens. Defaults to
+    /// ~/.tiny-reporter/config.toml.
+    #[arg(long, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+    /// Serve HTTPS with this PEM certificate chain (needs --tls-key).
+    #[arg(long, value_name = "PEM", requires = "tls_key")]
+    pub tls_cert: Option<PathBuf>,
+    /// PEM private key (PKCS#8 or RSA) for --tls-cert.
+    #[arg(long, value_name = "PEM", requires = "tls_cert")]
+    pub tls_key: Option<PathBuf>,
+}
+
+/// Options for the coverage subcommand
+#[derive(Parser, Debug)]
+pub struct CoverageOpts {
+    /// Job to audit
+    pub name: String,
+    /// Interval the job runs at (e.g. "1m"). Inferred from the records if omitted.
+    #[arg(long)]
+    pub every: Option<String>,
+    /// First day to show (YYYY-MM-DD). Defaults to the day of the first record.
+    #[arg(long)]
+    pub since: Option<NaiveDate>,
+    /// Last day to show (YYYY-MM-DD). Defaults to today.
+    #[arg(long)]
+    pub until: Option<NaiveDate>,
+}
+
+/// Options for the gaps subcommand
+#[derive(Parser, Debug)]
+pub struct GapsOpts {
+    /// Job to scan
+    pub name: String,
+    /// Interval the job runs at (e.g. "1m"). Inferred from the records if omitted.
+    #[arg(long)]
+    pub expect: Option<String>,
+    /// Shortest gap to report (e.g. "5m"). Defaults to 1.5 times the interval,
+    /// so every missed run shows up.
+    #[arg(long)]
+    pub min_gap: Option<String>,
+    /// Start of the period to check (YYYY-MM-DD). Time before the first
+    /// record counts as a gap.
+    #[arg(long)]
+    pub since: Option<NaiveDate>,
+    /// Last day of the period to check (YYYY-MM-DD). Time after the last
+    /// record, up to now, counts as a gap.
+    #[arg(long)]
+    pub until: Option<NaiveDate>,
+}
+
+/// Options for the sla subcommand
+#[derive(Parser, Debug)]
+pub struct SlaOpts {
+    /// Job to measure
+    pub name: String,
+    /// Report availability per day, week or month.
+    #[arg(long, value_enum, default_value_t = SlaPeriod::Month)]
+    pub period: SlaPeriod,
+    /// First day to include (YYYY-MM-DD).
+    #[arg(long)]
+    pub since: Option<NaiveDate>,
+    /// Last day to include (YYYY-MM-DD).
+    #[arg(long)]
+    pub until: Option<NaiveDate>,
+}
+
+/// Reporting periods for `sla --period`.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SlaPeriod {
+    Day,
+    /// ISO weeks, starting on Monday.
+    Week,
+    Month,
+}
+
+/// Options for the overhead subcommand
+#[derive(Parser, Debug)]
+pub struct OverheadOpts {
+    /// Job to analyze
+    pub name: String,
+    /// Span file written by `trep --trace <FILE> run ...`.
+    #[arg(long, value_name = "FILE")]
+    pub from: PathBuf,
+}
+
+/// Options for the validate subcommand
+#[derive(Parser, Debug)]
+pub struct ValidateOpts {
+    /// Job whose data files to check
+    pub name: String,
+}
+
+/// Options for the repair subcommand
+#[derive(Parser, Debug)]
+pub struct RepairOpts {
+    /// Job whose data files to repair
+    pub name: String,
+    /// Only show what would be changed.
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+/// Options for the prune subcommand
+#[derive(Parser, Debug)]
+#[command(group(ArgGroup::new("policy").required(true).multiple(true).args(["retain", "retain_files", "compress"])))]
+pub struct PruneOpts {
+    /// Job whose data files to prune
+    #[arg(long = "as", short = 'n')]
+    pub name: String,
+    /// Keep this many days of data (e.g. "30d"), today included.
+    #[arg(long, value_name = "DAYS")]
+    pub retain: Option<String>,
+    /// Keep only the day files of this many most recent days.
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    pub retain_files: Option<u32>,
+    /// Gzip the day files of past days.
+    #[arg(long)]
+    pub compress: bool,
+    /// Only show what would be deleted or compressed.
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+/// File formats written by `export --to`.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// One CSV file with a header row, as `trep run` writes.
+    Csv,
+    /// One JSON Lines file, as `trep run` writes.
+    Jsonl,
+    /// One Apache Parquet file with typed columns.
+    Parquet,
+    /// Excel workbook.
+    Xlsx,
+}
+
+impl ExportFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Jsonl => "jsonl",
+            ExportFormat::Parquet => "parquet",
+            ExportFormat::Xlsx => "xlsx",
+        }
+    }
+}
+
+/// How `export --sheets` splits records across worksheets.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SheetsBy {
+    /// One sheet per job and day.
+    Day,
+    /// One sheet per job.
+    Job,
+}
+
+/// Records written by `run --eventlog`.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EventLogMode {
+    /// Only runs with a non-zero exit code.
+    Failures,
+    /// Every record; failures as errors, the rest as information events.
+    All,
+}
+
+/// Facilities accepted by `run --syslog-facility`.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SyslogFacility {
+    User,
+    Daemon,
+    Local0,
+    Local1,
+    Local2,
+    Local3,
+    Local4,
+    Local5,
+    Local6,
+    Local7,
+}
+
+impl SyslogFacility {
+    /// Numeric facility code from RFC 5424.
+    pub fn code(self) -> u8 {
+        match self {
+            SyslogFacility::User => 1,
+            SyslogFacility::Daemon => 3,
+            SyslogFacility::Local0 => 16,
+            SyslogFacility::Local1 => 17,
+            SyslogFacility::Local2 => 18,
+            SyslogFacility::Local3 => 19,
+            SyslogFacility::Local4 => 20,
+            SyslogFacility::Local5 => 21,
+            SyslogFacility::Local6 => 22,
+            SyslogFacility::Local7 => 23,
+        }
+    }
+}
+
+/// Derived columns available to `run --derive`.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Derive {
+    /// Difference from the previous value.
+    Delta,
+    /// Difference from the previous value per second.
+    Rate,
+}
+
+/// Metric semantics for `run --kind`.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Kind {
+    /// Values may go up and down freely.
+    #[default]
+    Gauge,
+    /// Values only increase; a decrease means the counter was reset.
+    Counter,
+}
+
+/// Parsers for `run --parse`.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParseMode {
+    /// Fields selected from JSON output.
+    Json,
+    /// Named groups of a regular expression.
+    Regex,
+}
+
+/// Shells for `run --shell`.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shell {
+    /// POSIX `sh -c`.
+    Sh,
+    /// `bash -lc`, a login shell; the default on Unix.
+    Bash,
+    /// PowerShell 7, `pwsh -NoProfile -NonInteractive -Command`.
+    Pwsh,
+    /// `cmd /C`; the default on Windows.
+    Cmd,
+}
+
+/// Policies for `run --rotate-by`.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RotateBy {
+    /// The file of the day the run started, matching the record's timestamp.
+    #[default]
+    Start,
+    /// The file of the day the record is written.
+    Write,
+}
+
+/// Moving-average flavours for `run --rolling-avg`.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Smoothing {
+    /// Mean of the last N samples.
+    #[default]
+    Sma,
+    /// Exponential moving average with alpha = 2 / (N + 1).
+    Ema,
+}
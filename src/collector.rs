@@ -0,0 +1,188 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::thread;
+use std::time::Instant;
+
+use serde_json::{Map, Value};
+use sysinfo::{Disks, System, MINIMUM_CPU_UPDATE_INTERVAL};
+
+use crate::units;
+
+/// A host metric gathered natively instead of by a shell command.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Collector {
+    /// `cpu`: CPU usage across all cores, in percent.
+    Cpu,
+    /// `mem`: used memory, in percent.
+    Mem,
+    /// `disk:<path>`: used space of the file system holding the path, in percent.
+    Disk(PathBuf),
+    /// `loadavg`: the one-minute load average.
+    LoadAvg,
+}
+
+impl FromStr for Collector {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> io::Result<Self> {
+        match s {
+            "cpu" => Ok(Collector::Cpu),
+            "mem" => Ok(Collector::Mem),
+            "loadavg" => Ok(Collector::LoadAvg),
+            _ => match s.strip_prefix("disk:") {
+                Some(path) if !path.is_empty() => Ok(Collector::Disk(PathBuf::from(path))),
+                _ => Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("invalid collector '{s}': expected cpu, mem, disk:<path> or loadavg"),
+                )),
+            },
+        }
+    }
+}
+
+/// Gathers one collector's metric each tick, keeping the state needed
+/// between samples (CPU usage is measured between two refreshes).
+pub struct CollectorState {
+    collector: Collector,
+    system: System,
+    disks: Disks,
+    cpu_refreshed: Instant,
+}
+
+impl CollectorState {
+    pub fn new(collector: Collector) -> io::Result<Self> {
+        if cfg!(windows) && collector == Collector::LoadAvg {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "the loadavg collector is not available on Windows",
+            ));
+        }
+        let mut system = System::new();
+        if collector == Collector::Cpu {
+            system.refresh_cpu_usage();
+        }
+        let disks = match collector {
+            Collector::Disk(_) => Disks::new_with_refreshed_list(),
+            _ => Disks::new(),
+        };
+        Ok(Self {
+            collector,
+            system,
+            disks,
+            cpu_refreshed: Instant::now(),
+        })
+    }
+
+    /// Take a sample: the record value and its numeric fields.
+    pub fn collect(&mut self) -> io::Result<(String, Map<String, Value>)> {
+        let mut fields = Map::new();
+        let mut field = |name: &str, n: f64| {
+            fields.insert(name.to_string(), units::number_value(n));
+        };
+        let value = match &self.collector {
+            Collector::Cpu => {
+                // Usage is only meaningful over a minimum span since the last refresh.
+                let since = self.cpu_refreshed.elapsed();
+                if since < MINIMUM_CPU_UPDATE_INTERVAL {
+                    thread::sleep(MINIMUM_CPU_UPDATE_INTERVAL - since);
+                }
+                self.system.refresh_cpu_usage();
+                self.cpu_refreshed = Instant::now();
+                field("cores", self.system.cpus().len() as f64);
+                f64::from(self.system.global_cpu_usage())
+            }
+            Collector::Mem => {
+                self.system.refresh_memory();
+                let total = self.system.total_memory();
+                let available = self.system.available_memory();
+                let used = total.saturating_sub(available);
+                field("total_bytes", total as f64);
+                field("used_bytes", used as f64);
+                field("available_bytes", available as f64);
+                field("swap_used_bytes", self.system.used_swap() as f64);
+                percent(used, total)
+            }
+            Collector::Disk(path) => {
+                self.disks.refresh(true);
+                let disk = mount_for(self.disks.list().iter().map(|d| d.mount_point()), path)
+                    .and_then(|mount| self.disks.list().iter().find(|d| d.mount_point() == mount))
+                    .ok_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::NotFound,
+                            format!("no mounted file system holds {}", path.display()),
+                        )
+                    })?;
+                let total = disk.total_space();
+                let available = disk.available_space();
+                let used = total.saturating_sub(available);
+                field("total_bytes", total as f64);
+                field("used_bytes", used as f64);
+                field("available_bytes", available as f64);
+                percent(used, total)
+            }
+            Collector::LoadAvg => {
+                let load = System::load_average();
+                field("load1", load.one);
+                field("load5", load.five);
+                field("load15", load.fifteen);
+                load.one
+            }
+        };
+        Ok((units::format_number(round2(value)), fields))
+    }
+}
+
+fn percent(part: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        part as f64 / total as f64 * 100.0
+    }
+}
+
+fn round2(n: f64) -> f64 {
+    (n * 100.0).round() / 100.0
+}
+
+/// The mount point holding `path`: the longest one that is a prefix of it.
+fn mount_for<'a>(mounts: impl Iterator<Item = &'a Path>, path: &Path) -> Option<&'a Path> {
+    let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    mounts
+        .filter(|mount| path.starts_with(mount))
+        .max_by_key(|mount| mount.components().count())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_collectors() {
+        assert_eq!("cpu".parse::<Collector>().unwrap(), Collector::Cpu);
+        assert_eq!(
+            "disk:/".parse::<Collector>().unwrap(),
+            Collector::Disk(PathBuf::from("/"))
+        );
+        assert!("disk:".parse::<Collector>().is_err());
+        assert!("gpu".parse::<Collector>().is_err());
+    }
+
+    #[test]
+    fn picks_the_innermost_mount() {
+        let mounts = [Path::new("/"), Path::new("/home"), Path::new("/home2")];
+        let mount = |p: &str| mount_for(mounts.iter().copied(), Path::new(p));
+        assert_eq!(mount("/home/me"), Some(Path::new("/home")));
+        assert_eq!(mount("/home2"), Some(Path::new("/home2")));
+        assert_eq!(mount("/var/log"), Some(Path::new("/")));
+    }
+
+    #[test]
+    fn collects_memory_fields() {
+        let mut state = CollectorState::new(Collector::Mem).unwrap();
+        let (value, fields) = state.collect().unwrap();
+        let used: f64 = value.parse().unwrap();
+        assert!((0.0..=100.0).contains(&used));
+        assert!(fields["total_bytes"].as_u64().unwrap() > 0);
+    }
+}
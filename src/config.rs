@@ -0,0 +1,204 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use crate::storage;
+
+/// Settings read from `~/.tiny-reporter/config.toml`. Every section is
+/// optional, and a missing file means defaults.
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub serve: ServeConfig,
+}
+
+/// The `[serve]` section.
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServeConfig {
+    /// Bearer tokens accepted by the API. Without any, the API is open.
+    pub tokens: Vec<String>,
+}
+
+/// One `[[job]]` table of the jobs file.
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct JobConfig {
+    pub name: String,
+    /// Shell command, as given to `trep run`.
+    pub command: String,
+    /// Interval such as "1m"; without it the job runs once.
+    pub every: Option<String>,
+    /// "csv" (default), "jsonl" or "sqlite".
+    pub format: Option<String>,
+    pub timeout: Option<String>,
+    /// "sh", "bash", "pwsh" or "cmd", as `run --shell`.
+    pub shell: Option<String>,
+    /// Directory to run the command in.
+    pub cwd: Option<String>,
+    /// The `[job.env]` table: variables set for the command.
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// The `[job.alert]` table, as the `run --alert-*` options.
+    #[serde(default)]
+    pub alert: Option<AlertConfig>,
+}
+
+/// Alerting of a job in the jobs file.
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct AlertConfig {
+    pub above: Option<f64>,
+    pub below: Option<f64>,
+    pub webhook: Option<String>,
+    pub command: Option<String>,
+    /// Duration such as "30m".
+    pub cooldown: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JobsFile {
+    #[serde(default)]
+    job: Vec<JobConfig>,
+}
+
+/// The default config file: ~/.tiny-reporter/config.toml.
+pub fn default_path() -> PathBuf {
+    storage::base_dir().join("config.toml")
+}
+
+/// The default jobs file: ~/.tiny-reporter/jobs.toml.
+pub fn default_jobs_path() -> PathBuf {
+    storage::base_dir().join("jobs.toml")
+}
+
+/// Read the config file at `path`, or defaults if it does not exist.
+pub fn load(path: &Path) -> io::Result<Config> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Config::default()),
+        Err(e) => return Err(e),
+    };
+    warn_if_readable_by_others(path);
+    parse(path, &text)
+}
+
+/// Read the jobs defined in the jobs file at `path`. Unlike the config
+/// file it must exist, define at least one job and use each name once.
+pub fn load_jobs(path: &Path) -> io::Result<Vec<JobConfig>> {
+    let shown = dunce::simplified(path).display();
+    let text = fs::read_to_string(path)
+        .map_err(|e| io::Error::new(e.kind(), format!("cannot read jobs file {shown}: {e}")))?;
+    let jobs = parse::<JobsFile>(path, &text)?.job;
+    if jobs.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{shown} defines no [[job]]"),
+        ));
+    }
+    for (i, job) in jobs.iter().enumerate() {
+        if jobs[..i].iter().any(|other| other.name == job.name) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("job '{}' is defined more than once in {shown}", job.name),
+            ));
+        }
+    }
+    Ok(jobs)
+}
+
+fn parse<T: DeserializeOwned>(path: &Path, text: &str) -> io::Result<T> {
+    toml::from_str(text).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "invalid config file {}: {e}",
+                dunce::simplified(path).display()
+            ),
+        )
+    })
+}
+
+/// The config file holds secrets, so other users should not be able to read it.
+fn warn_if_readable_by_others(path: &Path) {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if let Ok(meta) = fs::metadata(path) {
+            if meta.permissions().mode() & 0o077 != 0 {
+                tracing::warn!(
+                    path = %dunce::simplified(path).display(),
+                    "config file is accessible by other users; consider chmod 600"
+                );
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_tokens_and_defaults() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        assert_eq!(load(&path).unwrap(), Config::default());
+
+        fs::write(&path, "[serve]\ntokens = [\"abc\", \"def\"]\n").unwrap();
+        assert_eq!(load(&path).unwrap().serve.tokens, ["abc", "def"]);
+
+        fs::write(&path, "[serve]\ntoken = \"abc\"\n").unwrap();
+        let err = load(&path).unwrap_err();
+        assert!(err.to_string().contains("unknown field"), "{err}");
+    }
+
+    #[test]
+    fn reads_jobs() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("jobs.toml");
+        assert_eq!(
+            load_jobs(&path).unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+
+        fs::write(
+            &path,
+            "[[job]]\nname = \"load\"\ncommand = \"cat /proc/loadavg\"\nevery = \"1m\"\n\n\
+             [[job]]\nname = \"disk\"\ncommand = \"df -h /\"\nformat = \"jsonl\"\ntimeout = \"5s\"\n\
+             [job.env]\nLANG = \"C\"\n\n[job.alert]\nabove = 90\ncommand = \"notify-send disk\"\n",
+        )
+        .unwrap();
+        let jobs = load_jobs(&path).unwrap();
+        assert_eq!(jobs.len(), 2);
+        assert_eq!(jobs[0].every.as_deref(), Some("1m"));
+        assert_eq!(jobs[1].format.as_deref(), Some("jsonl"));
+        assert_eq!(jobs[0].alert, None);
+        assert_eq!(jobs[1].env["LANG"], "C");
+        let alert = jobs[1].alert.as_ref().unwrap();
+        assert_eq!(alert.above, Some(90.0));
+        assert_eq!(alert.command.as_deref(), Some("notify-send disk"));
+
+        fs::write(
+            &path,
+            "[[job]]\nname = \"a\"\ncommand = \"x\"\n\n[[job]]\nname = \"a\"\ncommand = \"y\"\n",
+        )
+        .unwrap();
+        assert!(load_jobs(&path)
+            .unwrap_err()
+            .to_string()
+            .contains("more than once"));
+        fs::write(&path, "").unwrap();
+        assert!(load_jobs(&path)
+            .unwrap_err()
+            .to_string()
+            .contains("no [[job]]"));
+    }
+}
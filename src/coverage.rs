@@ -0,0 +1,223 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::time::Duration;
+
+use chrono::{DateTime, Local, NaiveDate, TimeZone};
+
+use crate::cli::CoverageOpts;
+use crate::storage::{self, Record};
+use crate::util;
+
+/// Records of one day compared with what the interval should have produced.
+#[derive(Debug, PartialEq)]
+struct DayCoverage {
+    date: NaiveDate,
+    records: usize,
+    failed: usize,
+    expected: usize,
+}
+
+pub fn coverage(opts: CoverageOpts) -> io::Result<()> {
+    let records = storage::read_job_records(&opts.name)?;
+    let times = record_times(&records);
+    let (Some(first), Some(last)) = (times.iter().min(), times.iter().max()) else {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("job '{}' has no records yet", opts.name),
+        ));
+    };
+    let interval = match &opts.every {
+        Some(every) => util::duration_arg(every, "interval")?,
+        None => {
+            let interval = infer_interval(&times).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "too few records to infer the interval; pass --every",
+                )
+            })?;
+            println!(
+                "Interval {} inferred from the records; pass --every to override",
+                humantime::format_duration(interval)
+            );
+            interval
+        }
+    };
+    let now = Local::now();
+    let since = opts.since.unwrap_or(first.date_naive());
+    let until = opts
+        .until
+        .unwrap_or(now.date_naive().max(last.date_naive()));
+    let days = daily_coverage(&records, *first, now, interval, since, until);
+
+    println!(
+        "{:<10} {:>8} {:>8} {:>8} {:>9}",
+        "date", "records", "failed", "expected", "coverage"
+    );
+    let (mut records, mut expected) = (0, 0);
+    for day in &days {
+        println!(
+            "{:<10} {:>8} {:>8} {:>8} {:>9}",
+            day.date,
+            day.records,
+            day.failed,
+            day.expected,
+            percent(day.records, day.expected)
+        );
+        records += day.records;
+        expected += day.expected;
+    }
+    println!(
+        "{:<10} {:>8} {:>8} {:>8} {:>9}",
+        "total",
+        records,
+        days.iter().map(|d| d.failed).sum::<usize>(),
+        expected,
+        percent(records, expected)
+    );
+    Ok(())
+}
+
+/// Record timestamps in local time; unparseable ones are skipped.
+pub fn record_times(records: &[Record]) -> Vec<DateTime<Local>> {
+    records
+        .iter()
+        .filter_map(|r| DateTime::parse_from_rfc3339(&r.timestamp).ok())
+        .map(|t| t.with_timezone(&Local))
+        .collect()
+}
+
+/// The median spacing of consecutive records.
+pub fn infer_interval(times: &[DateTime<Local>]) -> Option<Duration> {
+    let mut sorted = times.to_vec();
+    sorted.sort();
+    let mut gaps: Vec<Duration> = sorted
+        .windows(2)
+        .filter_map(|w| (w[1] - w[0]).to_std().ok())
+        .filter(|gap| !gap.is_zero())
+        .collect();
+    gaps.sort();
+    gaps.get(gaps.len() / 2).copied()
+}
+
+fn percent(records: usize, expected: usize) -> String {
+    if expected == 0 {
+        "-".to_string()
+    } else {
+        format!("{:.1}%", records as f64 / expected as f64 * 100.0)
+    }
+}
+
+/// Count records per local day from `since` to `until`. A day expects one
+/// record per `interval` of the part of it between the first record and
+/// `now`.
+fn daily_coverage(
+    records: &[Record],
+    first: DateTime<Local>,
+    now: DateTime<Local>,
+    interval: Duration,
+    since: NaiveDate,
+    until: NaiveDate,
+) -> Vec<DayCoverage> {
+    let mut counts: BTreeMap<NaiveDate, (usize, usize)> = BTreeMap::new();
+    for record in records {
+        let Ok(at) = DateTime::parse_from_rfc3339(&record.timestamp) else {
+            continue;
+        };
+        let entry = counts
+            .entry(at.with_timezone(&Local).date_naive())
+            .or_default();
+        entry.0 += 1;
+        if record.exit_code != 0 {
+            entry.1 += 1;
+        }
+    }
+    let interval = interval.as_secs_f64().max(f64::EPSILON);
+    since
+        .iter_days()
+        .take_while(|d| *d <= until)
+        .map(|date| {
+            let (records, failed) = counts.get(&date).copied().unwrap_or_default();
+            let start = start_of_day(date).max(first);
+            let end = date.succ_opt().map_or(now, start_of_day).min(now);
+            let window = (end - start).as_seconds_f64().max(0.0);
+            DayCoverage {
+                date,
+                records,
+                failed,
+                expected: (window / interval).ceil() as usize,
+            }
+        })
+        .collect()
+}
+
+/// The first moment of `date` in local time.
+pub fn start_of_day(date: NaiveDate) -> DateTime<Local> {
+    let midnight = date.and_hms_opt(0, 0, 0).expect("midnight exists");
+    // Days starting in a DST gap begin at the first valid local time.
+    Local
+        .from_local_datetime(&midnight)
+        .earliest()
+        .unwrap_or_else(|| Local.from_utc_datetime(&midnight))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(date: NaiveDate, h: u32, m: u32) -> DateTime<Local> {
+        Local
+            .from_local_datetime(&date.and_hms_opt(h, m, 0).unwrap())
+            .unwrap()
+    }
+
+    #[test]
+    fn compares_daily_counts_with_the_interval() {
+        let d1 = NaiveDate::from_ymd_opt(2025, 1, 1).unwrap();
+        let d2 = d1.succ_opt().unwrap();
+        let d3 = d2.succ_opt().unwrap();
+        let record = |t: DateTime<Local>, code| Record::new(t.to_rfc3339(), "1", code);
+        let mut records = vec![record(at(d1, 18, 0), 0), record(at(d1, 23, 0), 1)];
+        records.extend((0..20).map(|h| record(at(d2, h, 0), 0)));
+        let now = at(d3, 6, 0);
+        let hour = Duration::from_secs(3600);
+        let days = daily_coverage(&records, at(d1, 18, 0), now, hour, d1, d3);
+        assert_eq!(
+            days,
+            [
+                DayCoverage {
+                    date: d1,
+                    records: 2,
+                    failed: 1,
+                    expected: 6
+                },
+                DayCoverage {
+                    date: d2,
+                    records: 20,
+                    failed: 0,
+                    expected: 24
+                },
+                DayCoverage {
+                    date: d3,
+                    records: 0,
+                    failed: 0,
+                    expected: 6
+                },
+            ]
+        );
+        assert_eq!(percent(20, 24), "83.3%");
+    }
+
+    #[test]
+    fn infers_the_median_interval() {
+        let d = NaiveDate::from_ymd_opt(2025, 1, 1).unwrap();
+        let times = [
+            at(d, 0, 0),
+            at(d, 0, 1),
+            at(d, 0, 2),
+            at(d, 0, 30),
+            at(d, 0, 3),
+        ];
+        assert_eq!(infer_interval(&times), Some(Duration::from_secs(60)));
+        assert_eq!(infer_interval(&times[..1]), None);
+    }
+}
@@ -0,0 +1,75 @@
+use std::fmt;
+use std::io;
+use std::time::Duration;
+
+/// What went wrong running a job or a command.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// An option of the job has a value trep cannot use.
+    InvalidOption {
+        option: &'static str,
+        reason: String,
+    },
+    /// Another process (or thread) holds the job's lock.
+    Locked { job: String },
+    /// The command could not be started.
+    Spawn { command: String, source: io::Error },
+    /// The command did not finish within its timeout and was killed.
+    Timeout { command: String, after: Duration },
+    /// Reading or writing records, connecting a sink, loading a plugin, ...
+    Io(io::Error),
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl Error {
+    pub(crate) fn invalid(option: &'static str, reason: impl fmt::Display) -> Self {
+        Error::InvalidOption {
+            option,
+            reason: reason.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidOption { option, reason } => write!(f, "{option}: {reason}"),
+            Error::Locked { job } => write!(f, "job '{job}' is already running"),
+            Error::Spawn { command, source } => write!(f, "cannot run '{command}': {source}"),
+            Error::Timeout { after, .. } => write!(f, "command timed out after {after:?}"),
+            Error::Io(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Spawn { source, .. } => Some(source),
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+/// For the subcommands, which report every failure as an `io::Error`.
+impl From<Error> for io::Error {
+    fn from(e: Error) -> Self {
+        let kind = match e {
+            Error::Io(e) => return e,
+            Error::InvalidOption { .. } => io::ErrorKind::InvalidInput,
+            Error::Locked { .. } => io::ErrorKind::WouldBlock,
+            Error::Spawn { ref source, .. } => source.kind(),
+            Error::Timeout { .. } => io::ErrorKind::TimedOut,
+        };
+        io::Error::new(kind, e)
+    }
+}
@@ -1,47 +1,190 @@
+use std::fmt;
 use std::io;
+use std::path::PathBuf;
 use std::process::{Command, Stdio};
 use std::thread;
 use std::time::Duration;
 
-/// Run the provided shell command and capture its stdout.
-/// Returns (output trimmed, exit code).
-pub fn run_shell_command(command: &str, timeout: Option<Duration>) -> io::Result<(String, i32)> {
-    let shell = if cfg!(target_os = "windows") {
-        "cmd"
-    } else {
-        "bash"
-    };
-    let args: &[&str] = if cfg!(target_os = "windows") {
-        &["/C"]
-    } else {
-        &["-lc"]
-    };
-    let child = Command::new(shell)
-        .args(args)
-        .arg(command)
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()?;
+pub use crate::cli::Shell;
+use crate::error::{Error, Result};
+
+impl Shell {
+    /// `bash -lc` on Unix, `cmd /C` on Windows.
+    pub fn platform() -> Self {
+        if cfg!(target_os = "windows") {
+            Shell::Cmd
+        } else {
+            Shell::Bash
+        }
+    }
+
+    /// A `Command` that runs the command line `command` in this shell.
+    fn command(self, command: &str) -> Command {
+        let (program, args): (&str, &[&str]) = match self {
+            Shell::Sh => ("sh", &["-c"]),
+            Shell::Bash => ("bash", &["-lc"]),
+            Shell::Pwsh => ("pwsh", &["-NoProfile", "-NonInteractive", "-Command"]),
+            Shell::Cmd => ("cmd", &["/C"]),
+        };
+        let mut cmd = Command::new(program);
+        cmd.args(args).arg(command);
+        cmd
+    }
+}
+
+/// Build a `Command` that runs `command` through the platform shell.
+pub fn shell_command(command: &str) -> Command {
+    Shell::platform().command(command)
+}
+
+/// How to run a job's command: through which shell, if any, in which
+/// directory and with which extra environment variables.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExecSpec {
+    /// The command line, joined with spaces for the shell; without a
+    /// shell, the program followed by its arguments.
+    pub argv: Vec<String>,
+    /// The shell that interprets the command line, or `None` to start the
+    /// program directly.
+    pub shell: Option<Shell>,
+    /// Working directory; trep's own when unset.
+    pub cwd: Option<PathBuf>,
+    /// Variables added to the environment trep was started with.
+    pub env: Vec<(String, String)>,
+}
+
+impl ExecSpec {
+    /// Run the command line `command` through the platform shell.
+    pub fn shell(command: impl Into<String>) -> Self {
+        Self {
+            argv: vec![command.into()],
+            shell: Some(Shell::platform()),
+            cwd: None,
+            env: Vec::new(),
+        }
+    }
+
+    /// Start `argv[0]` with the rest as its arguments, without a shell.
+    pub fn direct<S: Into<String>>(argv: impl IntoIterator<Item = S>) -> Self {
+        Self {
+            argv: argv.into_iter().map(Into::into).collect(),
+            shell: None,
+            cwd: None,
+            env: Vec::new(),
+        }
+    }
+
+    /// Run in `dir`.
+    pub fn cwd(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.cwd = Some(dir.into());
+        self
+    }
+
+    /// Set the environment variable `key` to `value`.
+    pub fn env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.env.push((key.into(), value.into()));
+        self
+    }
+
+    fn command(&self) -> io::Result<Command> {
+        let mut cmd = match (self.shell, self.argv.split_first()) {
+            (Some(shell), _) => shell.command(&self.argv.join(" ")),
+            (None, Some((program, args))) => {
+                let mut cmd = Command::new(program);
+                cmd.args(args);
+                cmd
+            }
+            (None, None) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "no program to run",
+                ))
+            }
+        };
+        if let Some(dir) = &self.cwd {
+            cmd.current_dir(dir);
+        }
+        cmd.envs(self.env.iter().map(|(k, v)| (k, v)));
+        Ok(cmd)
+    }
+}
+
+/// The command line, for logs and errors.
+impl fmt::Display for ExecSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.argv.join(" "))
+    }
+}
+
+/// Captured result of a single command run.
+#[derive(Debug)]
+pub struct CommandOutput {
+    /// Trimmed stdout.
+    pub stdout: String,
+    /// Trimmed stderr.
+    pub stderr: String,
+    pub exit_code: i32,
+}
+
+/// Run the command `spec` describes and capture its stdout and stderr. With
+/// a `timeout`, a command still running after it is killed and the result
+/// is [`Error::Timeout`].
+pub fn run_command(spec: &ExecSpec, timeout: Option<Duration>) -> Result<CommandOutput> {
+    let command = spec.to_string();
+    let span = tracing::info_span!("exec", command, exit_code = tracing::field::Empty);
+    let _enter = span.enter();
+    let result = run_and_wait(spec, &command, timeout);
+    if let Ok(out) = &result {
+        span.record("exit_code", out.exit_code);
+    }
+    result
+}
+
+fn run_and_wait(
+    spec: &ExecSpec,
+    command: &str,
+    timeout: Option<Duration>,
+) -> Result<CommandOutput> {
+    let child = tracing::debug_span!("spawn")
+        .in_scope(|| {
+            spec.command()?
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()
+        })
+        .map_err(|source| Error::Spawn {
+            command: command.to_string(),
+            source,
+        })?;
 
     let pid = child.id();
 
     let (tx, rx) = std::sync::mpsc::channel();
+    let exec_span = tracing::Span::current();
     thread::spawn(move || {
-        let output = match child.wait_with_output() {
+        // `wait` is the child's own runtime; `capture` is trep decoding its output.
+        let wait = tracing::debug_span!(parent: &exec_span, "wait");
+        let output = match wait.in_scope(|| child.wait_with_output()) {
             Ok(out) => out,
             Err(e) => {
                 let _ = tx.send(Err(io::Error::other(format!("wait error: {e}"))));
                 return;
             }
         };
-        let exit_code = output.status.code().unwrap_or(-1);
-        let stdout_str = String::from_utf8_lossy(&output.stdout).trim().to_string();
-        let _ = tx.send(Ok((stdout_str, exit_code)));
+        drop(wait);
+        let capture = tracing::debug_span!(parent: &exec_span, "capture");
+        let output = capture.in_scope(|| CommandOutput {
+            stdout: String::from_utf8_lossy(&output.stdout).trim().to_string(),
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            exit_code: output.status.code().unwrap_or(-1),
+        });
+        drop(capture);
+        let _ = tx.send(Ok(output));
     });
 
     if let Some(to) = timeout {
         match rx.recv_timeout(to) {
-            Ok(res) => res,
+            Ok(res) => Ok(res?),
             Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {
                 if cfg!(target_os = "windows") {
                     let _ = Command::new("taskkill")
@@ -50,17 +193,17 @@ pub fn run_shell_command(command: &str, timeout: Option<Duration>) -> io::Result
                 } else {
                     let _ = Command::new("kill").args(["-9", &pid.to_string()]).status();
                 }
-                Err(io::Error::new(
-                    io::ErrorKind::TimedOut,
-                    format!("command timed out after {to:?}"),
-                ))
+                Err(Error::Timeout {
+                    command: command.to_string(),
+                    after: to,
+                })
             }
-            Err(_) => Err(io::Error::other("command execution error")),
+            Err(_) => Err(io::Error::other("command execution error").into()),
         }
     } else {
         match rx.recv() {
-            Ok(res) => res,
-            Err(_) => Err(io::Error::other("command execution error")),
+            Ok(res) => Ok(res?),
+            Err(_) => Err(io::Error::other("command execution error").into()),
         }
     }
 }
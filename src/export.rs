@@ -0,0 +1,430 @@
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, NaiveDate};
+use rust_xlsxwriter::{Chart, ChartType, Format, Workbook, Worksheet, XlsxError};
+use serde_json::Value;
+
+use crate::cli::{ExportFormat, ExportOpts, SheetsBy};
+use crate::storage::{self, CsvOptions, Record, RecordWriter};
+use crate::{parquet, report};
+
+/// Excel limits sheet names to 31 characters.
+const MAX_SHEET_NAME: usize = 31;
+
+pub fn export(opts: ExportOpts) -> io::Result<()> {
+    if opts.chart && opts.to != ExportFormat::Xlsx {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--chart only applies to xlsx exports",
+        ));
+    }
+    let out = opts.out.clone().unwrap_or_else(|| {
+        let stem = match opts.names.as_slice() {
+            [name] => name.as_str(),
+            _ => "trep-export",
+        };
+        PathBuf::from(format!("{stem}.{}", opts.to.extension()))
+    });
+    let mut jobs = Vec::new();
+    for name in &opts.names {
+        let files = storage::job_data_files(name)?;
+        let records = report::select_records(&files, opts.since, opts.until, false)?;
+        jobs.push((name.as_str(), records));
+    }
+    match opts.to {
+        ExportFormat::Csv | ExportFormat::Jsonl => {
+            write_records(&out, opts.to.extension(), &merge(&jobs))?;
+        }
+        ExportFormat::Parquet => write_parquet(&out, &merge(&jobs))?,
+        ExportFormat::Xlsx => write_xlsx(&out, &jobs, opts.sheets, opts.chart)?,
+    }
+    println!("Exported {} to {}", opts.names.join(", "), out.display());
+    Ok(())
+}
+
+/// Merge the jobs' records into one table in timestamp order. Every row
+/// gets the same columns, so the file reads back as one table: `timestamp`,
+/// `value` and `exit_code`, then a `job` field when there are several jobs,
+/// then every field any record has, null where a record lacks it.
+fn merge(jobs: &[(&str, Vec<Record>)]) -> Vec<Record> {
+    let mut columns: Vec<&str> = Vec::new();
+    if jobs.len() > 1 {
+        columns.push("job");
+    }
+    for (_, records) in jobs {
+        for key in records.iter().flat_map(|r| r.fields.keys()) {
+            if !columns.contains(&key.as_str()) {
+                columns.push(key);
+            }
+        }
+    }
+    let mut merged: Vec<(Option<DateTime<_>>, &str, &Record)> = jobs
+        .iter()
+        .flat_map(|(job, records)| {
+            records
+                .iter()
+                .map(|r| (DateTime::parse_from_rfc3339(&r.timestamp).ok(), *job, r))
+        })
+        .collect();
+    // Stable, so each job's records keep their order among equal times.
+    merged.sort_by_key(|(at, _, _)| *at);
+    merged
+        .into_iter()
+        .map(|(_, job, record)| {
+            let mut row = Record::new(&record.timestamp, &record.value, record.exit_code);
+            for &column in &columns {
+                let value = match column {
+                    "job" if jobs.len() > 1 => Value::String(job.to_string()),
+                    _ => record.fields.get(column).cloned().unwrap_or(Value::Null),
+                };
+                row.fields.insert(column.to_string(), value);
+            }
+            row
+        })
+        .collect()
+}
+
+/// Write merged records to one CSV or JSONL file.
+fn write_records(path: &Path, format: &str, rows: &[Record]) -> io::Result<()> {
+    // The writer appends, as `trep run` does; start from an empty file.
+    File::create(path)?;
+    let mut writer = RecordWriter::new(format, CsvOptions::default());
+    for row in rows {
+        writer.write(path, row)?;
+    }
+    Ok(())
+}
+
+/// Write merged records to a Parquet file.
+fn write_parquet(path: &Path, rows: &[Record]) -> io::Result<()> {
+    std::fs::write(path, parquet::encode(rows.len(), &parquet_columns(rows))?)
+}
+
+/// The Parquet columns of merged records: `timestamp` as a UTC timestamp
+/// (null where it does not parse), `value` as a string, `exit_code` as a
+/// 32-bit integer, and each field as booleans, 64-bit integers or doubles
+/// when all its values are, else as strings.
+fn parquet_columns(rows: &[Record]) -> Vec<parquet::Column> {
+    let timestamps: Vec<Option<i64>> = rows
+        .iter()
+        .map(|r| {
+            DateTime::parse_from_rfc3339(&r.timestamp)
+                .ok()
+                .map(|t| t.timestamp_micros())
+        })
+        .collect();
+    let mut columns = vec![
+        parquet::Column {
+            name: "timestamp".to_string(),
+            defined: Some(timestamps.iter().map(Option::is_some).collect()),
+            values: parquet::Values::Timestamp(timestamps.into_iter().flatten().collect()),
+        },
+        parquet::Column {
+            name: "value".to_string(),
+            defined: None,
+            values: parquet::Values::String(rows.iter().map(|r| r.value.clone()).collect()),
+        },
+        parquet::Column {
+            name: "exit_code".to_string(),
+            defined: None,
+            values: parquet::Values::Int32(rows.iter().map(|r| r.exit_code).collect()),
+        },
+    ];
+    let Some(first) = rows.first() else {
+        return columns;
+    };
+    for name in first.fields.keys() {
+        let cells: Vec<&Value> = rows
+            .iter()
+            .map(|r| r.fields.get(name).unwrap_or(&Value::Null))
+            .collect();
+        let present = || cells.iter().filter(|v| !v.is_null());
+        let values = if present().all(|v| v.is_boolean()) {
+            parquet::Values::Boolean(present().filter_map(|v| v.as_bool()).collect())
+        } else if present().all(|v| v.is_i64()) {
+            parquet::Values::Int64(present().filter_map(|v| v.as_i64()).collect())
+        } else if present().all(|v| v.is_number()) {
+            parquet::Values::Double(present().filter_map(|v| v.as_f64()).collect())
+        } else {
+            parquet::Values::String(present().map(|v| storage::field_to_string(v)).collect())
+        };
+        columns.push(parquet::Column {
+            name: name.clone(),
+            defined: Some(cells.iter().map(|v| !v.is_null()).collect()),
+            values,
+        });
+    }
+    columns
+}
+
+/// A worksheet's worth of records.
+struct Sheet<'a> {
+    name: String,
+    records: Vec<&'a Record>,
+}
+
+/// Split each job's records into sheets, per job or per job and day.
+fn group_sheets<'a>(jobs: &'a [(&str, Vec<Record>)], by: SheetsBy) -> Vec<Sheet<'a>> {
+    let mut sheets: Vec<Sheet> = Vec::new();
+    for (job, records) in jobs {
+        match by {
+            SheetsBy::Job => sheets.push(Sheet {
+                name: job.to_string(),
+                records: records.iter().collect(),
+            }),
+            SheetsBy::Day => {
+                let mut current: Option<NaiveDate> = None;
+                for record in records {
+                    let day = record_date(record);
+                    if current != Some(day) {
+                        current = Some(day);
+                        let name = if jobs.len() == 1 {
+                            day.to_string()
+                        } else {
+                            format!("{job} {day}")
+                        };
+                        sheets.push(Sheet {
+                            name,
+                            records: Vec::new(),
+                        });
+                    }
+                    sheets.last_mut().unwrap().records.push(record);
+                }
+            }
+        }
+    }
+    let mut used = Vec::new();
+    for sheet in &mut sheets {
+        sheet.name = unique_sheet_name(&sheet.name, &used);
+        used.push(sheet.name.clone());
+    }
+    sheets
+}
+
+fn record_date(record: &Record) -> NaiveDate {
+    DateTime::parse_from_rfc3339(&record.timestamp)
+        .map(|t| t.date_naive())
+        .unwrap_or_default()
+}
+
+/// Replace characters Excel rejects in sheet names and keep the name
+/// within 31 characters and distinct from the names already in `used`.
+fn unique_sheet_name(name: &str, used: &[String]) -> String {
+    let clean: String = name
+        .chars()
+        .map(|c| match c {
+            '[' | ']' | ':' | '*' | '?' | '/' | '\\' => '_',
+            c => c,
+        })
+        .collect();
+    let clean = clean.trim_matches('\'');
+    let clean = if clean.is_empty() { "Sheet" } else { clean };
+    let taken = |n: &str| used.iter().any(|u| u.eq_ignore_ascii_case(n));
+    let mut candidate: String = clean.chars().take(MAX_SHEET_NAME).collect();
+    let mut n = 2;
+    while taken(&candidate) {
+        let suffix = format!(" ({n})");
+        let keep = MAX_SHEET_NAME - suffix.len();
+        let head: String = clean.chars().take(keep).collect();
+        candidate = format!("{head}{suffix}");
+        n += 1;
+    }
+    candidate
+}
+
+fn xlsx_error(e: XlsxError) -> io::Error {
+    io::Error::other(format!("xlsx export failed: {e}"))
+}
+
+/// Write the sheets to an .xlsx workbook with typed cells: timestamps as
+/// Excel dates in the recorded local time, numeric values and fields as
+/// numbers. With `chart`, a chart sheet plots the value of every sheet.
+fn write_xlsx(
+    path: &Path,
+    jobs: &[(&str, Vec<Record>)],
+    by: SheetsBy,
+    chart: bool,
+) -> io::Result<()> {
+    let sheets = group_sheets(jobs, by);
+    let mut workbook = Workbook::new();
+    let mut plot = Chart::new(ChartType::ScatterStraight);
+    for sheet in &sheets {
+        let worksheet = workbook.add_worksheet();
+        worksheet.set_name(&sheet.name).map_err(xlsx_error)?;
+        write_sheet(worksheet, &sheet.records).map_err(xlsx_error)?;
+        if !sheet.records.is_empty() {
+            let last = sheet.records.len() as u32;
+            plot.add_series()
+                .set_name(sheet.name.as_str())
+                .set_categories((sheet.name.as_str(), 1, 0, last, 0))
+                .set_values((sheet.name.as_str(), 1, 1, last, 1));
+        }
+    }
+    if chart && !sheets.is_empty() {
+        plot.title().set_name("value");
+        plot.x_axis().set_num_format("yyyy-mm-dd hh:mm");
+        let chartsheet = workbook.add_chartsheet();
+        chartsheet.set_name("Chart").map_err(xlsx_error)?;
+        chartsheet.insert_chart(0, 0, &plot).map_err(xlsx_error)?;
+    }
+    workbook.save(path).map_err(xlsx_error)
+}
+
+fn write_sheet(worksheet: &mut Worksheet, records: &[&Record]) -> Result<(), XlsxError> {
+    let bold = Format::new().set_bold();
+    let datetime = Format::new().set_num_format("yyyy-mm-dd hh:mm:ss");
+    // Columns are the union of every record's fields, in order of appearance.
+    let mut columns: Vec<&str> = Vec::new();
+    for record in records {
+        for key in record.fields.keys() {
+            if !columns.contains(&key.as_str()) {
+                columns.push(key);
+            }
+        }
+    }
+    let header = ["timestamp", "value", "exit_code"];
+    for (col, title) in header.iter().chain(columns.iter()).enumerate() {
+        worksheet.write_string_with_format(0, col as u16, *title, &bold)?;
+    }
+    for (i, record) in records.iter().enumerate() {
+        let row = i as u32 + 1;
+        match DateTime::parse_from_rfc3339(&record.timestamp) {
+            Ok(t) => worksheet.write_datetime_with_format(row, 0, t.naive_local(), &datetime)?,
+            Err(_) => worksheet.write_string(row, 0, &record.timestamp)?,
+        };
+        match record.value.trim().parse::<f64>() {
+            Ok(n) if n.is_finite() => worksheet.write_number(row, 1, n)?,
+            _ => worksheet.write_string(row, 1, &record.value)?,
+        };
+        worksheet.write_number(row, 2, record.exit_code)?;
+        for (j, key) in columns.iter().enumerate() {
+            let col = j as u16 + 3;
+            match record.fields.get(*key) {
+                None | Some(Value::Null) => {}
+                Some(Value::Number(n)) => {
+                    worksheet.write_number(row, col, n.as_f64().unwrap_or(f64::NAN))?;
+                }
+                Some(Value::Bool(b)) => {
+                    worksheet.write_boolean(row, col, *b)?;
+                }
+                Some(v) => {
+                    worksheet.write_string(row, col, storage::field_to_string(v))?;
+                }
+            }
+        }
+    }
+    worksheet.set_freeze_panes(1, 0)?;
+    worksheet.autofit();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sheet_names_are_valid_and_unique() {
+        let used = vec!["a_b".to_string()];
+        assert_eq!(unique_sheet_name("a/b", &used), "a_b (2)");
+        assert_eq!(unique_sheet_name("'x'", &[]), "x");
+        let long = "x".repeat(40);
+        assert_eq!(unique_sheet_name(&long, &[]).len(), MAX_SHEET_NAME);
+        let taken = vec!["x".repeat(MAX_SHEET_NAME)];
+        let next = unique_sheet_name(&long, &taken);
+        assert!(next.ends_with(" (2)") && next.len() == MAX_SHEET_NAME);
+    }
+
+    #[test]
+    fn groups_records_by_day_or_job() {
+        let records = vec![
+            Record::new("2025-01-01T23:59:00+09:00", "1", 0),
+            Record::new("2025-01-02T00:01:00+09:00", "2", 0),
+        ];
+        let jobs = [("disk", records)];
+        let days = group_sheets(&jobs, SheetsBy::Day);
+        let names: Vec<_> = days.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["2025-01-01", "2025-01-02"]);
+        let by_job = group_sheets(&jobs, SheetsBy::Job);
+        assert_eq!(by_job.len(), 1);
+        assert_eq!(by_job[0].records.len(), 2);
+    }
+
+    #[test]
+    fn writes_workbook_with_chart() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.xlsx");
+        let mut record = Record::new("2025-01-01T00:00:00Z", "1.5", 0);
+        record.fields.insert("unit".into(), "s".into());
+        let jobs = [("job", vec![record, Record::new("bad", "err", 1)])];
+        write_xlsx(&path, &jobs, SheetsBy::Job, true).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        assert_eq!(&bytes[..2], b"PK");
+    }
+
+    #[test]
+    fn merges_jobs_into_one_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut slow = Record::new("2025-01-01T00:01:00Z", "2.5", 0);
+        slow.fields.insert("unit".into(), "s".into());
+        let jobs = [
+            ("a", vec![Record::new("2025-01-01T00:00:00Z", "1", 0), slow]),
+            ("b", vec![Record::new("2025-01-01T00:00:30Z", "=x", 3)]),
+        ];
+        for format in ["csv", "jsonl"] {
+            let path = dir.path().join(format!("out.{format}"));
+            std::fs::write(&path, "left over\n").unwrap();
+            write_records(&path, format, &merge(&jobs)).unwrap();
+            let records = storage::read_records(&path).unwrap();
+            let rows: Vec<_> = records
+                .iter()
+                .map(|r| {
+                    (
+                        r.value.as_str(),
+                        r.fields["job"].clone(),
+                        r.fields["unit"].clone(),
+                    )
+                })
+                .collect();
+            assert_eq!(
+                rows,
+                [
+                    ("1", "a".into(), Value::Null),
+                    ("=x", "b".into(), Value::Null),
+                    ("2.5", "a".into(), "s".into()),
+                ],
+                "{format}"
+            );
+            assert_eq!(records[1].exit_code, 3);
+        }
+    }
+
+    #[test]
+    fn types_parquet_columns() {
+        let mut first = Record::new("2025-01-01T00:00:00Z", "1", 0);
+        first.fields.insert("n".into(), 3.into());
+        first.fields.insert("x".into(), 1.into());
+        first.fields.insert("ok".into(), true.into());
+        let mut second = Record::new("bad", "2", 1);
+        second.fields.insert("x".into(), 2.5.into());
+        second.fields.insert("ok".into(), "yes".into());
+        let rows = merge(&[("job", vec![first, second])]);
+        let columns = parquet_columns(&rows);
+        let find = |name: &str| columns.iter().find(|c| c.name == name).unwrap();
+        // Records without a valid timestamp sort first.
+        assert_eq!(find("timestamp").defined, Some(vec![false, true]));
+        assert_eq!(
+            find("timestamp").values,
+            parquet::Values::Timestamp(vec![1_735_689_600_000_000])
+        );
+        assert_eq!(find("exit_code").values, parquet::Values::Int32(vec![1, 0]));
+        assert_eq!(find("n").defined, Some(vec![false, true]));
+        assert_eq!(find("n").values, parquet::Values::Int64(vec![3]));
+        assert_eq!(find("x").values, parquet::Values::Double(vec![2.5, 1.0]));
+        assert_eq!(
+            find("ok").values,
+            parquet::Values::String(vec!["yes".into(), "true".into()])
+        );
+    }
+}
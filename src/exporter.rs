@@ -0,0 +1,220 @@
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use tiny_http::{Header, Response, Server};
+
+use crate::metrics::{self, JobState};
+use crate::storage::{JobMeta, Record};
+use crate::top::JobSummary;
+
+/// Where `--export-prometheus` publishes a job's gauges.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    /// A node_exporter textfile, rewritten after every run.
+    Textfile(PathBuf),
+    /// A `/metrics` endpoint served on this address.
+    Http(SocketAddr),
+}
+
+impl Target {
+    /// An address such as `127.0.0.1:9464`, or just `:9464` for every
+    /// interface, serves HTTP; anything else is a textfile path.
+    pub fn parse(s: &str) -> Self {
+        let addr = match s.strip_prefix(':') {
+            Some(port) => format!("0.0.0.0:{port}"),
+            None => s.to_string(),
+        };
+        match addr.parse() {
+            Ok(addr) => Target::Http(addr),
+            Err(_) => Target::Textfile(PathBuf::from(s)),
+        }
+    }
+}
+
+/// Keeps a running job's latest value, exit code, run duration and failure
+/// streak as Prometheus gauges, in the format `trep serve` uses for
+/// `/metrics`.
+pub struct Exporter {
+    state: Arc<Mutex<JobState>>,
+    textfile: Option<PathBuf>,
+    server: Option<Arc<Server>>,
+}
+
+impl Exporter {
+    /// Start exporting the gauges of job `name` to `target`. An HTTP
+    /// endpoint starts listening here, so a busy port fails before the job
+    /// runs.
+    pub fn new(name: &str, target: Target) -> io::Result<Self> {
+        let state = Arc::new(Mutex::new(JobState {
+            name: name.to_string(),
+            summary: JobSummary::default(),
+            meta: None,
+        }));
+        let mut exporter = Self {
+            state: state.clone(),
+            textfile: None,
+            server: None,
+        };
+        match target {
+            Target::Textfile(path) => exporter.textfile = Some(path),
+            Target::Http(addr) => {
+                let server = Server::http(addr).map_err(|e| {
+                    io::Error::new(
+                        io::ErrorKind::AddrNotAvailable,
+                        format!("cannot listen on {addr}: {e}"),
+                    )
+                })?;
+                let server = Arc::new(server);
+                let serving = server.clone();
+                thread::spawn(move || serve(&serving, &state));
+                exporter.server = Some(server);
+            }
+        }
+        Ok(exporter)
+    }
+
+    /// The address the endpoint listens on, if it serves HTTP.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.server.as_ref()?.server_addr().to_ip()
+    }
+
+    /// Take in the record of a finished run and the job's metadata, which
+    /// carries the run's duration.
+    pub fn update(&mut self, record: &Record, meta: &JobMeta) {
+        let text = {
+            let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+            let summary = &mut state.summary;
+            if record.exit_code == 0 {
+                summary.consecutive_failures = 0;
+            } else {
+                summary.consecutive_failures += 1;
+                summary.last_failure = Some(record.clone());
+            }
+            summary.latest = Some(record.clone());
+            state.meta = Some(meta.clone());
+            metrics::render(std::slice::from_ref(&*state))
+        };
+        if let Some(path) = &self.textfile {
+            if let Err(e) = write_textfile(path, &text) {
+                tracing::warn!(path = %dunce::simplified(path).display(), error = %e, "could not write the metrics textfile");
+            }
+        }
+    }
+}
+
+impl Drop for Exporter {
+    fn drop(&mut self) {
+        if let Some(server) = &self.server {
+            server.unblock();
+        }
+    }
+}
+
+/// Answer scrapes until the exporter is dropped.
+fn serve(server: &Server, state: &Mutex<JobState>) {
+    for request in server.incoming_requests() {
+        let response = if request.url() == "/metrics" {
+            let text = {
+                let state = state.lock().unwrap_or_else(|e| e.into_inner());
+                metrics::render(std::slice::from_ref(&*state))
+            };
+            let header = Header::from_bytes("Content-Type", metrics::CONTENT_TYPE)
+                .expect("content types are valid headers");
+            Response::from_string(text).with_header(header)
+        } else {
+            Response::from_string("not found").with_status_code(404)
+        };
+        if let Err(e) = request.respond(response) {
+            tracing::warn!(error = %e, "could not send metrics");
+        }
+    }
+}
+
+/// Replace the textfile in one step, as node_exporter may read it at any
+/// time: write a temporary file next to it, then rename it over.
+fn write_textfile(path: &Path, text: &str) -> io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    fs::write(&tmp, text)?;
+    fs::rename(&tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+
+    fn meta(duration: f64) -> JobMeta {
+        JobMeta {
+            pid: 1,
+            source: "true".to_string(),
+            interval_secs: Some(60.0),
+            cron: None,
+            last_duration_secs: Some(duration),
+            ..JobMeta::default()
+        }
+    }
+
+    #[test]
+    fn parses_targets() {
+        assert_eq!(
+            Target::parse("127.0.0.1:9464"),
+            Target::Http("127.0.0.1:9464".parse().unwrap())
+        );
+        assert_eq!(
+            Target::parse(":9464"),
+            Target::Http("0.0.0.0:9464".parse().unwrap())
+        );
+        assert_eq!(
+            Target::parse("/var/lib/node_exporter/cpu.prom"),
+            Target::Textfile(PathBuf::from("/var/lib/node_exporter/cpu.prom"))
+        );
+    }
+
+    #[test]
+    fn writes_a_textfile() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cpu.prom");
+        let mut exporter = Exporter::new("cpu", Target::Textfile(path.clone())).unwrap();
+        exporter.update(&Record::new("2025-01-01T00:00:00Z", "oops", 2), &meta(1.5));
+        exporter.update(&Record::new("2025-01-01T00:01:00Z", "oops", 2), &meta(0.5));
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(text.contains("trep_exit_code{job=\"cpu\"} 2\n"), "{text}");
+        assert!(text.contains("trep_consecutive_failures{job=\"cpu\"} 2\n"));
+        assert!(text.contains("trep_run_duration_seconds{job=\"cpu\"} 0.5\n"));
+        assert!(!text.contains("trep_value{"));
+
+        exporter.update(&Record::new("2025-01-01T00:02:00Z", "7", 0), &meta(0.5));
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(text.contains("trep_value{job=\"cpu\"} 7\n"));
+        assert!(text.contains("trep_consecutive_failures{job=\"cpu\"} 0\n"));
+    }
+
+    #[test]
+    fn serves_metrics_over_http() {
+        let mut exporter =
+            Exporter::new("cpu", Target::Http("127.0.0.1:0".parse().unwrap())).unwrap();
+        exporter.update(&Record::new("2025-01-01T00:00:00Z", "42", 0), &meta(0.25));
+        let get = |path: &str| {
+            let mut stream = TcpStream::connect(exporter.local_addr().unwrap()).unwrap();
+            write!(
+                stream,
+                "GET {path} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n"
+            )
+            .unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+        let response = get("/metrics");
+        assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+        assert!(response.contains("trep_value{job=\"cpu\"} 42\n"));
+        assert!(response.contains("trep_run_duration_seconds{job=\"cpu\"} 0.25\n"));
+        assert!(get("/").starts_with("HTTP/1.1 404"));
+    }
+}
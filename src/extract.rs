@@ -0,0 +1,314 @@
+use std::fmt;
+use std::io;
+
+use regex::Regex;
+use serde_json::{Map, Value};
+
+use crate::units;
+
+/// One step of a JSON path.
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Key(String),
+    Index(i64),
+}
+
+/// A jq-style path such as `.data.temperature`, `.items[0].name` or
+/// `.["odd key"]`, used to select a value from JSON command output.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonPath {
+    segments: Vec<Segment>,
+}
+
+impl fmt::Display for JsonPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.segments.is_empty() {
+            return write!(f, ".");
+        }
+        for seg in &self.segments {
+            match seg {
+                Segment::Key(k) => write!(f, ".{k}")?,
+                Segment::Index(i) => write!(f, "[{i}]")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+fn invalid(path: &str, msg: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("invalid json path '{path}': {msg}"),
+    )
+}
+
+impl JsonPath {
+    pub fn parse(path: &str) -> io::Result<Self> {
+        let trimmed = path.trim();
+        let mut rest = trimmed.strip_prefix('$').unwrap_or(trimmed);
+        if rest.is_empty() {
+            return Err(invalid(path, "path is empty"));
+        }
+        let mut segments = Vec::new();
+        while !rest.is_empty() {
+            if let Some(after) = rest.strip_prefix('[') {
+                let end = after
+                    .find(']')
+                    .ok_or_else(|| invalid(path, "unclosed '['"))?;
+                let inner = after[..end].trim();
+                let seg = if let Some(quoted) =
+                    inner.strip_prefix('"').and_then(|s| s.strip_suffix('"'))
+                {
+                    Segment::Key(quoted.to_string())
+                } else {
+                    Segment::Index(
+                        inner
+                            .parse()
+                            .map_err(|_| invalid(path, "index must be an integer"))?,
+                    )
+                };
+                segments.push(seg);
+                rest = &after[end + 1..];
+            } else if let Some(after) = rest.strip_prefix('.') {
+                if after.is_empty() || after.starts_with('[') {
+                    rest = after;
+                    continue;
+                }
+                let end = after.find(['.', '[']).unwrap_or(after.len());
+                let key = &after[..end];
+                if key.is_empty() {
+                    return Err(invalid(path, "empty key"));
+                }
+                segments.push(Segment::Key(key.to_string()));
+                rest = &after[end..];
+            } else {
+                return Err(invalid(path, "expected '.' or '['"));
+            }
+        }
+        Ok(Self { segments })
+    }
+
+    /// Select the value at this path from `json`.
+    pub fn select<'a>(&self, json: &'a Value) -> Option<&'a Value> {
+        let mut current = json;
+        for seg in &self.segments {
+            current = match seg {
+                Segment::Key(k) => current.get(k)?,
+                Segment::Index(i) => {
+                    let items = current.as_array()?;
+                    let idx = if *i < 0 {
+                        items.len().checked_sub(i.unsigned_abs() as usize)?
+                    } else {
+                        *i as usize
+                    };
+                    items.get(idx)?
+                }
+            };
+        }
+        Some(current)
+    }
+
+    /// Parse `output` as JSON and return the selected value as record text:
+    /// strings unquoted, everything else as compact JSON.
+    pub fn extract(&self, output: &str) -> io::Result<String> {
+        let json: Value = serde_json::from_str(output)
+            .map_err(|e| io::Error::other(format!("output is not valid JSON: {e}")))?;
+        match self.select(&json) {
+            Some(Value::String(s)) => Ok(s.clone()),
+            Some(other) => Ok(other.to_string()),
+            None => Err(io::Error::other(format!("path {self} not found in output"))),
+        }
+    }
+}
+
+/// Record columns a parsed field may not replace.
+const CORE_COLUMNS: [&str; 3] = ["timestamp", "value", "exit_code"];
+
+/// Splits command output into named fields, for `run --parse`.
+#[derive(Debug, Clone)]
+pub enum FieldParser {
+    /// Fields selected from JSON output, in column order.
+    Json(Vec<(String, JsonPath)>),
+    /// A regex whose named groups are the fields.
+    Regex { regex: Regex, names: Vec<String> },
+}
+
+impl FieldParser {
+    /// `--parse json --fields`: each field is a top-level key of the output,
+    /// or `name=path` for a value further in, e.g. `temp=.sensors[0].value`.
+    pub fn json(fields: &[String]) -> io::Result<Self> {
+        let mut selected = Vec::new();
+        for field in fields {
+            let (name, path) = match field.split_once('=') {
+                Some((name, path)) => (name.trim(), JsonPath::parse(path)?),
+                None => (
+                    field.trim(),
+                    JsonPath {
+                        segments: vec![Segment::Key(field.trim().to_string())],
+                    },
+                ),
+            };
+            selected.push((name.to_string(), path));
+        }
+        check_names(selected.iter().map(|(name, _)| name.as_str()))?;
+        Ok(FieldParser::Json(selected))
+    }
+
+    /// `--parse regex --pattern`: the named groups of `pattern` become fields.
+    pub fn regex(pattern: &str) -> io::Result<Self> {
+        let regex = Regex::new(pattern).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid pattern '{pattern}': {e}"),
+            )
+        })?;
+        let names: Vec<String> = regex.capture_names().flatten().map(String::from).collect();
+        if names.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("pattern '{pattern}' has no named groups such as (?<cpu>\\d+)"),
+            ));
+        }
+        check_names(names.iter().map(String::as_str))?;
+        Ok(FieldParser::Regex { regex, names })
+    }
+
+    /// The field names, in column order.
+    pub fn names(&self) -> Vec<&str> {
+        match self {
+            FieldParser::Json(fields) => fields.iter().map(|(name, _)| name.as_str()).collect(),
+            FieldParser::Regex { names, .. } => names.iter().map(String::as_str).collect(),
+        }
+    }
+
+    /// The fields of `output`, in column order. A JSON field missing from
+    /// the output is null; output that is not JSON, or that the regex does
+    /// not match, is an error. Regex captures that are numbers become
+    /// numbers.
+    pub fn parse(&self, output: &str) -> io::Result<Map<String, Value>> {
+        let mut fields = Map::new();
+        match self {
+            FieldParser::Json(selected) => {
+                let json: Value = serde_json::from_str(output)
+                    .map_err(|e| io::Error::other(format!("output is not valid JSON: {e}")))?;
+                for (name, path) in selected {
+                    let value = path.select(&json).cloned().unwrap_or(Value::Null);
+                    fields.insert(name.clone(), value);
+                }
+            }
+            FieldParser::Regex { regex, names } => {
+                let captures = regex
+                    .captures(output)
+                    .ok_or_else(|| io::Error::other("pattern does not match the output"))?;
+                for name in names {
+                    let value = match captures.name(name) {
+                        Some(m) => match m.as_str().trim().parse::<f64>() {
+                            Ok(n) if n.is_finite() => units::number_value(n),
+                            _ => Value::String(m.as_str().to_string()),
+                        },
+                        None => Value::Null,
+                    };
+                    fields.insert(name.clone(), value);
+                }
+            }
+        }
+        Ok(fields)
+    }
+}
+
+/// Field names must be unique and must not shadow the record's own columns.
+fn check_names<'a>(names: impl Iterator<Item = &'a str>) -> io::Result<()> {
+    let mut seen = Vec::new();
+    for name in names {
+        let problem = if name.is_empty() {
+            "field names cannot be empty"
+        } else if CORE_COLUMNS.contains(&name) {
+            "is a column of every record"
+        } else if seen.contains(&name) {
+            "is given more than once"
+        } else {
+            seen.push(name);
+            continue;
+        };
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid field '{name}': {problem}"),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_paths() {
+        let p = JsonPath::parse(".data.items[1][\"odd key\"]").unwrap();
+        assert_eq!(
+            p.segments,
+            vec![
+                Segment::Key("data".into()),
+                Segment::Key("items".into()),
+                Segment::Index(1),
+                Segment::Key("odd key".into()),
+            ]
+        );
+        assert_eq!(
+            JsonPath::parse("$.a").unwrap(),
+            JsonPath::parse(".a").unwrap()
+        );
+        assert!(JsonPath::parse("").is_err());
+        assert!(JsonPath::parse(".a[").is_err());
+        assert!(JsonPath::parse(".a[x]").is_err());
+        assert!(JsonPath::parse("a").is_err());
+    }
+
+    #[test]
+    fn extracts_values() {
+        let out = r#"{"data":{"temperature":21.5,"name":"lab","tags":["a","b"]}}"#;
+        let get = |p: &str| JsonPath::parse(p).unwrap().extract(out);
+        assert_eq!(get(".data.temperature").unwrap(), "21.5");
+        assert_eq!(get(".data.name").unwrap(), "lab");
+        assert_eq!(get(".data.tags[-1]").unwrap(), "b");
+        assert_eq!(get(".data.tags").unwrap(), r#"["a","b"]"#);
+        assert!(get(".data.missing").is_err());
+        assert!(JsonPath::parse(".a").unwrap().extract("not json").is_err());
+    }
+
+    #[test]
+    fn parses_json_fields() {
+        let fields = [
+            "cpu".to_string(),
+            "temp=.sensors[0].value".to_string(),
+            "gone".to_string(),
+        ];
+        let parser = FieldParser::json(&fields).unwrap();
+        assert_eq!(parser.names(), ["cpu", "temp", "gone"]);
+        let parsed = parser
+            .parse(r#"{"cpu": 12.5, "sensors": [{"value": "warm"}]}"#)
+            .unwrap();
+        assert_eq!(
+            Value::Object(parsed),
+            serde_json::json!({"cpu": 12.5, "temp": "warm", "gone": null})
+        );
+        assert!(parser.parse("cpu=12").is_err());
+
+        assert!(FieldParser::json(&["value".to_string()]).is_err());
+        assert!(FieldParser::json(&["a".to_string(), "a=.b".to_string()]).is_err());
+    }
+
+    #[test]
+    fn parses_regex_fields() {
+        let parser =
+            FieldParser::regex(r"load (?<load1>\S+) users (?<users>\d+) on (?<host>\w+)").unwrap();
+        let parsed = parser.parse("load 0.25 users 3 on box1").unwrap();
+        assert_eq!(
+            Value::Object(parsed),
+            serde_json::json!({"load1": 0.25, "users": 3, "host": "box1"})
+        );
+        assert!(parser.parse("nothing here").is_err());
+        assert!(FieldParser::regex(r"(\d+)").is_err());
+        assert!(FieldParser::regex(r"(?<x>").is_err());
+    }
+}
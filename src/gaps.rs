@@ -0,0 +1,164 @@
+use std::io;
+use std::time::Duration;
+
+use chrono::{DateTime, Local};
+
+use crate::cli::GapsOpts;
+use crate::coverage;
+use crate::storage;
+use crate::util;
+
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// A stretch of time without records.
+#[derive(Debug, PartialEq)]
+struct Gap {
+    start: DateTime<Local>,
+    end: DateTime<Local>,
+}
+
+impl Gap {
+    fn duration(&self) -> Duration {
+        (self.end - self.start).to_std().unwrap_or_default()
+    }
+
+    /// Runs that should have happened inside the gap.
+    fn missed(&self, expect: Duration) -> u64 {
+        let runs = self.duration().as_secs_f64() / expect.as_secs_f64().max(f64::EPSILON);
+        (runs.round() as u64).saturating_sub(1)
+    }
+}
+
+pub fn gaps(opts: GapsOpts) -> io::Result<()> {
+    let records = storage::read_job_records(&opts.name)?;
+    let mut times = coverage::record_times(&records);
+    times.sort();
+    if times.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("job '{}' has no records yet", opts.name),
+        ));
+    }
+    let expect = match &opts.expect {
+        Some(expect) => util::duration_arg(expect, "interval")?,
+        None => {
+            let expect = coverage::infer_interval(&times).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "too few records to infer the interval; pass --expect",
+                )
+            })?;
+            println!(
+                "Interval {} inferred from the records; pass --expect to override",
+                humantime::format_duration(expect)
+            );
+            expect
+        }
+    };
+    let min_gap = match &opts.min_gap {
+        Some(min_gap) => util::duration_arg(min_gap, "minimum gap")?,
+        None => expect.mul_f64(1.5),
+    };
+    let now = Local::now();
+    let start = opts.since.map(coverage::start_of_day);
+    let end = opts
+        .until
+        .map(|d| d.succ_opt().map_or(now, coverage::start_of_day).min(now));
+    let period_start = start.unwrap_or(times[0]);
+    let period_end = end.unwrap_or(times[times.len() - 1]);
+    let found = find_gaps(&times, start, end, min_gap);
+
+    let mut total = Duration::ZERO;
+    for gap in &found {
+        total += gap.duration();
+        println!(
+            "{} - {}  {:>10}  (~{} missed)",
+            gap.start.format(TIME_FORMAT),
+            gap.end.format(TIME_FORMAT),
+            humantime::format_duration(round_secs(gap.duration())).to_string(),
+            gap.missed(expect)
+        );
+    }
+    let period = (period_end - period_start).to_std().unwrap_or_default();
+    let share = if period.is_zero() {
+        0.0
+    } else {
+        total.as_secs_f64() / period.as_secs_f64() * 100.0
+    };
+    println!(
+        "{} gaps of at least {} between {} and {}: {} without records ({share:.1}%)",
+        found.len(),
+        humantime::format_duration(min_gap),
+        period_start.format(TIME_FORMAT),
+        period_end.format(TIME_FORMAT),
+        humantime::format_duration(round_secs(total))
+    );
+    Ok(())
+}
+
+fn round_secs(d: Duration) -> Duration {
+    Duration::from_secs(d.as_secs_f64().round() as u64)
+}
+
+/// Stretches of at least `min_gap` without records in sorted `times`. With
+/// explicit period bounds, the time from `start` to the first record and
+/// from the last record to `end` count too.
+fn find_gaps(
+    times: &[DateTime<Local>],
+    start: Option<DateTime<Local>>,
+    end: Option<DateTime<Local>>,
+    min_gap: Duration,
+) -> Vec<Gap> {
+    let inside: Vec<DateTime<Local>> = times
+        .iter()
+        .copied()
+        .filter(|t| start.is_none_or(|s| *t >= s) && end.is_none_or(|e| *t <= e))
+        .collect();
+    let bounds = start
+        .into_iter()
+        .chain(inside)
+        .chain(end)
+        .collect::<Vec<_>>();
+    bounds
+        .windows(2)
+        .map(|w| Gap {
+            start: w[0],
+            end: w[1],
+        })
+        .filter(|gap| gap.duration() >= min_gap)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{NaiveDate, TimeZone};
+
+    fn at(h: u32, m: u32) -> DateTime<Local> {
+        let date = NaiveDate::from_ymd_opt(2025, 1, 1).unwrap();
+        Local
+            .from_local_datetime(&date.and_hms_opt(h, m, 0).unwrap())
+            .unwrap()
+    }
+
+    #[test]
+    fn lists_stretches_without_records() {
+        let times = [at(10, 0), at(10, 1), at(10, 2), at(10, 9), at(10, 10)];
+        let five = Duration::from_secs(300);
+        let found = find_gaps(&times, None, None, five);
+        assert_eq!(
+            found,
+            [Gap {
+                start: at(10, 2),
+                end: at(10, 9)
+            }]
+        );
+        assert_eq!(found[0].missed(Duration::from_secs(60)), 6);
+
+        let bounded = find_gaps(&times, Some(at(9, 0)), Some(at(11, 0)), five);
+        assert_eq!(bounded.len(), 3);
+        assert_eq!(bounded[0].start, at(9, 0));
+        assert_eq!(bounded[2].end, at(11, 0));
+        assert!(find_gaps(&times, None, None, Duration::from_secs(600)).is_empty());
+    }
+}
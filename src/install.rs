@@ -0,0 +1,205 @@
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use clap::Parser;
+
+use crate::cli::{Cli, Commands, CronOpts, InstallTarget};
+use crate::storage;
+
+const CRON_MACROS: &[&str] = &[
+    "@reboot",
+    "@yearly",
+    "@annually",
+    "@monthly",
+    "@weekly",
+    "@daily",
+    "@midnight",
+    "@hourly",
+];
+
+pub fn install(target: InstallTarget) -> io::Result<()> {
+    match target {
+        InstallTarget::Cron(opts) => cron(opts),
+    }
+}
+
+fn cron(opts: CronOpts) -> io::Result<()> {
+    let schedule = opts.schedule.trim();
+    check_schedule(schedule)?;
+    let name = job_name(&opts.run_args)?;
+    let exe = std::env::current_exe()?;
+    let log = storage::ensure_data_dir(&name)?.join("cron.log");
+    let entry = cron_entry(schedule, &exe, &name, &opts.run_args, &log);
+    if !opts.install {
+        println!("{entry}");
+        return Ok(());
+    }
+    let current = read_crontab()?;
+    write_crontab(&replace_entry(&current, &name, &entry))?;
+    println!("Installed crontab entry for '{name}':\n{entry}");
+    Ok(())
+}
+
+/// Accept a cron macro or exactly five schedule fields.
+fn check_schedule(schedule: &str) -> io::Result<()> {
+    let fields = schedule.split_whitespace().count();
+    let ok = if schedule.starts_with('@') {
+        CRON_MACROS.contains(&schedule)
+    } else {
+        fields == 5 && !schedule.contains('%')
+    };
+    if ok {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "invalid cron schedule '{schedule}': expected five fields like \"*/5 * * * *\" or one of {}",
+                CRON_MACROS.join(", ")
+            ),
+        ))
+    }
+}
+
+/// Check that `run_args` form a valid one-shot `trep run` and return its job name.
+fn job_name(run_args: &[String]) -> io::Result<String> {
+    let argv = ["trep", "run"]
+        .into_iter()
+        .map(String::from)
+        .chain(run_args.iter().cloned());
+    let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidInput, msg);
+    let cli =
+        Cli::try_parse_from(argv).map_err(|e| invalid(format!("invalid run arguments: {e}")))?;
+    match cli.command {
+        Commands::Run(run) if run.every.is_some() => Err(invalid(
+            "--every cannot be used with install cron; the schedule decides when the job runs"
+                .to_string(),
+        )),
+        Commands::Run(run) => Ok(run.name),
+        _ => unreachable!("argv always starts with the run subcommand"),
+    }
+}
+
+/// Marker comment identifying the crontab line managed for a job.
+fn marker(name: &str) -> String {
+    format!("# trep:{name}")
+}
+
+/// The crontab line running `trep run --single-shot` with `run_args`,
+/// appending its output to `log`.
+fn cron_entry(schedule: &str, exe: &Path, name: &str, run_args: &[String], log: &Path) -> String {
+    let mut command = vec![shell_quote(&exe.to_string_lossy()), "run".to_string()];
+    let options = run_args.iter().take_while(|a| *a != "--");
+    if !options.clone().any(|a| a == "--single-shot") {
+        command.push("--single-shot".to_string());
+    }
+    command.extend(run_args.iter().map(|a| shell_quote(a)));
+    let command = format!(
+        "{} >> {} 2>&1",
+        command.join(" "),
+        shell_quote(&log.to_string_lossy())
+    );
+    // cron turns unescaped % into newlines.
+    let command = command.replace('%', "\\%");
+    format!("{schedule} {command} {}", marker(name))
+}
+
+/// Quote `arg` for a POSIX shell unless it only holds safe characters.
+fn shell_quote(arg: &str) -> String {
+    let safe = !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_-./:=@,+%".contains(c));
+    if safe {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
+}
+
+/// Return `crontab` with the job's previous entry (if any) replaced by `entry`.
+fn replace_entry(crontab: &str, name: &str, entry: &str) -> String {
+    let marker = marker(name);
+    let mut out: String = crontab
+        .lines()
+        .filter(|line| !line.trim_end().ends_with(&marker))
+        .flat_map(|line| [line, "\n"])
+        .collect();
+    out.push_str(entry);
+    out.push('\n');
+    out
+}
+
+fn read_crontab() -> io::Result<String> {
+    let output = Command::new("crontab")
+        .arg("-l")
+        .output()
+        .map_err(|e| io::Error::new(e.kind(), format!("failed to run crontab: {e}")))?;
+    // `crontab -l` fails when the user has no crontab yet.
+    if !output.status.success() {
+        return Ok(String::new());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn write_crontab(contents: &str) -> io::Result<()> {
+    let mut child = Command::new("crontab")
+        .arg("-")
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|e| io::Error::new(e.kind(), format!("failed to run crontab: {e}")))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(contents.as_bytes())?;
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(io::Error::other(format!("crontab exited with {status}")));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn builds_quoted_entry() {
+        let run_args = args(&["--as", "disk", "--", "df -h / | awk '{print $5}'", "%"]);
+        assert_eq!(job_name(&run_args).unwrap(), "disk");
+        let entry = cron_entry(
+            "*/5 * * * *",
+            Path::new("/usr/bin/trep"),
+            "disk",
+            &run_args,
+            Path::new("/home/u/.tiny-reporter/disk/cron.log"),
+        );
+        assert_eq!(
+            entry,
+            r"*/5 * * * * /usr/bin/trep run --single-shot --as disk -- 'df -h / | awk '\''{print $5}'\''' \% >> /home/u/.tiny-reporter/disk/cron.log 2>&1 # trep:disk"
+        );
+    }
+
+    #[test]
+    fn rejects_bad_schedules_and_run_args() {
+        assert!(check_schedule("*/5 * * * *").is_ok());
+        assert!(check_schedule("@hourly").is_ok());
+        assert!(check_schedule("* * * *").is_err());
+        assert!(check_schedule("@often").is_err());
+        assert!(job_name(&args(&["--as", "x", "--every", "1m", "--", "true"])).is_err());
+        assert!(job_name(&args(&["--", "true"])).is_err());
+    }
+
+    #[test]
+    fn replaces_previous_entry_for_the_job() {
+        let crontab = "MAILTO=me\n0 * * * * old # trep:disk\n0 * * * * other # trep:cpu\n";
+        assert_eq!(
+            replace_entry(crontab, "disk", "1 * * * * new # trep:disk"),
+            "MAILTO=me\n0 * * * * other # trep:cpu\n1 * * * * new # trep:disk\n"
+        );
+    }
+}
@@ -0,0 +1,1167 @@
+use std::io;
+use std::path::PathBuf;
+use std::sync::{
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+    Arc,
+};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chrono::{Local, NaiveDate};
+
+use crate::alert::{self, AlertOptions, Alerter};
+use crate::change::OnChange;
+use crate::cli::{ParseMode, RotateBy, RunOpts, Shell};
+use crate::error::{Error, Result};
+use crate::exec::ExecSpec;
+use crate::exporter::{self, Exporter};
+use crate::retention::Retention;
+use crate::schedule::{self, IntervalSchedule, Schedule, WallSchedule};
+use crate::sink::{self, Sink};
+use crate::storage::{self, CsvOptions, Record, RecordStore, STOPPED_EXIT_CODE, STOPPED_MARKER};
+use crate::{collector, exec, extract, pipeline, plugin, probe, template, transform, util};
+
+/// One job: what to sample, when, and where its records go. Build it for a
+/// shell command with [`Job::command`] (or [`Job::exec`]) and the builder
+/// methods, then [`run`](Job::run) it.
+pub struct Job {
+    name: String,
+    source: Source,
+    /// What the job samples, for logs and `trep top`.
+    source_desc: String,
+    /// Where the records, lock and metadata go; the job's directory under
+    /// the base directory unless set.
+    data_dir: Option<PathBuf>,
+    interval: Option<Duration>,
+    cron: Option<String>,
+    align: bool,
+    single_shot: bool,
+    format: String,
+    csv: CsvOptions,
+    rotate_by: RotateBy,
+    retention: Option<Retention>,
+    timeout: Option<Duration>,
+    max_expected: Option<Duration>,
+    retry: Option<Retry>,
+    /// Only store records that differ from the last stored one.
+    on_change: bool,
+    /// With `on_change`, store an unchanged record this long after the last.
+    heartbeat: Option<Duration>,
+    pipeline: pipeline::Pipeline,
+    rejects: bool,
+    transform: Option<transform::Transform>,
+    plugin_transforms: Vec<plugin::PluginTransform>,
+    sinks: Vec<Box<dyn Sink>>,
+    recorder: Option<Box<dyn RecordStore>>,
+    alerter: Option<Alerter>,
+    exporter: Option<Exporter>,
+}
+
+impl Job {
+    /// A job that runs `command` through the shell once and appends the
+    /// result to CSV day files, with none of the value processing.
+    pub fn command(name: impl Into<String>, command: impl Into<String>) -> Self {
+        Self::exec(name, ExecSpec::shell(command))
+    }
+
+    /// Like [`Job::command`], for a command with its own shell, working
+    /// directory or environment.
+    pub fn exec(name: impl Into<String>, spec: ExecSpec) -> Self {
+        Self {
+            name: name.into(),
+            source_desc: spec.to_string(),
+            source: Source::Command(spec),
+            data_dir: None,
+            interval: None,
+            cron: None,
+            align: false,
+            single_shot: false,
+            format: "csv".to_string(),
+            csv: CsvOptions::default(),
+            rotate_by: RotateBy::default(),
+            retention: None,
+            timeout: None,
+            max_expected: None,
+            retry: None,
+            on_change: false,
+            heartbeat: None,
+            pipeline: pipeline::Pipeline::new(pipeline::PipelineOptions::default()),
+            rejects: false,
+            transform: None,
+            plugin_transforms: Vec::new(),
+            sinks: Vec::new(),
+            recorder: None,
+            alerter: None,
+            exporter: None,
+        }
+    }
+
+    /// Run every `interval` until stopped.
+    pub fn every(mut self, interval: Duration) -> Self {
+        self.interval = Some(interval);
+        self
+    }
+
+    /// Run at the times of a cron expression until stopped.
+    pub fn cron(mut self, expr: impl Into<String>) -> Self {
+        self.cron = Some(expr.into());
+        self
+    }
+
+    /// Kill a command still running after `timeout`; the run is recorded as
+    /// failed.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Delete or gzip old day files (or database records) by `policy`, at
+    /// start and whenever the day rotates.
+    pub fn retain(mut self, policy: Retention) -> Self {
+        self.retention = Some(policy);
+        self
+    }
+
+    /// Run a failed command again, as `retry` says, before storing its record.
+    pub fn retry(mut self, retry: Retry) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+
+    /// Only store a record when its value or exit code differs from the last
+    /// stored one, or, with a `heartbeat`, once that long has passed since.
+    pub fn on_change(mut self, heartbeat: Option<Duration>) -> Self {
+        self.on_change = true;
+        self.heartbeat = heartbeat;
+        self
+    }
+
+    /// Store records as "csv" or "jsonl" day files, or in a "sqlite" database.
+    pub fn format(mut self, format: impl Into<String>) -> Self {
+        self.format = format.into().to_lowercase();
+        self
+    }
+
+    /// Keep the records, lock and metadata in `dir` instead of the job's
+    /// directory under the base directory.
+    pub fn data_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.data_dir = Some(dir.into());
+        self
+    }
+
+    /// Also hand every stored record to `sink`.
+    pub fn sink(mut self, sink: Box<dyn Sink>) -> Self {
+        self.sinks.push(sink);
+        self
+    }
+
+    /// Append records to `recorder` instead of the files (or database) of
+    /// the format.
+    pub fn recorder(mut self, recorder: Box<dyn RecordStore>) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    /// Alert on failed, timed out and out-of-range runs.
+    pub fn alert(mut self, opts: AlertOptions) -> Self {
+        self.alerter = Some(Alerter::new(&self.name, opts));
+        self
+    }
+
+    /// Publish the job's latest state as Prometheus gauges.
+    pub fn exporter(mut self, exporter: Exporter) -> Self {
+        self.exporter = Some(exporter);
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The job `trep run` describes: its source, processing and sinks are
+    /// set up (and the sinks connected) here, so option errors show up
+    /// before anything runs.
+    pub(crate) fn from_opts(opts: RunOpts) -> Result<Self> {
+        let RunOpts {
+            name,
+            every,
+            single_shot,
+            cron,
+            align,
+            format,
+            rotate_by,
+            retain,
+            retain_files,
+            compress,
+            timeout,
+            max_expected,
+            retries,
+            retry_delay,
+            retry_backoff,
+            on_change,
+            heartbeat,
+            no_formula_escape,
+            crlf,
+            bom,
+            jsonpath,
+            parse,
+            fields,
+            pattern,
+            normalize_units,
+            validate,
+            rejects,
+            derive,
+            kind,
+            rolling_avg,
+            rolling_mode,
+            precision,
+            value_template,
+            redis_url,
+            redis_stream,
+            nats_url,
+            nats_subject,
+            nats_jetstream,
+            syslog,
+            syslog_facility,
+            eventlog,
+            pipe_to,
+            alert_above,
+            alert_below,
+            alert_webhook,
+            alert_command,
+            alert_cooldown,
+            export_prometheus,
+            transform,
+            plugins,
+            collector: collector_spec,
+            check,
+            warn_days,
+            critical_days,
+            shell,
+            no_shell,
+            cwd,
+            env,
+            cmd,
+        } = opts;
+        let duration = |value: Option<String>, option, what| {
+            value
+                .map(|s| util::duration_arg(&s, what).map_err(|e| Error::invalid(option, e)))
+                .transpose()
+        };
+        let interval = duration(every, "--every", "interval")?;
+        let timeout = duration(timeout, "--timeout", "timeout")?;
+        let max_expected = duration(max_expected, "--max-expected", "duration")?;
+        let alert_cooldown = duration(alert_cooldown, "--alert-cooldown", "cooldown")?;
+        let retry_delay = duration(retry_delay, "--retry-delay", "retry delay")?;
+        let heartbeat = duration(heartbeat, "--heartbeat", "heartbeat")?;
+        let retry = match retries {
+            Some(retries) => Some(Retry {
+                retries,
+                delay: retry_delay.unwrap_or(DEFAULT_RETRY_DELAY),
+                backoff: match &retry_backoff {
+                    Some(s) => {
+                        parse_backoff(s).map_err(|e| Error::invalid("--retry-backoff", e))?
+                    }
+                    None => 1.0,
+                },
+            }),
+            None => None,
+        };
+        let format = format.to_lowercase();
+        check_format(&format)?;
+        if format != "csv" && (crlf || bom || no_formula_escape) {
+            return Err(Error::invalid(
+                "--format",
+                "--crlf, --bom and --no-formula-escape only apply to CSV output",
+            ));
+        }
+        let retention = Retention::from_opts(retain.as_deref(), retain_files, compress)
+            .map_err(|e| Error::invalid("--retain", e))?;
+        if format == "sqlite" && (retain_files.is_some() || compress) {
+            return Err(Error::invalid(
+                "--format",
+                "--retain-files and --compress only apply to day files",
+            ));
+        }
+        let csv = CsvOptions {
+            escape_formulas: !no_formula_escape,
+            crlf,
+            bom,
+        };
+
+        let pipeline = pipeline::Pipeline::new(pipeline::PipelineOptions {
+            jsonpath: match &jsonpath {
+                Some(p) => Some(extract::JsonPath::parse(p)?),
+                None => None,
+            },
+            parser: match (parse, &pattern) {
+                (Some(ParseMode::Json), None) => Some(
+                    extract::FieldParser::json(&fields)
+                        .map_err(|e| Error::invalid("--fields", e))?,
+                ),
+                (Some(ParseMode::Regex), Some(pattern)) if fields.is_empty() => Some(
+                    extract::FieldParser::regex(pattern)
+                        .map_err(|e| Error::invalid("--pattern", e))?,
+                ),
+                (Some(ParseMode::Json), Some(_)) => {
+                    return Err(Error::invalid("--pattern", "only applies to --parse regex"))
+                }
+                (Some(ParseMode::Regex), _) => {
+                    return Err(Error::invalid("--fields", "only applies to --parse json"))
+                }
+                (None, _) => None,
+            },
+            normalize_units,
+            rules: validate
+                .iter()
+                .map(|r| r.parse())
+                .collect::<io::Result<_>>()?,
+            derive,
+            kind,
+            rolling_avg,
+            rolling_mode,
+            precision,
+            template: match &value_template {
+                Some(t) => Some(template::ValueTemplate::parse(t)?),
+                None => None,
+            },
+        });
+
+        let mut sinks: Vec<Box<dyn Sink>> = Vec::new();
+        if let Some(url) = &redis_url {
+            let stream = redis_stream.unwrap_or_else(|| format!("trep:{name}"));
+            sinks.push(Box::new(sink::RedisSink::new(url, &stream)?));
+        }
+        if let Some(url) = &nats_url {
+            let subject = nats_subject.unwrap_or_else(|| format!("trep.{name}"));
+            sinks.push(Box::new(sink::NatsSink::new(
+                url,
+                &subject,
+                nats_jetstream,
+            )?));
+        }
+        if let Some(target) = &syslog {
+            sinks.push(Box::new(sink::SyslogSink::new(
+                target,
+                syslog_facility.code(),
+                &name,
+            )?));
+        }
+        if let Some(mode) = eventlog {
+            #[cfg(windows)]
+            sinks.push(Box::new(sink::EventLogSink::new(
+                &name,
+                mode == crate::cli::EventLogMode::All,
+            )?));
+            #[cfg(not(windows))]
+            {
+                let _ = mode;
+                return Err(Error::invalid("--eventlog", "only available on Windows"));
+            }
+        }
+        if let Some(command) = &pipe_to {
+            sinks.push(Box::new(sink::PipeSink::new(command)?));
+        }
+
+        let alerter = if alert_webhook.is_some() || alert_command.is_some() {
+            Some(Alerter::new(
+                &name,
+                AlertOptions {
+                    above: alert_above,
+                    below: alert_below,
+                    webhook: alert_webhook,
+                    command: alert_command,
+                    cooldown: alert_cooldown.unwrap_or(alert::DEFAULT_COOLDOWN),
+                },
+            ))
+        } else if let Some(option) = [
+            ("--alert-above", alert_above.is_some()),
+            ("--alert-below", alert_below.is_some()),
+            ("--alert-cooldown", alert_cooldown.is_some()),
+        ]
+        .into_iter()
+        .find_map(|(option, set)| set.then_some(option))
+        {
+            return Err(Error::invalid(
+                option,
+                "needs --alert-webhook or --alert-command",
+            ));
+        } else {
+            None
+        };
+
+        let exporter = match &export_prometheus {
+            Some(target) => Some(
+                Exporter::new(&name, exporter::Target::parse(target))
+                    .map_err(|e| Error::invalid("--export-prometheus", e))?,
+            ),
+            None => None,
+        };
+
+        let (source, source_desc) = match (&collector_spec, &check) {
+            (Some(spec), _) => (
+                Source::Collector(Box::new(collector::CollectorState::new(spec.parse()?)?)),
+                format!("collector {spec}"),
+            ),
+            (_, Some(target)) => {
+                let defaults = probe::ExpiryThresholds::default();
+                let expiry = probe::ExpiryThresholds {
+                    warn: warn_days.unwrap_or(defaults.warn),
+                    critical: critical_days.unwrap_or(defaults.critical),
+                };
+                (
+                    Source::Check(
+                        probe::Prober::new(target.parse()?, timeout).expiry_thresholds(expiry),
+                    ),
+                    format!("check {target}"),
+                )
+            }
+            _ => {
+                let env = env
+                    .iter()
+                    .map(|pair| match pair.split_once('=') {
+                        Some((key, value)) if !key.is_empty() => {
+                            Ok((key.to_string(), value.to_string()))
+                        }
+                        _ => Err(Error::invalid(
+                            "--env",
+                            format!("expected KEY=VALUE, got '{pair}'"),
+                        )),
+                    })
+                    .collect::<Result<_>>()?;
+                let spec = ExecSpec {
+                    argv: cmd,
+                    shell: (!no_shell).then(|| shell.unwrap_or_else(Shell::platform)),
+                    cwd,
+                    env,
+                };
+                let desc = spec.to_string();
+                (Source::Command(spec), desc)
+            }
+        };
+
+        let transform = match &transform {
+            Some(path) => Some(transform::Transform::from_file(path)?),
+            None => None,
+        };
+        let mut plugin_transforms = Vec::new();
+        for path in &plugins {
+            let plugin = plugin::Plugin::load(path)?;
+            let transform = plugin.transform()?;
+            let sink = plugin.sink()?;
+            if transform.is_none() && sink.is_none() {
+                return Err(Error::invalid(
+                    "--plugin",
+                    format!(
+                        "plugin {} exports neither trep:plugin/transform nor trep:plugin/sink",
+                        path.display()
+                    ),
+                ));
+            }
+            plugin_transforms.extend(transform);
+            if let Some(sink) = sink {
+                sinks.push(Box::new(sink));
+            }
+        }
+
+        Ok(Self {
+            name,
+            source,
+            source_desc,
+            data_dir: None,
+            interval,
+            cron,
+            align,
+            single_shot,
+            format,
+            csv,
+            rotate_by,
+            retention: (!retention.is_empty()).then_some(retention),
+            timeout,
+            max_expected,
+            retry,
+            on_change,
+            heartbeat,
+            pipeline,
+            rejects,
+            transform,
+            plugin_transforms,
+            sinks,
+            recorder: None,
+            alerter,
+            exporter,
+        })
+    }
+
+    /// Run the job until it is done (after one run, without a schedule) or
+    /// `stop` is set. Fails with [`Error::Locked`] while another process
+    /// runs a job of the same name, except for single shots, which skip the
+    /// run instead.
+    pub fn run(mut self, stop: &Stop) -> Result<()> {
+        let _span = tracing::info_span!("run", job = %self.name).entered();
+        check_format(&self.format)?;
+
+        // Acquire global lock to prevent concurrent runs of same name
+        let data_dir = match &self.data_dir {
+            Some(dir) => dir.clone(),
+            None => storage::data_dir(&self.name),
+        };
+        std::fs::create_dir_all(&data_dir)?;
+        let lock_path = storage::lock_path_in(&data_dir, &self.name);
+        let lock = match storage::try_lock(&lock_path)? {
+            // `trep list` and `status` look at the lock by taking it shared
+            // for a moment; do not mistake that for another run.
+            None => {
+                thread::sleep(LOCK_RETRY);
+                storage::try_lock(&lock_path)?
+            }
+            lock => lock,
+        };
+        let _lock_file = match lock {
+            Some(file) => file,
+            // A cron tick must not pile up behind a run that is still going.
+            None if self.single_shot => {
+                tracing::warn!("previous run is still in progress; skipping");
+                return Ok(());
+            }
+            None => {
+                return Err(Error::Locked {
+                    job: self.name.clone(),
+                })
+            }
+        };
+
+        if let Err(e) = storage::record_job_dir(&self.name, &data_dir) {
+            tracing::warn!(error = %e, "could not record the job's data directory");
+        }
+
+        let mut writer = match self.recorder.take() {
+            Some(recorder) => recorder,
+            None => storage::open_store(&data_dir, &self.format, self.csv.clone(), false)?,
+        };
+        let mut rejects_writer =
+            storage::open_store(&data_dir, &self.format, self.csv.clone(), true)?;
+
+        // SIGUSR2 asks for the output files to be closed and reopened, so
+        // logrotate can move them away without losing records.
+        let reopen = Arc::new(AtomicBool::new(false));
+        #[cfg(unix)]
+        signal_hook::flag::register(signal_hook::consts::SIGUSR2, reopen.clone())?;
+
+        match &self.source {
+            Source::Command(command) => tracing::info!(command = %command, "job started"),
+            _ => tracing::info!(source = %self.source_desc, "job started"),
+        }
+        // Let `trep top`, `list` and `status` see what this job runs, how
+        // its last run went and when it runs next.
+        let meta_file = MetaFile(storage::meta_path_in(&data_dir, &self.name));
+        let mut meta = storage::JobMeta {
+            pid: std::process::id(),
+            source: self.source_desc.clone(),
+            interval_secs: self.interval.map(|d| d.as_secs_f64()),
+            cron: self.cron.clone(),
+            started_at: Some(Local::now().to_rfc3339()),
+            ..storage::JobMeta::default()
+        };
+        meta_file.publish(&meta);
+
+        let mut changes = self
+            .on_change
+            .then(|| OnChange::load(storage::last_path_in(&data_dir, &self.name), self.heartbeat));
+
+        // The day file to write to; it follows the wall clock.
+        let mut day = schedule::DayTracker::new(Local::now());
+        // The day the retention policy was last applied on.
+        let mut retained = None;
+        let mut schedule = match (&self.cron, self.interval) {
+            (Some(expr), _) => Some(Schedule::Wall(
+                WallSchedule::cron(expr, Local::now()).map_err(|e| Error::invalid("--cron", e))?,
+            )),
+            (None, Some(dur)) if self.align => Some(Schedule::Wall(
+                WallSchedule::aligned(dur, Local::now())
+                    .map_err(|e| Error::invalid("--every", e))?,
+            )),
+            (None, Some(dur)) => Some(Schedule::Every(IntervalSchedule::new(Instant::now(), dur))),
+            (None, None) => None,
+        };
+        // When the current tick should have started, to measure scheduling drift.
+        let mut due: Option<Instant> = None;
+        let mut ticked = false;
+        loop {
+            if let Some(schedule) = schedule
+                .as_mut()
+                .filter(|s| ticked || s.waits_for_first_run())
+            {
+                if ticked {
+                    let skipped = schedule.advance(Instant::now(), Local::now());
+                    if skipped > 0 {
+                        tracing::warn!(skipped, "run overran the schedule; skipping missed runs");
+                    }
+                }
+                let now = Instant::now();
+                let wait = schedule.until_next(now, Local::now());
+                due = Some(now + wait);
+                meta.next_run = chrono::Duration::from_std(wait)
+                    .ok()
+                    .map(|wait| (Local::now() + wait).to_rfc3339());
+                meta_file.publish(&meta);
+                wait_for_run(
+                    schedule,
+                    stop,
+                    &mut day,
+                    [writer.as_mut(), rejects_writer.as_mut()],
+                );
+            }
+            if stop.is_stopped() {
+                break;
+            }
+            if let Some(policy) = &self.retention {
+                if retained.replace(day.current()) != Some(day.current()) {
+                    // Close the finished day's files before they are moved.
+                    writer.reopen();
+                    rejects_writer.reopen();
+                    policy.enforce(&data_dir, day.current());
+                }
+            }
+            ticked = true;
+            let started = Instant::now();
+            // Each tick is its own trace, so exporters see it as soon as it ends.
+            let tick = tracing::info_span!(
+                parent: None,
+                "tick",
+                job = %self.name,
+                drift_ms = tracing::field::Empty
+            );
+            if let Some(due) = due {
+                let drift = started.saturating_duration_since(due);
+                tick.record("drift_ms", drift.as_secs_f64() * 1000.0);
+            }
+            {
+                let _tick = tick.entered();
+                // Take a sample and write a record
+                let now = Local::now();
+                let mut attempts = 1;
+                let (sample, took) = loop {
+                    let exec_started = Instant::now();
+                    let sample = self.source.sample(now.to_rfc3339(), self.timeout);
+                    let took = exec_started.elapsed();
+                    let exit_code = sample.record.exit_code;
+                    match self.retry {
+                        Some(retry) if exit_code != 0 && attempts <= retry.retries => {
+                            let wait = retry.wait(attempts);
+                            tracing::info!(attempt = attempts, exit_code, wait = ?wait, "run failed; retrying");
+                            sleep_with_interrupt(stop, wait);
+                            if stop.is_stopped() {
+                                break (sample, took);
+                            }
+                            attempts += 1;
+                        }
+                        _ => break (sample, took),
+                    }
+                };
+                let Sample {
+                    mut record,
+                    stderr,
+                    timed_out,
+                } = sample;
+                let date = match self.rotate_by {
+                    RotateBy::Start => day.update(now),
+                    RotateBy::Write => day.update(Local::now()),
+                };
+                meta.last_duration_secs = Some(took.as_secs_f64());
+                if let Some(max) = self.max_expected {
+                    flag_slow(&mut record, took, max);
+                }
+                if self.retry.is_some() {
+                    note_attempts(&mut record, attempts);
+                }
+                if record.exit_code != 0 {
+                    tracing::info!(exit_code = record.exit_code, value = %record.value, "command failed");
+                }
+                if reopen.swap(false, Ordering::SeqCst) {
+                    writer.reopen();
+                    rejects_writer.reopen();
+                }
+                let valid =
+                    tracing::info_span!("process").in_scope(|| self.pipeline.process(&mut record));
+                if let Some(alerter) = &mut self.alerter {
+                    alerter.check(&record, timed_out);
+                }
+                meta.last_run = Some(record.timestamp.clone());
+                meta.last_exit_code = Some(record.exit_code);
+                if let Some(exporter) = &mut self.exporter {
+                    exporter.update(&record, &meta);
+                }
+                if changes.as_ref().is_some_and(|c| !c.changed(&record)) {
+                    tracing::debug!(value = %record.value, "unchanged; not stored");
+                } else if !valid && self.rejects {
+                    rejects_writer.write(date, &record)?;
+                } else {
+                    // Compare later samples with this one as it is now,
+                    // before transforms, but only once it is stored.
+                    let compared = changes.is_some().then(|| record.clone());
+                    let stored = process_and_store(
+                        writer.as_mut(),
+                        date,
+                        record,
+                        &stderr,
+                        self.transform.as_ref(),
+                        &mut self.plugin_transforms,
+                        &mut self.sinks,
+                    )?;
+                    if let (Some(changes), Some(record), true) = (&mut changes, &compared, stored) {
+                        changes.commit(record);
+                    }
+                }
+            }
+
+            if schedule.is_none() {
+                break; // run once
+            }
+        }
+        if let Some(reason) = stop.reason() {
+            // Mark the end of collection in the data, so it does not look like
+            // the job silently died.
+            let marker = Record::new(
+                Local::now().to_rfc3339(),
+                format!("{STOPPED_MARKER}: {reason}"),
+                STOPPED_EXIT_CODE,
+            );
+            writer.write_marker(day.update(Local::now()), marker.clone())?;
+            for sink in self.sinks.iter_mut() {
+                if let Err(e) = sink.send(&marker) {
+                    tracing::warn!(sink = sink.name(), error = %e, "sink failed");
+                }
+            }
+            tracing::info!(reason, "job stopped");
+            return Ok(());
+        }
+        tracing::info!("job stopped");
+        Ok(())
+    }
+}
+
+/// How a failed run is retried before its record is stored.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Retry {
+    /// Attempts after the first.
+    pub retries: u32,
+    /// Wait before the first retry.
+    pub delay: Duration,
+    /// Factor the wait grows by with each further retry.
+    pub backoff: f64,
+}
+
+impl Retry {
+    /// The wait after failed attempt `attempt` (1 for the first).
+    fn wait(&self, attempt: u32) -> Duration {
+        let factor = self.backoff.powi(attempt.saturating_sub(1) as i32);
+        Duration::try_from_secs_f64(self.delay.as_secs_f64() * factor).unwrap_or(Duration::MAX)
+    }
+}
+
+/// Runs several jobs at once, each on its own thread, until they are all
+/// done or stopped.
+#[derive(Default)]
+pub struct Scheduler {
+    jobs: Vec<Job>,
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, job: Job) -> &mut Self {
+        self.jobs.push(job);
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.jobs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.jobs.is_empty()
+    }
+
+    /// Run the jobs until they are done or `stop` is set, and return each
+    /// job's name and outcome in the order they were added. A job that
+    /// fails does not stop the others.
+    pub fn run(self, stop: &Stop) -> Vec<(String, Result<()>)> {
+        thread::scope(|scope| {
+            let handles: Vec<_> = self
+                .jobs
+                .into_iter()
+                .map(|job| {
+                    let name = job.name.clone();
+                    (name, scope.spawn(move || job.run(stop)))
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|(name, handle)| {
+                    let result = handle
+                        .join()
+                        .unwrap_or_else(|_| Err(io::Error::other("job panicked").into()));
+                    (name, result)
+                })
+                .collect()
+        })
+    }
+}
+
+/// Asks running jobs to stop once the current run is stored. Clones share
+/// the flag, so one `Stop` can stop every job it was given to.
+#[derive(Debug, Clone, Default)]
+pub struct Stop(Arc<AtomicUsize>);
+
+impl Stop {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A stop flag that Ctrl-C and SIGTERM set. Install it once per process.
+    pub fn on_signals() -> Result<Self> {
+        let stop = Self::new();
+        {
+            let flag = stop.0.clone();
+            ctrlc::set_handler(move || {
+                flag.store(INTERRUPTED, Ordering::SeqCst);
+            })
+            .map_err(io::Error::other)?;
+        }
+        #[cfg(unix)]
+        signal_hook::flag::register_usize(
+            signal_hook::consts::SIGTERM,
+            stop.0.clone(),
+            TERMINATED,
+        )?;
+        Ok(stop)
+    }
+
+    /// Ask the jobs to stop.
+    pub fn stop(&self) {
+        self.0.store(REQUESTED, Ordering::SeqCst);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.0.load(Ordering::SeqCst) != RUNNING
+    }
+
+    /// Why the jobs were asked to stop, for the marker record.
+    fn reason(&self) -> Option<&'static str> {
+        match self.0.load(Ordering::SeqCst) {
+            INTERRUPTED => Some("interrupted"),
+            TERMINATED => Some("terminated"),
+            REQUESTED => Some("stopped"),
+            _ => None,
+        }
+    }
+}
+
+/// A running job's metadata file, removed when the job stops for any reason.
+struct MetaFile(PathBuf);
+
+impl MetaFile {
+    /// Write the metadata. It is informational, so a failure only warns.
+    fn publish(&self, meta: &storage::JobMeta) {
+        if let Err(e) = storage::write_job_meta(&self.0, meta) {
+            tracing::warn!(error = %e, path = %dunce::simplified(&self.0).display(), "could not write job metadata");
+        }
+    }
+}
+
+impl Drop for MetaFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// Where each tick's record comes from.
+enum Source {
+    Command(ExecSpec),
+    Collector(Box<collector::CollectorState>),
+    Check(probe::Prober),
+}
+
+/// One tick's sample, before processing.
+struct Sample {
+    record: Record,
+    /// The command's stderr, if any.
+    stderr: String,
+    /// Whether the command was killed by the timeout.
+    timed_out: bool,
+}
+
+impl Source {
+    /// Take one sample. Failures to sample at all become records with exit
+    /// code -1.
+    fn sample(&mut self, timestamp: String, timeout: Option<Duration>) -> Sample {
+        let with_fields = |value, exit_code, fields| {
+            let mut record = Record::new(timestamp.clone(), value, exit_code);
+            record.fields = fields;
+            (record, String::new())
+        };
+        let result = match self {
+            Source::Command(spec) => exec::run_command(spec, timeout).map(|out| {
+                (
+                    Record::new(timestamp.clone(), out.stdout, out.exit_code),
+                    out.stderr,
+                )
+            }),
+            Source::Collector(c) => tracing::info_span!("collect")
+                .in_scope(|| c.collect())
+                .map(|(value, fields)| with_fields(value, 0, fields))
+                .map_err(Error::from),
+            Source::Check(p) => tracing::info_span!("check")
+                .in_scope(|| p.check())
+                .map(|r| with_fields(r.value, r.exit_code, r.fields))
+                .map_err(Error::from),
+        };
+        match result {
+            Ok((record, stderr)) => Sample {
+                record,
+                stderr,
+                timed_out: false,
+            },
+            Err(e) => Sample {
+                record: Record::new(timestamp.clone(), format!("error: {e}"), -1),
+                stderr: String::new(),
+                timed_out: matches!(e, Error::Timeout { .. }),
+            },
+        }
+    }
+}
+
+/// Record how many attempts the run took and whether the last one
+/// succeeded. Both fields are always set so CSV columns stay aligned.
+fn note_attempts(record: &mut Record, attempts: u32) {
+    let succeeded = record.exit_code == 0;
+    record.fields.insert("attempts".into(), attempts.into());
+    record.fields.insert("succeeded".into(), succeeded.into());
+}
+
+/// Fail unless `format` is a storage format a job can write.
+fn check_format(format: &str) -> Result<()> {
+    if ["csv", "jsonl", "sqlite"].contains(&format) {
+        Ok(())
+    } else {
+        Err(Error::invalid(
+            "--format",
+            "must be 'csv', 'jsonl' or 'sqlite'",
+        ))
+    }
+}
+
+/// Parse a backoff factor such as "2x" or "1.5".
+fn parse_backoff(s: &str) -> std::result::Result<f64, String> {
+    let factor = s.strip_suffix(['x', 'X']).unwrap_or(s);
+    match factor.trim().parse::<f64>() {
+        Ok(n) if n.is_finite() && n >= 1.0 => Ok(n),
+        _ => Err(format!(
+            "invalid factor '{s}': expected a number of at least 1, such as 2x"
+        )),
+    }
+}
+
+/// Record how long the run took and whether it exceeded `--max-expected`.
+/// Both fields are always set so CSV columns stay aligned.
+fn flag_slow(record: &mut Record, took: Duration, max: Duration) {
+    let slow = took > max;
+    let duration_ms = took.as_millis() as u64;
+    record
+        .fields
+        .insert("duration_ms".into(), duration_ms.into());
+    record.fields.insert("slow".into(), slow.into());
+    if slow {
+        tracing::warn!(
+            duration_ms,
+            max_expected_ms = max.as_millis() as u64,
+            "run took longer than --max-expected"
+        );
+    }
+}
+
+/// Apply user transforms to a record, then store it and hand it to the sinks.
+/// Returns whether it was stored, as a transform may drop it.
+fn process_and_store(
+    writer: &mut dyn RecordStore,
+    date: NaiveDate,
+    record: Record,
+    stderr: &str,
+    transform: Option<&transform::Transform>,
+    plugin_transforms: &mut [plugin::PluginTransform],
+    sinks: &mut [Box<dyn Sink>],
+) -> io::Result<bool> {
+    let record = match transform {
+        Some(t) => {
+            match tracing::info_span!("transform").in_scope(|| t.apply(record.clone(), stderr)) {
+                Ok(transformed) => transformed,
+                Err(e) => {
+                    tracing::warn!(error = %e, "transform failed; storing the record unchanged");
+                    Some(record)
+                }
+            }
+        }
+        None => Some(record),
+    };
+    let record = apply_plugins(plugin_transforms, record, stderr);
+    if let Some(record) = &record {
+        writer.write(date, record)?;
+        for sink in sinks.iter_mut() {
+            let span = tracing::info_span!("sink", sink = sink.name());
+            if let Err(e) = span.in_scope(|| sink.send(record)) {
+                tracing::warn!(sink = sink.name(), error = %e, "sink failed");
+            }
+        }
+    }
+    Ok(record.is_some())
+}
+
+/// Run the record through each plugin transform in order, stopping if one drops it.
+fn apply_plugins(
+    transforms: &mut [plugin::PluginTransform],
+    mut record: Option<Record>,
+    stderr: &str,
+) -> Option<Record> {
+    for t in transforms.iter_mut() {
+        let current = record?;
+        record = match tracing::info_span!("plugin").in_scope(|| t.apply(current.clone(), stderr)) {
+            Ok(next) => next,
+            Err(e) => {
+                tracing::warn!(error = %e, "plugin transform failed; passing the record through unchanged");
+                Some(current)
+            }
+        };
+    }
+    record
+}
+
+/// How often a sleeping job re-reads the wall clock while waiting for
+/// midnight, so setting the clock forward does not delay the rotation.
+const ROTATION_RECHECK: Duration = Duration::from_secs(60);
+
+/// How long a job waits before taking its lock a second time.
+const LOCK_RETRY: Duration = Duration::from_millis(100);
+
+/// Wait before the first retry when `--retry-delay` is not given.
+const DEFAULT_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Values of the stop flag: still running, or why the job was asked to stop.
+const RUNNING: usize = 0;
+const INTERRUPTED: usize = 1;
+const TERMINATED: usize = 2;
+const REQUESTED: usize = 3;
+
+/// Sleep until `schedule`'s next run is due or the job is stopped, waking at
+/// midnight too to close the finished day's files.
+fn wait_for_run(
+    schedule: &Schedule,
+    stop: &Stop,
+    day: &mut schedule::DayTracker,
+    mut writers: [&mut dyn RecordStore; 2],
+) {
+    while !stop.is_stopped() {
+        let left = schedule.until_next(Instant::now(), Local::now());
+        if left.is_zero() {
+            break;
+        }
+        let to_midnight = day.until_next_day(Local::now());
+        sleep_with_interrupt(stop, left.min(to_midnight).min(ROTATION_RECHECK));
+        let previous = day.current();
+        if day.update(Local::now()) != previous {
+            for writer in writers.iter_mut() {
+                writer.reopen();
+            }
+            tracing::info!(date = %day.current(), "rotated to a new day file");
+        }
+    }
+}
+
+fn sleep_with_interrupt(stop: &Stop, dur: Duration) {
+    let start = Instant::now();
+    while !stop.is_stopped() {
+        let elapsed = Instant::now().duration_since(start);
+        if elapsed >= dur {
+            break;
+        }
+        let remaining = dur - elapsed;
+        let chunk = remaining.min(Duration::from_millis(100));
+        thread::sleep(chunk);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[test]
+    fn flags_runs_slower_than_expected() {
+        let max = Duration::from_secs(2);
+        let mut record = Record::new("t", "1", 0);
+        flag_slow(&mut record, Duration::from_millis(1500), max);
+        assert_eq!(record.fields["duration_ms"], 1500);
+        assert_eq!(record.fields["slow"], false);
+        flag_slow(&mut record, Duration::from_millis(2001), max);
+        assert_eq!(record.fields["duration_ms"], 2001);
+        assert_eq!(record.fields["slow"], true);
+    }
+
+    #[test]
+    fn backs_off_between_retries() {
+        let retry = Retry {
+            retries: 3,
+            delay: Duration::from_secs(5),
+            backoff: parse_backoff("2x").unwrap(),
+        };
+        let waits: Vec<_> = (1..=3).map(|n| retry.wait(n).as_secs()).collect();
+        assert_eq!(waits, [5, 10, 20]);
+        assert_eq!(parse_backoff("1.5").unwrap(), 1.5);
+        assert!(parse_backoff("0.5x").is_err());
+        assert!(parse_backoff("fast").is_err());
+
+        let mut record = Record::new("t", "1", 0);
+        note_attempts(&mut record, 3);
+        assert_eq!(record.fields["attempts"], 3);
+        assert_eq!(record.fields["succeeded"], true);
+    }
+
+    #[test]
+    fn rejects_unknown_formats_and_durations() {
+        let err = Job::command("x", "true")
+            .format("xml")
+            .run(&Stop::new())
+            .unwrap_err();
+        assert!(
+            matches!(
+                err,
+                Error::InvalidOption {
+                    option: "--format",
+                    ..
+                }
+            ),
+            "{err}"
+        );
+
+        let opts = |args: &[&str]| {
+            let mut argv = vec!["run", "--as", "x"];
+            argv.extend(args);
+            argv.extend(["--", "true"]);
+            RunOpts::try_parse_from(argv).unwrap()
+        };
+        let err = Job::from_opts(opts(&["--every", "soon"])).err().unwrap();
+        assert!(
+            err.to_string()
+                .starts_with("--every: invalid interval 'soon'"),
+            "{err}"
+        );
+        let err = Job::from_opts(opts(&["--bom", "--format", "jsonl"]))
+            .err()
+            .unwrap();
+        assert!(matches!(err, Error::InvalidOption { .. }), "{err}");
+        let err = Job::from_opts(opts(&["--env", "=x"])).err().unwrap();
+        assert_eq!(err.to_string(), "--env: expected KEY=VALUE, got '=x'");
+    }
+}
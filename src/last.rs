@@ -0,0 +1,108 @@
+use std::io;
+
+use crate::cli::LastOpts;
+use crate::storage::{self, DataFile, Record};
+
+pub fn last(opts: LastOpts) -> io::Result<()> {
+    let files = storage::job_data_files(&opts.name)?;
+    let records = last_records(&files, opts.count)?;
+    if records.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("job '{}' has no records yet", opts.name),
+        ));
+    }
+    for record in &records {
+        match &opts.field {
+            Some(field) => println!("{}", field_value(record, field)?),
+            None => println!("{}", serde_json::to_string(record)?),
+        }
+    }
+    Ok(())
+}
+
+/// The newest `count` records in `files`, oldest first, skipping stop
+/// markers. Only the newest files are read.
+fn last_records(files: &[DataFile], count: usize) -> io::Result<Vec<Record>> {
+    let mut records = Vec::new();
+    for file in files.iter().rev() {
+        let mut older = storage::read_records(&file.path)?;
+        older.retain(|r| !r.is_marker());
+        older.append(&mut records);
+        records = older;
+        if records.len() >= count {
+            break;
+        }
+    }
+    let skip = records.len().saturating_sub(count);
+    Ok(records.split_off(skip))
+}
+
+/// A record's field as plain text: `timestamp`, `value`, `exit_code` or an
+/// extra field (empty when null).
+fn field_value(record: &Record, field: &str) -> io::Result<String> {
+    match field {
+        "timestamp" => Ok(record.timestamp.clone()),
+        "value" => Ok(record.value.clone()),
+        "exit_code" => Ok(record.exit_code.to_string()),
+        _ => match record.fields.get(field) {
+            Some(value) => Ok(storage::field_to_string(value)),
+            None => {
+                let mut known = vec!["timestamp", "value", "exit_code"];
+                known.extend(record.fields.keys().map(String::as_str));
+                Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "record at {} has no field '{field}' (fields: {})",
+                        record.timestamp,
+                        known.join(", ")
+                    ),
+                ))
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    #[test]
+    fn reads_only_the_newest_files() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("2025-01-01.csv"), "broken,\"\n").unwrap();
+        std::fs::write(dir.path().join("2025-01-02.csv"), "a,1,0\nb,2,0\n").unwrap();
+        std::fs::write(dir.path().join("2025-01-03.jsonl"), "").unwrap();
+        std::fs::write(
+            dir.path().join("2025-01-04.jsonl"),
+            "{\"timestamp\":\"c\",\"value\":\"3\",\"exit_code\":1}\n",
+        )
+        .unwrap();
+        let files = storage::list_data_files(dir.path()).unwrap();
+        let values = |count| -> Vec<String> {
+            let records = last_records(&files, count).unwrap();
+            records.into_iter().map(|r| r.value).collect()
+        };
+        assert_eq!(values(1), ["3"]);
+        assert_eq!(values(2), ["2", "3"]);
+        assert_eq!(values(3), ["1", "2", "3"]);
+    }
+
+    #[test]
+    fn prints_fields_as_plain_text() {
+        let mut record = Record::new("2025-01-01T00:00:00Z", "91", 0);
+        record.fields.insert("unit".into(), "%".into());
+        record.fields.insert("delta".into(), Value::Null);
+        record.fields.insert("ok".into(), true.into());
+        assert_eq!(field_value(&record, "value").unwrap(), "91");
+        assert_eq!(field_value(&record, "exit_code").unwrap(), "0");
+        assert_eq!(field_value(&record, "unit").unwrap(), "%");
+        assert_eq!(field_value(&record, "delta").unwrap(), "");
+        assert_eq!(field_value(&record, "ok").unwrap(), "true");
+        let err = field_value(&record, "nope").unwrap_err();
+        assert!(err
+            .to_string()
+            .ends_with("(fields: timestamp, value, exit_code, unit, delta, ok)"));
+    }
+}
@@ -0,0 +1,71 @@
+//! tiny-reporter: run a command (or a collector or check) on a schedule and
+//! keep every result as a record, in day files or a SQLite database.
+//!
+//! The `trep` binary is a thin command line over this library. To run jobs
+//! from another program, build a [`Job`], optionally with extra [`Sink`]s
+//! or a custom [`RecordStore`], and run it until a [`Stop`] is set; a
+//! [`Scheduler`] runs several jobs at once.
+//!
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! let stop = trep::Stop::new();
+//! let job = trep::Job::command("load", "cat /proc/loadavg")
+//!     .every(Duration::from_secs(60))
+//!     .timeout(Duration::from_secs(5))
+//!     .data_dir("/var/lib/load");
+//! job.run(&stop)?;
+//! # Ok::<(), trep::Error>(())
+//! ```
+
+pub mod alert;
+mod app;
+mod change;
+mod check;
+mod cli;
+mod collector;
+mod config;
+mod coverage;
+pub mod error;
+pub mod exec;
+mod export;
+pub mod exporter;
+mod extract;
+mod gaps;
+mod install;
+pub mod job;
+mod last;
+mod logging;
+mod metrics;
+mod overhead;
+mod parquet;
+mod pipeline;
+mod plugin;
+mod probe;
+mod prune;
+mod query;
+mod repair;
+mod report;
+pub mod retention;
+mod schedule;
+mod serve;
+pub mod sink;
+mod sla;
+mod sqlite;
+mod start;
+mod status;
+pub mod storage;
+mod template;
+mod top;
+mod trace;
+mod transform;
+mod units;
+mod util;
+mod validate;
+
+#[doc(hidden)]
+pub use app::main as cli_main;
+pub use error::{Error, Result};
+pub use job::{Job, Scheduler, Stop};
+pub use sink::Sink;
+pub use storage::{Record, RecordStore};
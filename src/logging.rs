@@ -0,0 +1,51 @@
+use std::io::{self, IsTerminal};
+
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{Layer, Registry};
+
+use crate::cli::LogTarget;
+use crate::trace;
+
+/// Install the global subscriber for trep's operational events.
+///
+/// On stderr only warnings are shown, so an interactive `trep run` stays
+/// quiet. journald also receives informational events (job start and stop,
+/// failed runs) with their fields as journal fields, e.g. `JOB=` and
+/// `EXIT_CODE=`.
+///
+/// With `trace` set (`--trace`), spans are also exported; see [`trace::layer`].
+/// Spans still on their way to a collector are sent when the returned guard
+/// is dropped.
+pub fn init(target: LogTarget, trace: Option<&str>) -> io::Result<LogGuard> {
+    let log: Box<dyn Layer<Registry> + Send + Sync> = match target {
+        LogTarget::Stderr => tracing_subscriber::fmt::layer()
+            .with_writer(io::stderr)
+            .with_ansi(io::stderr().is_terminal())
+            .without_time()
+            .with_target(false)
+            .with_filter(LevelFilter::WARN)
+            .boxed(),
+        LogTarget::Journald => tracing_journald::layer()
+            .map_err(|e| io::Error::new(e.kind(), format!("cannot log to journald: {e}")))?
+            .with_field_prefix(None)
+            .with_filter(LevelFilter::INFO)
+            .boxed(),
+    };
+    let (trace, guard) = match trace {
+        Some(target) => {
+            let (layer, guard) = trace::layer(target)?;
+            (Some(layer.with_filter(LevelFilter::DEBUG)), Some(guard))
+        }
+        None => (None, None),
+    };
+    tracing_subscriber::registry().with(log).with(trace).init();
+    Ok(LogGuard { _trace: guard })
+}
+
+/// Returned by [`init`]; keep it until trep is done.
+#[must_use]
+pub struct LogGuard {
+    _trace: Option<trace::TraceGuard>,
+}
@@ -0,0 +1,139 @@
+use std::fmt::Write;
+
+use chrono::DateTime;
+
+use crate::storage::JobMeta;
+use crate::top::JobSummary;
+
+/// Content type of [`render`]'s output.
+pub const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// What is known about one job when metrics are scraped.
+pub struct JobState {
+    pub name: String,
+    pub summary: JobSummary,
+    pub meta: Option<JobMeta>,
+}
+
+/// A metric family: name, unit (if the name ends in one) and help text.
+type Family = (&'static str, Option<&'static str>, &'static str);
+/// Reads one family's value for a job, if it has one.
+type Sample = fn(&JobState) -> Option<f64>;
+
+const VALUE: Family = (
+    "trep_value",
+    None,
+    "Latest value of the job, when it is a number.",
+);
+const EXIT_CODE: Family = ("trep_exit_code", None, "Exit code of the latest run.");
+const CONSECUTIVE_FAILURES: Family = (
+    "trep_consecutive_failures",
+    None,
+    "Failed runs since the last successful one.",
+);
+const RUN_DURATION: Family = (
+    "trep_run_duration_seconds",
+    Some("seconds"),
+    "How long the latest run took.",
+);
+const LAST_RUN: Family = (
+    "trep_last_run_timestamp_seconds",
+    Some("seconds"),
+    "When the latest run started, as a Unix timestamp.",
+);
+
+/// The jobs' latest state in the OpenMetrics text format, one gauge family
+/// per measurement with a `job` label. Jobs without records are left out.
+/// The run duration comes from the running job's metadata, or from the
+/// `duration_ms` field that `--max-expected` adds to records.
+pub fn render(jobs: &[JobState]) -> String {
+    let mut out = String::new();
+    let families: [(Family, Sample); 5] = [
+        (VALUE, |job| {
+            let value = &job.summary.latest.as_ref()?.value;
+            value.trim().parse().ok().filter(|n: &f64| n.is_finite())
+        }),
+        (EXIT_CODE, |job| {
+            Some(f64::from(job.summary.latest.as_ref()?.exit_code))
+        }),
+        (CONSECUTIVE_FAILURES, |job| {
+            job.summary
+                .latest
+                .as_ref()
+                .map(|_| job.summary.consecutive_failures as f64)
+        }),
+        (RUN_DURATION, |job| {
+            let from_meta = job.meta.as_ref().and_then(|m| m.last_duration_secs);
+            from_meta.or_else(|| {
+                let latest = job.summary.latest.as_ref()?;
+                Some(latest.fields.get("duration_ms")?.as_f64()? / 1000.0)
+            })
+        }),
+        (LAST_RUN, |job| {
+            let latest = job.summary.latest.as_ref()?;
+            let at = DateTime::parse_from_rfc3339(&latest.timestamp).ok()?;
+            Some(at.timestamp_millis() as f64 / 1000.0)
+        }),
+    ];
+    for ((name, unit, help), sample) in families {
+        let _ = writeln!(out, "# TYPE {name} gauge");
+        if let Some(unit) = unit {
+            let _ = writeln!(out, "# UNIT {name} {unit}");
+        }
+        let _ = writeln!(out, "# HELP {name} {help}");
+        for job in jobs {
+            if let Some(value) = sample(job) {
+                let _ = writeln!(out, "{name}{{job=\"{}\"}} {value}", escape(&job.name));
+            }
+        }
+    }
+    out.push_str("# EOF\n");
+    out
+}
+
+/// Escape a label value: backslash, double quote and newline.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::Record;
+
+    #[test]
+    fn renders_openmetrics() {
+        let mut latest = Record::new("2025-01-01T00:00:10+00:00", "42.5", 1);
+        latest.fields.insert("duration_ms".into(), 250.into());
+        let jobs = [
+            JobState {
+                name: "cpu \"a\"".to_string(),
+                summary: JobSummary {
+                    latest: Some(latest),
+                    consecutive_failures: 2,
+                    ..JobSummary::default()
+                },
+                meta: None,
+            },
+            JobState {
+                name: "empty".to_string(),
+                summary: JobSummary::default(),
+                meta: None,
+            },
+        ];
+        let text = render(&jobs);
+        assert!(text.contains("trep_value{job=\"cpu \\\"a\\\"\"} 42.5\n"));
+        assert!(text.contains("trep_exit_code{job=\"cpu \\\"a\\\"\"} 1\n"));
+        assert!(text.contains("trep_consecutive_failures{job=\"cpu \\\"a\\\"\"} 2\n"));
+        assert!(text.contains("trep_run_duration_seconds{job=\"cpu \\\"a\\\"\"} 0.25\n"));
+        assert!(text.contains("# UNIT trep_run_duration_seconds seconds\n"));
+        assert!(
+            text.contains("trep_last_run_timestamp_seconds{job=\"cpu \\\"a\\\"\"} 1735689610\n")
+        );
+        assert!(!text.contains("empty"));
+        assert!(text.ends_with("# EOF\n"));
+    }
+}
@@ -0,0 +1,207 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::cli::OverheadOpts;
+
+/// One line of a `--trace <file>` span log.
+#[derive(Debug, Deserialize)]
+struct SpanLine {
+    name: String,
+    trace_id: String,
+    duration_ms: f64,
+    #[serde(default)]
+    fields: Map<String, Value>,
+}
+
+/// Where the time of one tick went, in milliseconds.
+#[derive(Debug, Default, Clone, PartialEq)]
+struct TickTimings {
+    total: f64,
+    /// The child process running, including shell startup.
+    child: f64,
+    spawn: f64,
+    capture: f64,
+    /// Built-in processing, transform scripts and plugins.
+    process: f64,
+    write: f64,
+    sinks: f64,
+    /// Scheduling and bookkeeping not covered by the other spans.
+    other: f64,
+}
+
+impl TickTimings {
+    fn trep(&self) -> f64 {
+        self.total - self.child
+    }
+}
+
+pub fn overhead(opts: OverheadOpts) -> io::Result<()> {
+    let contents = fs::read_to_string(&opts.from).map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!("cannot read {}: {e}", opts.from.display()),
+        )
+    })?;
+    let spans: Vec<SpanLine> = contents
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect();
+    let ticks = tick_timings(&spans, &opts.name);
+    if ticks.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!(
+                "no ticks of job '{}' in {}; record them with `trep --trace <file> run --as {} ...`",
+                opts.name,
+                opts.from.display(),
+                opts.name
+            ),
+        ));
+    }
+    print!("{}", render(&opts.name, &ticks));
+    Ok(())
+}
+
+/// Sum the span durations of each trace and break down every tick of `job`.
+fn tick_timings(spans: &[SpanLine], job: &str) -> Vec<TickTimings> {
+    let mut by_trace: HashMap<&str, HashMap<&str, f64>> = HashMap::new();
+    for span in spans {
+        *by_trace
+            .entry(&span.trace_id)
+            .or_default()
+            .entry(&span.name)
+            .or_default() += span.duration_ms;
+    }
+    spans
+        .iter()
+        .filter(|s| s.name == "tick" && s.fields.get("job").and_then(Value::as_str) == Some(job))
+        .map(|tick| {
+            let sums = &by_trace[tick.trace_id.as_str()];
+            let sum = |name: &str| sums.get(name).copied().unwrap_or(0.0);
+            let (spawn, capture) = (sum("spawn"), sum("capture"));
+            let child = if sums.contains_key("wait") {
+                sum("wait")
+            } else {
+                // Traces without a `wait` span: everything in exec but spawning.
+                (sum("exec") - spawn - capture).max(0.0)
+            };
+            let mut t = TickTimings {
+                total: tick.duration_ms,
+                child,
+                spawn,
+                capture,
+                process: sum("process") + sum("transform") + sum("plugin"),
+                write: sum("write"),
+                sinks: sum("sink"),
+                other: 0.0,
+            };
+            let accounted = t.spawn + t.capture + t.process + t.write + t.sinks;
+            t.other = (t.trep() - accounted).max(0.0);
+            t
+        })
+        .collect()
+}
+
+/// Mean, 95th percentile and maximum.
+fn summarize(mut values: Vec<f64>) -> (f64, f64, f64) {
+    values.sort_by(f64::total_cmp);
+    let n = values.len();
+    let mean = values.iter().sum::<f64>() / n as f64;
+    let p95 = values[((n as f64 * 0.95).ceil() as usize).clamp(1, n) - 1];
+    (mean, p95, values[n - 1])
+}
+
+type Column = fn(&TickTimings) -> f64;
+
+fn render(job: &str, ticks: &[TickTimings]) -> String {
+    let rows: [(&str, Column); 9] = [
+        ("tick", |t| t.total),
+        ("child", |t| t.child),
+        ("trep", TickTimings::trep),
+        ("  spawn", |t| t.spawn),
+        ("  capture", |t| t.capture),
+        ("  process", |t| t.process),
+        ("  write", |t| t.write),
+        ("  sinks", |t| t.sinks),
+        ("  other", |t| t.other),
+    ];
+    let mut out = format!("Overhead of '{job}' over {} ticks (ms)\n", ticks.len());
+    out.push_str(&format!(
+        "{:<10} {:>10} {:>10} {:>10}\n",
+        "", "mean", "p95", "max"
+    ));
+    for (label, get) in rows {
+        let (mean, p95, max) = summarize(ticks.iter().map(get).collect());
+        out.push_str(&format!(
+            "{label:<10} {mean:>10.3} {p95:>10.3} {max:>10.3}\n"
+        ));
+    }
+    let total: f64 = ticks.iter().map(|t| t.total).sum();
+    let trep: f64 = ticks.iter().map(TickTimings::trep).sum();
+    if total > 0.0 {
+        out.push_str(&format!(
+            "trep itself used {:.1}% of the tick time\n",
+            trep / total * 100.0
+        ));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(name: &str, trace: &str, ms: f64, fields: Value) -> SpanLine {
+        SpanLine {
+            name: name.to_string(),
+            trace_id: trace.to_string(),
+            duration_ms: ms,
+            fields: fields.as_object().cloned().unwrap_or_default(),
+        }
+    }
+
+    #[test]
+    fn breaks_down_tick_time() {
+        let none = Value::Null;
+        let spans = vec![
+            span("spawn", "a", 1.0, none.clone()),
+            span("wait", "a", 6.0, none.clone()),
+            span("capture", "a", 0.5, none.clone()),
+            span("exec", "a", 7.5, none.clone()),
+            span("process", "a", 0.25, none.clone()),
+            span("write", "a", 0.75, none.clone()),
+            span("sink", "a", 1.0, none.clone()),
+            span("sink", "a", 0.5, none.clone()),
+            span("tick", "a", 10.5, serde_json::json!({ "job": "cpu" })),
+            span("tick", "b", 99.0, serde_json::json!({ "job": "other" })),
+        ];
+        let ticks = tick_timings(&spans, "cpu");
+        assert_eq!(
+            ticks,
+            vec![TickTimings {
+                total: 10.5,
+                child: 6.0,
+                spawn: 1.0,
+                capture: 0.5,
+                process: 0.25,
+                write: 0.75,
+                sinks: 1.5,
+                other: 0.5,
+            }]
+        );
+        assert_eq!(ticks[0].trep(), 4.5);
+        let report = render("cpu", &ticks);
+        assert!(report.contains("trep itself used 42.9% of the tick time"));
+    }
+
+    #[test]
+    fn summarizes_mean_p95_and_max() {
+        let values: Vec<f64> = (1..=20).map(f64::from).collect();
+        assert_eq!(summarize(values), (10.5, 19.0, 20.0));
+        assert_eq!(summarize(vec![3.0]), (3.0, 3.0, 3.0));
+    }
+}
@@ -0,0 +1,369 @@
+use std::io;
+
+/// First and last bytes of every Parquet file.
+const MAGIC: &[u8] = b"PAR1";
+
+// Thrift compact protocol type ids.
+const BOOL_TRUE: u8 = 1;
+const BOOL_FALSE: u8 = 2;
+const I32: u8 = 5;
+const I64: u8 = 6;
+const BINARY: u8 = 8;
+const LIST: u8 = 9;
+const STRUCT: u8 = 12;
+
+// Parquet enum values, from parquet.thrift.
+const TYPE_BOOLEAN: i32 = 0;
+const TYPE_INT32: i32 = 1;
+const TYPE_INT64: i32 = 2;
+const TYPE_DOUBLE: i32 = 5;
+const TYPE_BYTE_ARRAY: i32 = 6;
+const REQUIRED: i32 = 0;
+const OPTIONAL: i32 = 1;
+const CONVERTED_UTF8: i32 = 0;
+const CONVERTED_TIMESTAMP_MICROS: i32 = 10;
+const ENCODING_PLAIN: i32 = 0;
+const ENCODING_RLE: i32 = 3;
+const CODEC_UNCOMPRESSED: i32 = 0;
+const PAGE_DATA: i32 = 0;
+
+/// The non-null values of a column, which also decide its type.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Values {
+    Boolean(Vec<bool>),
+    Int32(Vec<i32>),
+    Int64(Vec<i64>),
+    Double(Vec<f64>),
+    /// Microseconds since the Unix epoch, as a UTC timestamp.
+    Timestamp(Vec<i64>),
+    String(Vec<String>),
+}
+
+impl Values {
+    fn physical_type(&self) -> i32 {
+        match self {
+            Values::Boolean(_) => TYPE_BOOLEAN,
+            Values::Int32(_) => TYPE_INT32,
+            Values::Int64(_) | Values::Timestamp(_) => TYPE_INT64,
+            Values::Double(_) => TYPE_DOUBLE,
+            Values::String(_) => TYPE_BYTE_ARRAY,
+        }
+    }
+
+    /// PLAIN encoding: little-endian numbers, bit-packed booleans and
+    /// length-prefixed strings.
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Values::Boolean(values) => {
+                let mut bytes = vec![0u8; values.len().div_ceil(8)];
+                for (i, _) in values.iter().enumerate().filter(|(_, v)| **v) {
+                    bytes[i / 8] |= 1 << (i % 8);
+                }
+                out.extend(bytes);
+            }
+            Values::Int32(values) => values.iter().for_each(|v| out.extend(v.to_le_bytes())),
+            Values::Int64(values) | Values::Timestamp(values) => {
+                values.iter().for_each(|v| out.extend(v.to_le_bytes()))
+            }
+            Values::Double(values) => values.iter().for_each(|v| out.extend(v.to_le_bytes())),
+            Values::String(values) => {
+                for v in values {
+                    out.extend((v.len() as u32).to_le_bytes());
+                    out.extend(v.as_bytes());
+                }
+            }
+        }
+    }
+}
+
+/// One column of the file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Column {
+    pub name: String,
+    /// Which rows have a value, for a column that may be null; `None` for
+    /// one where every row has a value.
+    pub defined: Option<Vec<bool>>,
+    pub values: Values,
+}
+
+/// Encode `rows` rows of `columns` as a Parquet file: one row group, one
+/// uncompressed, PLAIN-encoded data page per column, without statistics.
+/// That is all the spec requires, so any reader takes it.
+pub fn encode(rows: usize, columns: &[Column]) -> io::Result<Vec<u8>> {
+    let count = |n: usize| {
+        i32::try_from(n).map_err(|_| io::Error::other("too many rows for one Parquet page"))
+    };
+    let mut file = MAGIC.to_vec();
+    // (offset, size) of each column chunk.
+    let mut chunks = Vec::new();
+    for column in columns {
+        let mut page = Vec::new();
+        if let Some(defined) = &column.defined {
+            let levels = definition_levels(defined);
+            page.extend((levels.len() as u32).to_le_bytes());
+            page.extend(levels);
+        }
+        column.values.encode(&mut page);
+
+        let mut header = Thrift::default();
+        header.i32(1, PAGE_DATA);
+        header.i32(2, count(page.len())?);
+        header.i32(3, count(page.len())?);
+        header.begin(5);
+        header.i32(1, count(rows)?);
+        header.i32(2, ENCODING_PLAIN);
+        header.i32(3, ENCODING_RLE);
+        header.i32(4, ENCODING_RLE);
+        header.end();
+        let header = header.finish();
+
+        chunks.push((file.len() as i64, (header.len() + page.len()) as i64));
+        file.extend(header);
+        file.extend(page);
+    }
+
+    let mut meta = Thrift::default();
+    meta.i32(1, 1);
+    meta.list(2, STRUCT, columns.len() + 1);
+    meta.element();
+    meta.string(4, "schema");
+    meta.i32(5, count(columns.len())?);
+    meta.end();
+    for column in columns {
+        meta.element();
+        meta.i32(1, column.values.physical_type());
+        meta.i32(
+            3,
+            if column.defined.is_some() {
+                OPTIONAL
+            } else {
+                REQUIRED
+            },
+        );
+        meta.string(4, &column.name);
+        match column.values {
+            Values::String(_) => {
+                meta.i32(6, CONVERTED_UTF8);
+                // LogicalType STRING.
+                meta.begin(10);
+                meta.begin(1);
+                meta.end();
+                meta.end();
+            }
+            Values::Timestamp(_) => {
+                meta.i32(6, CONVERTED_TIMESTAMP_MICROS);
+                // LogicalType TIMESTAMP(isAdjustedToUTC, MICROS).
+                meta.begin(10);
+                meta.begin(8);
+                meta.bool(1, true);
+                meta.begin(2);
+                meta.begin(2);
+                meta.end();
+                meta.end();
+                meta.end();
+                meta.end();
+            }
+            _ => {}
+        }
+        meta.end();
+    }
+    meta.i64(3, rows as i64);
+    meta.list(4, STRUCT, 1);
+    meta.element();
+    meta.list(1, STRUCT, columns.len());
+    for (column, &(offset, size)) in columns.iter().zip(&chunks) {
+        meta.element();
+        meta.i64(2, offset);
+        meta.begin(3);
+        meta.i32(1, column.values.physical_type());
+        meta.list(2, I32, 2);
+        meta.list_i32(ENCODING_PLAIN);
+        meta.list_i32(ENCODING_RLE);
+        meta.list(3, BINARY, 1);
+        meta.list_string(&column.name);
+        meta.i32(4, CODEC_UNCOMPRESSED);
+        meta.i64(5, rows as i64);
+        meta.i64(6, size);
+        meta.i64(7, size);
+        meta.i64(9, offset);
+        meta.end();
+        meta.end();
+    }
+    meta.i64(2, chunks.iter().map(|(_, size)| size).sum());
+    meta.i64(3, rows as i64);
+    meta.end();
+    meta.string(6, concat!("trep version ", env!("CARGO_PKG_VERSION")));
+    let meta = meta.finish();
+
+    let footer = meta.len() as u32;
+    file.extend(meta);
+    file.extend(footer.to_le_bytes());
+    file.extend(MAGIC);
+    Ok(file)
+}
+
+/// Definition levels (1 for a value, 0 for null) in the RLE/bit-packing
+/// hybrid encoding, as runs of equal levels.
+fn definition_levels(defined: &[bool]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut rest = defined;
+    while let Some(&level) = rest.first() {
+        let run = rest.iter().take_while(|&&d| d == level).count();
+        varint(&mut out, (run as u64) << 1);
+        out.push(u8::from(level));
+        rest = &rest[run..];
+    }
+    out
+}
+
+fn varint(out: &mut Vec<u8>, mut v: u64) {
+    while v >= 0x80 {
+        out.push(v as u8 | 0x80);
+        v >>= 7;
+    }
+    out.push(v as u8);
+}
+
+fn zigzag(v: i64) -> u64 {
+    ((v << 1) ^ (v >> 63)) as u64
+}
+
+/// A Thrift struct being written with the compact protocol, which is what
+/// Parquet's page headers and footer are.
+struct Thrift {
+    buf: Vec<u8>,
+    /// The last field id written in each open struct.
+    last: Vec<i16>,
+}
+
+impl Default for Thrift {
+    fn default() -> Self {
+        Self {
+            buf: Vec::new(),
+            last: vec![0],
+        }
+    }
+}
+
+impl Thrift {
+    fn field(&mut self, id: i16, kind: u8) {
+        let last = self.last.last_mut().expect("a struct is open");
+        match id - *last {
+            delta @ 1..=15 => self.buf.push((delta as u8) << 4 | kind),
+            _ => {
+                self.buf.push(kind);
+                varint(&mut self.buf, zigzag(id.into()));
+            }
+        }
+        *last = id;
+    }
+
+    fn bool(&mut self, id: i16, v: bool) {
+        self.field(id, if v { BOOL_TRUE } else { BOOL_FALSE });
+    }
+
+    fn i32(&mut self, id: i16, v: i32) {
+        self.field(id, I32);
+        varint(&mut self.buf, zigzag(v.into()));
+    }
+
+    fn i64(&mut self, id: i16, v: i64) {
+        self.field(id, I64);
+        varint(&mut self.buf, zigzag(v));
+    }
+
+    fn string(&mut self, id: i16, s: &str) {
+        self.field(id, BINARY);
+        self.list_string(s);
+    }
+
+    /// Open a struct-valued field; close it with [`Thrift::end`].
+    fn begin(&mut self, id: i16) {
+        self.field(id, STRUCT);
+        self.last.push(0);
+    }
+
+    /// Open a struct that is an element of a list.
+    fn element(&mut self) {
+        self.last.push(0);
+    }
+
+    fn end(&mut self) {
+        self.buf.push(0);
+        self.last.pop();
+    }
+
+    fn list(&mut self, id: i16, kind: u8, len: usize) {
+        self.field(id, LIST);
+        if len < 15 {
+            self.buf.push((len as u8) << 4 | kind);
+        } else {
+            self.buf.push(0xf0 | kind);
+            varint(&mut self.buf, len as u64);
+        }
+    }
+
+    fn list_i32(&mut self, v: i32) {
+        varint(&mut self.buf, zigzag(v.into()));
+    }
+
+    fn list_string(&mut self, s: &str) {
+        varint(&mut self.buf, s.len() as u64);
+        self.buf.extend(s.as_bytes());
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        self.buf.push(0);
+        self.buf
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_levels_and_thrift() {
+        assert_eq!(
+            definition_levels(&[true, true, false, true]),
+            [4, 1, 2, 0, 2, 1]
+        );
+        let mut t = Thrift::default();
+        t.i32(1, -1);
+        t.begin(3);
+        t.bool(1, true);
+        t.end();
+        t.i64(20, 300);
+        assert_eq!(
+            t.finish(),
+            [0x15, 0x01, 0x2c, 0x11, 0x00, 0x06, 0x28, 0xd8, 0x04, 0x00]
+        );
+    }
+
+    #[test]
+    fn frames_the_file() {
+        let columns = [
+            Column {
+                name: "exit_code".to_string(),
+                defined: None,
+                values: Values::Int32(vec![0, 3]),
+            },
+            Column {
+                name: "ok".to_string(),
+                defined: Some(vec![false, true]),
+                values: Values::Boolean(vec![true]),
+            },
+        ];
+        let file = encode(2, &columns).unwrap();
+        assert!(file.starts_with(MAGIC) && file.ends_with(MAGIC));
+        let end = file.len() - MAGIC.len();
+        let footer = u32::from_le_bytes(file[end - 4..end].try_into().unwrap()) as usize;
+        let meta = &file[end - 4 - footer..end - 4];
+        // The footer's first field is the format version.
+        assert_eq!(&meta[..2], [0x15, 0x02]);
+        // Each page follows its header: the first holds 0 and 3.
+        let first = &file[MAGIC.len()..];
+        let data = [0, 0, 0, 0, 3, 0, 0, 0];
+        assert!(first.windows(8).position(|w| w == data).is_some());
+    }
+}
@@ -0,0 +1,356 @@
+use std::collections::VecDeque;
+
+use chrono::{DateTime, FixedOffset};
+use serde_json::Value;
+
+use crate::cli::{Derive, Kind, Smoothing};
+use crate::extract::{FieldParser, JsonPath};
+use crate::storage::{self, Record};
+use crate::template::{self, ValueTemplate};
+use crate::units;
+use crate::validate::Rule;
+
+/// Settings for the built-in value processing, taken from `run` options.
+#[derive(Default)]
+pub struct PipelineOptions {
+    pub jsonpath: Option<JsonPath>,
+    /// Splits the output into fields, for `--parse`.
+    pub parser: Option<FieldParser>,
+    pub normalize_units: bool,
+    /// Rules every successful value must satisfy; adds a `valid` field.
+    pub rules: Vec<Rule>,
+    pub derive: Vec<Derive>,
+    pub kind: Kind,
+    /// Window size for the `avg` field.
+    pub rolling_avg: Option<u32>,
+    pub rolling_mode: Smoothing,
+    pub precision: Option<usize>,
+    pub template: Option<ValueTemplate>,
+}
+
+/// Built-in value processing applied to each record between running the
+/// command and handing the record to transforms, storage and sinks.
+pub struct Pipeline {
+    opts: PipelineOptions,
+    /// Last successful numeric sample, used for derived columns.
+    previous: Option<Sample>,
+    /// Recent numeric values for the simple moving average.
+    window: VecDeque<f64>,
+    /// Running exponential moving average.
+    ema: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    value: f64,
+    at: DateTime<FixedOffset>,
+}
+
+impl Sample {
+    fn from_record(record: &Record) -> Option<Self> {
+        if record.exit_code != 0 {
+            return None;
+        }
+        Some(Self {
+            value: record.value.trim().parse().ok()?,
+            at: DateTime::parse_from_rfc3339(&record.timestamp).ok()?,
+        })
+    }
+}
+
+impl Pipeline {
+    pub fn new(opts: PipelineOptions) -> Self {
+        Self {
+            opts,
+            previous: None,
+            window: VecDeque::new(),
+            ema: None,
+        }
+    }
+
+    /// Process `record` in place. Returns `false` if it failed validation.
+    pub fn process(&mut self, record: &mut Record) -> bool {
+        // Failed runs keep their raw output so the error stays readable, but
+        // still get every extra field so CSV columns stay aligned.
+        if let Some(parser) = &self.opts.parser {
+            parse_fields(parser, record);
+        }
+        if record.exit_code == 0 {
+            if let Some(path) = &self.opts.jsonpath {
+                if let Err(e) = path.extract(&record.value).map(|v| record.value = v) {
+                    record.value = format!("error: jsonpath: {e}");
+                    record.exit_code = -1;
+                }
+            }
+        }
+        if self.opts.normalize_units {
+            let mut unit = "";
+            if record.exit_code == 0 {
+                if let Some((n, u)) = units::normalize(&record.value) {
+                    record.value = units::format_number(n);
+                    unit = u;
+                }
+            }
+            record.fields.insert("unit".to_string(), unit.into());
+        }
+        let mut valid = true;
+        if !self.opts.rules.is_empty() {
+            // Failed runs are already flagged by their exit code; `valid` stays empty.
+            let mut flag = Value::Null;
+            if record.exit_code == 0 {
+                valid = self.opts.rules.iter().all(|r| r.check(&record.value));
+                flag = valid.into();
+            }
+            record.fields.insert("valid".to_string(), flag);
+        }
+        // Invalid values must not skew derived columns or averages.
+        if !self.opts.derive.is_empty() || self.opts.kind == Kind::Counter {
+            self.derive_columns(record, valid);
+        }
+        if let Some(n) = self.opts.rolling_avg {
+            let avg = if valid {
+                self.rolling_average(record, n as usize)
+            } else {
+                None
+            };
+            record.fields.insert(
+                "avg".to_string(),
+                avg.map(units::number_value).unwrap_or(Value::Null),
+            );
+        }
+        if record.exit_code != 0 {
+            return valid;
+        }
+        if let Some(precision) = self.opts.precision {
+            record.value = template::apply_precision(&record.value, precision);
+        }
+        if let Some(template) = &self.opts.template {
+            record.value = template.render(record);
+        }
+        valid
+    }
+
+    /// Fold the record's value into the moving average. Returns `None` for
+    /// failed or non-numeric records, which leave the average untouched.
+    fn rolling_average(&mut self, record: &Record, n: usize) -> Option<f64> {
+        if record.exit_code != 0 {
+            return None;
+        }
+        let value: f64 = record.value.trim().parse().ok()?;
+        match self.opts.rolling_mode {
+            Smoothing::Sma => {
+                if self.window.len() == n {
+                    self.window.pop_front();
+                }
+                self.window.push_back(value);
+                Some(self.window.iter().sum::<f64>() / self.window.len() as f64)
+            }
+            Smoothing::Ema => {
+                let alpha = 2.0 / (n as f64 + 1.0);
+                let ema = match self.ema {
+                    Some(prev) => alpha * value + (1.0 - alpha) * prev,
+                    None => value,
+                };
+                self.ema = Some(ema);
+                Some(ema)
+            }
+        }
+    }
+
+    /// Add the requested derived columns, empty when there is no previous
+    /// sample or the current value is not numeric. For counters a decrease
+    /// is a reset: the delta is then the new value itself (counted from zero)
+    /// and the record gets `reset: true`.
+    fn derive_columns(&mut self, record: &mut Record, valid: bool) {
+        let current = Sample::from_record(record).filter(|_| valid);
+        let mut reset = false;
+        let delta = match (self.previous, current) {
+            (Some(prev), Some(cur)) => {
+                let mut d = cur.value - prev.value;
+                if self.opts.kind == Kind::Counter && d < 0.0 {
+                    reset = true;
+                    d = cur.value;
+                }
+                Some((d, cur.at - prev.at))
+            }
+            _ => None,
+        };
+        for column in &self.opts.derive {
+            let value = match (column, delta) {
+                (Derive::Delta, Some((d, _))) => units::number_value(d),
+                (Derive::Rate, Some((d, elapsed))) if elapsed.num_milliseconds() > 0 => {
+                    units::number_value(d / (elapsed.num_milliseconds() as f64 / 1000.0))
+                }
+                _ => Value::Null,
+            };
+            let key = match column {
+                Derive::Delta => "delta",
+                Derive::Rate => "rate",
+            };
+            record.fields.insert(key.to_string(), value);
+        }
+        if self.opts.kind == Kind::Counter {
+            record.fields.insert("reset".to_string(), reset.into());
+        }
+        if current.is_some() {
+            self.previous = current;
+        }
+    }
+}
+
+/// Store the parsed fields of a successful run and make the first one the
+/// value. Output that does not parse is kept as the value of a failed
+/// record (exit code -1), and like other failed runs it gets every field
+/// empty so CSV columns stay aligned.
+fn parse_fields(parser: &FieldParser, record: &mut Record) {
+    if record.exit_code == 0 {
+        match parser.parse(&record.value) {
+            Ok(fields) => {
+                if let Some(first) = fields.values().next() {
+                    record.value = storage::field_to_string(first);
+                }
+                record.fields.extend(fields);
+                return;
+            }
+            Err(e) => {
+                tracing::warn!(error = %e, "could not parse the output; storing it as is");
+                record.exit_code = -1;
+            }
+        }
+    }
+    for name in parser.names() {
+        record.fields.insert(name.to_string(), Value::Null);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn derives_delta_and_rate_from_previous_sample() {
+        let mut pipeline = Pipeline::new(PipelineOptions {
+            derive: vec![Derive::Delta, Derive::Rate],
+            ..PipelineOptions::default()
+        });
+        let mut first = Record::new("2025-01-01T00:00:00Z", "100", 0);
+        pipeline.process(&mut first);
+        assert_eq!(first.fields["delta"], Value::Null);
+        assert_eq!(first.fields["rate"], Value::Null);
+
+        let mut failed = Record::new("2025-01-01T00:00:05Z", "oops", 1);
+        pipeline.process(&mut failed);
+        assert_eq!(failed.fields["delta"], Value::Null);
+
+        let mut second = Record::new("2025-01-01T00:00:10Z", "150", 0);
+        pipeline.process(&mut second);
+        assert_eq!(second.fields["delta"], 50);
+        assert_eq!(second.fields["rate"], 5);
+        assert!(!second.fields.contains_key("reset"));
+    }
+
+    #[test]
+    fn rolling_averages_skip_failed_runs() {
+        let values = ["10", "20", "bad", "30", "40"];
+        let run = |mode| {
+            let mut pipeline = Pipeline::new(PipelineOptions {
+                rolling_avg: Some(3),
+                rolling_mode: mode,
+                ..PipelineOptions::default()
+            });
+            values
+                .iter()
+                .map(|v| {
+                    let mut r = Record::new("2025-01-01T00:00:00Z", *v, 0);
+                    pipeline.process(&mut r);
+                    r.fields["avg"].clone()
+                })
+                .collect::<Vec<_>>()
+        };
+        let sma = run(Smoothing::Sma);
+        assert_eq!(sma[0], 10);
+        assert_eq!(sma[1], 15);
+        assert_eq!(sma[2], Value::Null);
+        assert_eq!(sma[3], 20);
+        assert_eq!(sma[4], 30);
+        let ema = run(Smoothing::Ema);
+        assert_eq!(ema[1], 15);
+        assert_eq!(ema[3], 22.5);
+    }
+
+    #[test]
+    fn invalid_values_are_flagged_and_skipped() {
+        let mut pipeline = Pipeline::new(PipelineOptions {
+            rules: vec!["range:0..100".parse().unwrap()],
+            derive: vec![Derive::Delta],
+            ..PipelineOptions::default()
+        });
+        let mut first = Record::new("2025-01-01T00:00:00Z", "50", 0);
+        assert!(pipeline.process(&mut first));
+        assert_eq!(first.fields["valid"], true);
+
+        let mut glitch = Record::new("2025-01-01T00:00:10Z", "9999", 0);
+        assert!(!pipeline.process(&mut glitch));
+        assert_eq!(glitch.fields["valid"], false);
+        assert_eq!(glitch.fields["delta"], Value::Null);
+
+        let mut failed = Record::new("2025-01-01T00:00:20Z", "error", 1);
+        assert!(pipeline.process(&mut failed));
+        assert_eq!(failed.fields["valid"], Value::Null);
+
+        let mut next = Record::new("2025-01-01T00:00:30Z", "60", 0);
+        assert!(pipeline.process(&mut next));
+        assert_eq!(next.fields["delta"], 10);
+    }
+
+    #[test]
+    fn counter_decrease_is_a_reset() {
+        let mut pipeline = Pipeline::new(PipelineOptions {
+            derive: vec![Derive::Delta, Derive::Rate],
+            kind: Kind::Counter,
+            ..PipelineOptions::default()
+        });
+        let mut before = Record::new("2025-01-01T00:00:00Z", "1000", 0);
+        pipeline.process(&mut before);
+        assert_eq!(before.fields["reset"], false);
+
+        let mut after = Record::new("2025-01-01T00:00:10Z", "40", 0);
+        pipeline.process(&mut after);
+        assert_eq!(after.fields["reset"], true);
+        assert_eq!(after.fields["delta"], 40);
+        assert_eq!(after.fields["rate"], 4);
+
+        let mut next = Record::new("2025-01-01T00:00:20Z", "60", 0);
+        pipeline.process(&mut next);
+        assert_eq!(next.fields["reset"], false);
+        assert_eq!(next.fields["delta"], 20);
+    }
+
+    #[test]
+    fn parses_fields_into_columns() {
+        let parser = FieldParser::json(&["cpu".to_string(), "mem".to_string()]).unwrap();
+        let mut pipeline = Pipeline::new(PipelineOptions {
+            parser: Some(parser),
+            derive: vec![Derive::Delta],
+            ..PipelineOptions::default()
+        });
+        let mut first = Record::new("2025-01-01T00:00:00Z", r#"{"mem": 40, "cpu": 12}"#, 0);
+        pipeline.process(&mut first);
+        assert_eq!(first.value, "12");
+        let columns: Vec<&String> = first.fields.keys().collect();
+        assert_eq!(columns, ["cpu", "mem", "delta"]);
+        assert_eq!(first.fields["mem"], 40);
+
+        let mut garbled = Record::new("2025-01-01T00:00:05Z", "cpu=20", 0);
+        pipeline.process(&mut garbled);
+        assert_eq!(garbled.value, "cpu=20");
+        assert_eq!(garbled.exit_code, -1);
+        assert_eq!(garbled.fields["cpu"], Value::Null);
+        assert_eq!(garbled.fields.len(), 3);
+
+        let mut second = Record::new("2025-01-01T00:00:10Z", r#"{"cpu": 15}"#, 0);
+        pipeline.process(&mut second);
+        assert_eq!(second.fields["mem"], Value::Null);
+        assert_eq!(second.fields["delta"], 3);
+    }
+}
@@ -0,0 +1,294 @@
+use std::io;
+use std::path::Path;
+
+use serde_json::Value;
+use wasmtime::component::{Component, Instance, Linker, ResourceTable, TypedFunc};
+use wasmtime::{Config, Engine, Store};
+use wasmtime_wasi::{WasiCtx, WasiCtxBuilder, WasiCtxView, WasiView};
+
+use crate::sink::Sink;
+use crate::storage::Record;
+
+/// Export names from `wit/plugin.wit`.
+const TRANSFORM_INTERFACE: &str = "trep:plugin/transform@0.1.0";
+const SINK_INTERFACE: &str = "trep:plugin/sink@0.1.0";
+/// Fuel granted to each plugin call (roughly one unit per wasm instruction),
+/// so a misbehaving plugin fails the call instead of hanging the job.
+const FUEL_PER_CALL: u64 = 500_000_000;
+
+/// Host state for a plugin instance. Plugins get WASI with no filesystem,
+/// network or environment access; only stderr is passed through for debugging.
+struct PluginState {
+    ctx: WasiCtx,
+    table: ResourceTable,
+}
+
+impl WasiView for PluginState {
+    fn ctx(&mut self) -> WasiCtxView<'_> {
+        WasiCtxView {
+            ctx: &mut self.ctx,
+            table: &mut self.table,
+        }
+    }
+}
+
+/// An instance of a plugin and one function it exports.
+struct Export<P, R> {
+    plugin: Plugin,
+    interface: &'static str,
+    func_name: &'static str,
+    store: Store<PluginState>,
+    func: TypedFunc<P, R>,
+}
+
+/// A compiled WASM component implementing the `trep:plugin` interfaces.
+#[derive(Clone)]
+pub struct Plugin {
+    name: String,
+    engine: Engine,
+    component: Component,
+}
+
+/// The `transform` export of a plugin.
+pub struct PluginTransform {
+    apply: Export<(String,), (Result<Option<String>, String>,)>,
+}
+
+/// The `sink` export of a plugin.
+pub struct PluginSink {
+    write: Export<(String,), (Result<(), String>,)>,
+}
+
+fn plugin_error(name: &str, e: impl std::fmt::Display) -> io::Error {
+    io::Error::other(format!("plugin {name}: {e}"))
+}
+
+impl Plugin {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_else(|| path.display().to_string());
+        let bytes = std::fs::read(path).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("cannot read plugin {}: {e}", path.display()),
+            )
+        })?;
+        Self::from_bytes(&name, &bytes)
+    }
+
+    pub fn from_bytes(name: &str, bytes: &[u8]) -> io::Result<Self> {
+        let mut config = Config::new();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config).map_err(|e| plugin_error(name, e))?;
+        let component = Component::new(&engine, bytes).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("plugin {name}: not a valid component: {e:#}"),
+            )
+        })?;
+        Ok(Self {
+            name: name.to_string(),
+            engine,
+            component,
+        })
+    }
+
+    fn instantiate(&self) -> io::Result<(Store<PluginState>, Instance)> {
+        let mut linker = Linker::new(&self.engine);
+        wasmtime_wasi::p2::add_to_linker_sync(&mut linker)
+            .map_err(|e| plugin_error(&self.name, e))?;
+        let state = PluginState {
+            ctx: WasiCtxBuilder::new().inherit_stderr().build(),
+            table: ResourceTable::new(),
+        };
+        let mut store = Store::new(&self.engine, state);
+        store
+            .set_fuel(FUEL_PER_CALL)
+            .map_err(|e| plugin_error(&self.name, e))?;
+        let instance = linker
+            .instantiate(&mut store, &self.component)
+            .map_err(|e| plugin_error(&self.name, format!("{e:#}")))?;
+        Ok((store, instance))
+    }
+
+    fn exports(&self, interface: &str) -> bool {
+        self.component.get_export_index(None, interface).is_some()
+    }
+
+    /// Instantiate the plugin's `transform` export, if it has one.
+    pub fn transform(&self) -> io::Result<Option<PluginTransform>> {
+        if !self.exports(TRANSFORM_INTERFACE) {
+            return Ok(None);
+        }
+        Ok(Some(PluginTransform {
+            apply: Export::new(self, TRANSFORM_INTERFACE, "apply")?,
+        }))
+    }
+
+    /// Instantiate the plugin's `sink` export, if it has one.
+    pub fn sink(&self) -> io::Result<Option<PluginSink>> {
+        if !self.exports(SINK_INTERFACE) {
+            return Ok(None);
+        }
+        Ok(Some(PluginSink {
+            write: Export::new(self, SINK_INTERFACE, "write")?,
+        }))
+    }
+}
+
+impl<P, R> Export<P, R>
+where
+    P: wasmtime::component::ComponentNamedList + wasmtime::component::Lower,
+    R: wasmtime::component::ComponentNamedList + wasmtime::component::Lift,
+{
+    /// Instantiate `plugin` and look up `func_name` in its `interface`.
+    fn new(plugin: &Plugin, interface: &'static str, func_name: &'static str) -> io::Result<Self> {
+        let name = &plugin.name;
+        let (mut store, instance) = plugin.instantiate()?;
+        let iface = instance
+            .get_export_index(&mut store, None, interface)
+            .ok_or_else(|| plugin_error(name, format!("missing export {interface}")))?;
+        let index = instance
+            .get_export_index(&mut store, Some(&iface), func_name)
+            .ok_or_else(|| {
+                plugin_error(name, format!("{interface} has no `{func_name}` function"))
+            })?;
+        let func = instance
+            .get_typed_func(&mut store, index)
+            .map_err(|e| plugin_error(name, format!("{interface}#{func_name}: {e:#}")))?;
+        Ok(Self {
+            plugin: plugin.clone(),
+            interface,
+            func_name,
+            store,
+            func,
+        })
+    }
+
+    fn call(&mut self, params: P) -> io::Result<R> {
+        let name = &self.plugin.name;
+        self.store
+            .set_fuel(FUEL_PER_CALL)
+            .map_err(|e| plugin_error(name, e))?;
+        let e = match self.func.call(&mut self.store, params) {
+            Ok(results) => return Ok(results),
+            Err(e) => e,
+        };
+        // A trap, running out of fuel included, leaves the instance unable
+        // to take further calls; the next record gets a fresh one.
+        if e.downcast_ref::<wasmtime::Trap>().is_some() {
+            match Self::new(&self.plugin, self.interface, self.func_name) {
+                Ok(fresh) => *self = fresh,
+                Err(reload) => tracing::warn!(error = %reload, "could not reload plugin"),
+            }
+        }
+        Err(plugin_error(&self.plugin.name, format!("{e:#}")))
+    }
+}
+
+impl PluginTransform {
+    /// Run the plugin on `record`. Returns `Ok(None)` when the plugin drops it.
+    pub fn apply(&mut self, record: Record, stderr: &str) -> io::Result<Option<Record>> {
+        let mut input = serde_json::to_value(&record)?;
+        if let Value::Object(map) = &mut input {
+            map.insert("stderr".to_string(), Value::String(stderr.to_string()));
+        }
+        let (result,) = self.apply.call((input.to_string(),))?;
+        let name = &self.apply.plugin.name;
+        let Some(output) = result.map_err(|e| plugin_error(name, e))? else {
+            return Ok(None);
+        };
+        let mut output: Value = serde_json::from_str(&output)
+            .map_err(|e| plugin_error(name, format!("invalid record JSON: {e}")))?;
+        if let Value::Object(map) = &mut output {
+            map.remove("stderr");
+        }
+        let record = serde_json::from_value(output)
+            .map_err(|e| plugin_error(name, format!("invalid record: {e}")))?;
+        Ok(Some(record))
+    }
+}
+
+impl Sink for PluginSink {
+    fn name(&self) -> &str {
+        &self.write.plugin.name
+    }
+
+    fn send(&mut self, record: &Record) -> io::Result<()> {
+        let json = serde_json::to_string(record)?;
+        let (result,) = self.write.call((json,))?;
+        result.map_err(|e| plugin_error(&self.write.plugin.name, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A transform that returns its input unchanged, written directly in the
+    /// component text format.
+    const IDENTITY_TRANSFORM: &str = r#"
+    (component
+      (core module $m
+        (memory (export "memory") 1)
+        (global $heap (mut i32) (i32.const 1024))
+        (func (export "realloc") (param i32 i32 i32 i32) (result i32)
+          (local $ptr i32)
+          (local.set $ptr (global.get $heap))
+          (global.set $heap
+            (i32.and (i32.add (i32.add (local.get $ptr) (local.get 3)) (i32.const 7))
+                     (i32.const -8)))
+          (local.get $ptr))
+        (func (export "apply") (param $ptr i32) (param $len i32) (result i32)
+          (i32.store8 (i32.const 0) (i32.const 0))
+          (i32.store8 (i32.const 4) (i32.const 1))
+          (i32.store (i32.const 8) (local.get $ptr))
+          (i32.store (i32.const 12) (local.get $len))
+          (i32.const 0)))
+      (core instance $i (instantiate $m))
+      (func $apply (param "record" string) (result (result (option string) (error string)))
+        (canon lift (core func $i "apply")
+          (memory (core memory $i "memory")) (realloc (core func $i "realloc"))))
+      (instance $transform (export "apply" (func $apply)))
+      (export "trep:plugin/transform@0.1.0" (instance $transform)))
+    "#;
+
+    #[test]
+    fn identity_transform_round_trips_record() {
+        let plugin = Plugin::from_bytes("identity", IDENTITY_TRANSFORM.as_bytes()).unwrap();
+        assert!(plugin.sink().unwrap().is_none());
+        let mut transform = plugin.transform().unwrap().unwrap();
+        let mut record = Record::new("2025-01-01T00:00:00Z", "42", 0);
+        record.fields.insert("unit".to_string(), "C".into());
+        let out = transform.apply(record, "noise").unwrap().unwrap();
+        assert_eq!(out.value, "42");
+        assert_eq!(out.fields["unit"], "C");
+        assert!(!out.fields.contains_key("stderr"));
+    }
+
+    #[test]
+    fn recovers_from_running_out_of_fuel() {
+        // Spins on long records until its fuel runs out.
+        let spinning = IDENTITY_TRANSFORM.replace(
+            "(i32.store8 (i32.const 0) (i32.const 0))",
+            "(if (i32.gt_u (local.get $len) (i32.const 200)) (then (loop $spin (br $spin))))
+          (i32.store8 (i32.const 0) (i32.const 0))",
+        );
+        let plugin = Plugin::from_bytes("spinning", spinning.as_bytes()).unwrap();
+        let mut transform = plugin.transform().unwrap().unwrap();
+        let long = Record::new("2025-01-01T00:00:00Z", "x".repeat(300), 0);
+        for _ in 0..2 {
+            let e = transform.apply(long.clone(), "").unwrap_err();
+            assert!(e.to_string().contains("fuel"), "{e}");
+        }
+        let short = Record::new("2025-01-01T00:00:00Z", "42", 0);
+        assert_eq!(transform.apply(short, "").unwrap().unwrap().value, "42");
+    }
+
+    #[test]
+    fn rejects_non_component_input() {
+        assert!(Plugin::from_bytes("bad", b"not wasm").is_err());
+    }
+}
@@ -0,0 +1,539 @@
+use std::io;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::client::WebPkiServerVerifier;
+use rustls::crypto::ring;
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{
+    ClientConfig, ClientConnection, DigitallySignedStruct, RootCertStore, SignatureScheme,
+};
+use serde_json::{Map, Value};
+
+use crate::units;
+
+/// Used when `--timeout` is not given, so a stuck endpoint cannot stall the job.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A check trep performs itself instead of running a command.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Probe {
+    /// `http://...` or `https://...`: request the URL with GET.
+    Http(String),
+    /// `tcp:<host>:<port>`: open a TCP connection.
+    Tcp(String, u16),
+    /// `tls:<host>:<port>`: read the certificate the server presents.
+    Tls(String, u16),
+}
+
+impl FromStr for Probe {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> io::Result<Self> {
+        if s.starts_with("http://") || s.starts_with("https://") {
+            return Ok(Probe::Http(s.to_string()));
+        }
+        let invalid = |msg: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid check '{s}': {msg}"),
+            )
+        };
+        if let Some(target) = s.strip_prefix("tcp:") {
+            let (host, port) =
+                host_port(target).ok_or_else(|| invalid("expected tcp:<host>:<port>"))?;
+            return Ok(Probe::Tcp(host, port));
+        }
+        if let Some(target) = s.strip_prefix("tls:") {
+            let (host, port) =
+                host_port(target).ok_or_else(|| invalid("expected tls:<host>:<port>"))?;
+            return Ok(Probe::Tls(host, port));
+        }
+        Err(invalid(
+            "expected an http:// or https:// URL, tcp:<host>:<port> or tls:<host>:<port>",
+        ))
+    }
+}
+
+/// Split `host:port`, where an IPv6 host is written in brackets (`[::1]:22`).
+fn host_port(target: &str) -> Option<(String, u16)> {
+    let (host, port) = target.rsplit_once(':')?;
+    let host = host
+        .strip_prefix('[')
+        .and_then(|h| h.strip_suffix(']'))
+        .unwrap_or(host);
+    let port = port.parse().ok()?;
+    (!host.is_empty()).then(|| (host.to_string(), port))
+}
+
+/// Milliseconds with two decimals, as a JSON number.
+fn millis(d: Duration) -> Value {
+    units::number_value((d.as_secs_f64() * 100_000.0).round() / 100.0)
+}
+
+/// The outcome of one check, shaped like a record.
+#[derive(Debug, PartialEq)]
+pub struct CheckResult {
+    pub value: String,
+    pub exit_code: i32,
+    pub fields: Map<String, Value>,
+}
+
+/// Days before a certificate expires at which a TLS check starts failing:
+/// exit code 1 below `warn`, 2 below `critical`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExpiryThresholds {
+    pub warn: i64,
+    pub critical: i64,
+}
+
+impl Default for ExpiryThresholds {
+    fn default() -> Self {
+        Self {
+            warn: 30,
+            critical: 7,
+        }
+    }
+}
+
+impl ExpiryThresholds {
+    /// Exit code for a certificate `days` from expiry. An untrusted
+    /// certificate is critical whatever its expiry.
+    fn exit_code(&self, days: i64, trusted: bool) -> i32 {
+        if !trusted || days < self.critical {
+            2
+        } else if days < self.warn {
+            1
+        } else {
+            0
+        }
+    }
+}
+
+/// Runs a probe every tick, reusing connections between ticks.
+pub struct Prober {
+    probe: Probe,
+    agent: ureq::Agent,
+    timeout: Duration,
+    expiry: ExpiryThresholds,
+}
+
+impl Prober {
+    pub fn new(probe: Probe, timeout: Option<Duration>) -> Self {
+        let timeout = timeout.unwrap_or(DEFAULT_TIMEOUT);
+        let agent = ureq::Agent::config_builder()
+            .timeout_global(Some(timeout))
+            .http_status_as_error(false)
+            .build()
+            .into();
+        Self {
+            probe,
+            agent,
+            timeout,
+            expiry: ExpiryThresholds::default(),
+        }
+    }
+
+    /// Replace the default expiry thresholds of TLS checks.
+    pub fn expiry_thresholds(mut self, expiry: ExpiryThresholds) -> Self {
+        self.expiry = expiry;
+        self
+    }
+
+    /// Run the probe once. An unreachable target is a failed result, not an error.
+    pub fn check(&mut self) -> io::Result<CheckResult> {
+        match &self.probe {
+            Probe::Http(url) => self.http(url),
+            Probe::Tcp(host, port) => Ok(self.tcp(host, *port)),
+            Probe::Tls(host, port) => Ok(self.tls(host, *port)),
+        }
+    }
+
+    /// Whole days until the server's certificate expires as value (negative
+    /// once expired), with its `expires` time, `issuer`, whether it chains to
+    /// a trusted root for the host (`trusted`) and why not (`error`). The exit
+    /// code follows the expiry thresholds. Untrusted and expired certificates
+    /// are still read, so their expiry is recorded; a failed handshake gets
+    /// exit code -1 and keeps the same fields.
+    fn tls(&self, host: &str, port: u16) -> CheckResult {
+        let mut fields = Map::new();
+        for name in ["expires", "issuer", "trusted", "error"] {
+            fields.insert(name.into(), Value::Null);
+        }
+        let cert = match peer_certificate(host, port, self.timeout) {
+            Ok(cert) => cert,
+            Err(e) => {
+                return CheckResult {
+                    value: format!("error: {e}"),
+                    exit_code: -1,
+                    fields,
+                }
+            }
+        };
+        let days = (cert.expires - Utc::now()).num_days();
+        fields.insert("expires".into(), cert.expires.to_rfc3339().into());
+        fields.insert("issuer".into(), cert.issuer.into());
+        fields.insert("trusted".into(), cert.untrusted.is_none().into());
+        if let Some(reason) = &cert.untrusted {
+            fields.insert("error".into(), reason.clone().into());
+        }
+        CheckResult {
+            value: days.to_string(),
+            exit_code: self.expiry.exit_code(days, cert.untrusted.is_none()),
+            fields,
+        }
+    }
+
+    /// `1` and the connect (TCP handshake) latency when a connection to one
+    /// of the host's addresses succeeds; otherwise `0`, exit code 1 and the
+    /// error. Both fields are always present.
+    fn tcp(&self, host: &str, port: u16) -> CheckResult {
+        let connected = (host, port).to_socket_addrs().and_then(|addrs| {
+            let mut last_err =
+                io::Error::new(io::ErrorKind::NotFound, format!("{host} has no addresses"));
+            for addr in addrs {
+                let attempt = Instant::now();
+                match TcpStream::connect_timeout(&addr, self.timeout) {
+                    Ok(_) => return Ok(attempt.elapsed()),
+                    Err(e) => last_err = e,
+                }
+            }
+            Err(last_err)
+        });
+        let mut fields = Map::new();
+        let (value, exit_code) = match connected {
+            Ok(latency) => {
+                fields.insert("latency_ms".into(), millis(latency));
+                fields.insert("error".into(), Value::Null);
+                ("1", 0)
+            }
+            Err(e) => {
+                fields.insert("latency_ms".into(), Value::Null);
+                fields.insert("error".into(), e.to_string().into());
+                ("0", 1)
+            }
+        };
+        CheckResult {
+            value: value.to_string(),
+            exit_code,
+            fields,
+        }
+    }
+
+    /// The status code as value, failing (exit code 1) for 4xx and 5xx, with
+    /// the latency until the whole body was read and the body size. Requests
+    /// that get no response fail with exit code -1 and keep the same fields,
+    /// so CSV columns stay aligned.
+    fn http(&self, url: &str) -> io::Result<CheckResult> {
+        let started = Instant::now();
+        let response = self.agent.get(url).call().and_then(|mut response| {
+            let body_bytes = io::copy(&mut response.body_mut().as_reader(), &mut io::sink())?;
+            Ok((response.status().as_u16(), body_bytes))
+        });
+        let latency_ms = started.elapsed().as_millis() as u64;
+        let (value, exit_code, body_bytes) = match response {
+            Ok((status, bytes)) => (status.to_string(), i32::from(status >= 400), bytes.into()),
+            Err(e) => (format!("error: {e}"), -1, Value::Null),
+        };
+        let mut fields = Map::new();
+        fields.insert("latency_ms".into(), latency_ms.into());
+        fields.insert("body_bytes".into(), body_bytes);
+        Ok(CheckResult {
+            value,
+            exit_code,
+            fields,
+        })
+    }
+}
+
+/// What a TLS check learns about the server's certificate.
+struct PeerCertificate {
+    expires: DateTime<Utc>,
+    issuer: String,
+    /// Why the certificate is not trusted for the host, if it is not.
+    untrusted: Option<String>,
+}
+
+/// Connect, complete a TLS handshake and read the leaf certificate.
+fn peer_certificate(host: &str, port: u16, timeout: Duration) -> io::Result<PeerCertificate> {
+    let provider = Arc::new(ring::default_provider());
+    let roots = RootCertStore {
+        roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+    };
+    let webpki = WebPkiServerVerifier::builder_with_provider(Arc::new(roots), provider.clone())
+        .build()
+        .map_err(io::Error::other)?;
+    let verifier = Arc::new(RecordingVerifier {
+        inner: webpki,
+        outcome: Mutex::new(None),
+    });
+    let config = ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .map_err(io::Error::other)?
+        .dangerous()
+        .with_custom_certificate_verifier(verifier.clone())
+        .with_no_client_auth();
+    let server_name = ServerName::try_from(host.to_string()).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid host '{host}': {e}"),
+        )
+    })?;
+    let mut conn =
+        ClientConnection::new(Arc::new(config), server_name).map_err(io::Error::other)?;
+
+    let mut sock = connect(host, port, timeout)?;
+    sock.set_read_timeout(Some(timeout))?;
+    sock.set_write_timeout(Some(timeout))?;
+    while conn.is_handshaking() {
+        conn.complete_io(&mut sock)?;
+    }
+    let der = conn
+        .peer_certificates()
+        .and_then(|certs| certs.first())
+        .ok_or_else(|| io::Error::other("the server sent no certificate"))?;
+    let (_, cert) = x509_parser::parse_x509_certificate(der).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unreadable certificate: {e}"),
+        )
+    })?;
+    let expires =
+        DateTime::from_timestamp(cert.validity().not_after.timestamp(), 0).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "certificate expiry out of range",
+            )
+        })?;
+    let issuer = cert
+        .issuer()
+        .iter_common_name()
+        .next()
+        .and_then(|cn| cn.as_str().ok())
+        .map_or_else(|| cert.issuer().to_string(), str::to_string);
+    let untrusted = verifier
+        .outcome
+        .lock()
+        .expect("verifier lock poisoned")
+        .take()
+        .unwrap_or_else(|| Some("the certificate was not verified".to_string()));
+    Ok(PeerCertificate {
+        expires,
+        issuer,
+        untrusted,
+    })
+}
+
+/// Open a TCP connection to the first of the host's addresses that answers.
+fn connect(host: &str, port: u16, timeout: Duration) -> io::Result<TcpStream> {
+    let mut last_err = io::Error::new(io::ErrorKind::NotFound, format!("{host} has no addresses"));
+    for addr in (host, port).to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, timeout) {
+            Ok(sock) => return Ok(sock),
+            Err(e) => last_err = e,
+        }
+    }
+    Err(last_err)
+}
+
+/// Verifies the server certificate against the web PKI roots but lets the
+/// handshake go on whatever the outcome, remembering it instead: a check
+/// should report an expired or misissued certificate, not fail to read it.
+/// Handshake signatures are still checked.
+#[derive(Debug)]
+struct RecordingVerifier {
+    inner: Arc<WebPkiServerVerifier>,
+    /// `Some(None)` once verified, `Some(Some(reason))` once rejected.
+    outcome: Mutex<Option<Option<String>>>,
+}
+
+impl ServerCertVerifier for RecordingVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verified = self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            ocsp_response,
+            now,
+        );
+        *self.outcome.lock().expect("verifier lock poisoned") =
+            Some(verified.err().map(|e| e.to_string()));
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Answer one HTTP request with `status` and `body`.
+    fn serve_once(status: &str, body: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let status = status.to_string();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0u8; 1024];
+            let _ = stream.read(&mut buf);
+            write!(
+                stream,
+                "HTTP/1.1 {status}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            )
+            .unwrap();
+        });
+        format!("http://{addr}/health")
+    }
+
+    #[test]
+    fn records_status_latency_and_size() {
+        let url = serve_once("200 OK", "healthy");
+        let mut prober = Prober::new(url.parse().unwrap(), None);
+        let result = prober.check().unwrap();
+        assert_eq!(result.value, "200");
+        assert_eq!(result.exit_code, 0);
+        assert_eq!(result.fields["body_bytes"], 7);
+        assert!(result.fields["latency_ms"].is_u64());
+
+        let url = serve_once("503 Service Unavailable", "");
+        let result = Prober::new(url.parse().unwrap(), None).check().unwrap();
+        assert_eq!((result.value.as_str(), result.exit_code), ("503", 1));
+
+        let refused = Prober::new("http://127.0.0.1:1/".parse().unwrap(), None)
+            .check()
+            .unwrap();
+        assert_eq!(refused.exit_code, -1);
+        assert!(refused.value.starts_with("error: "));
+        assert_eq!(refused.fields["body_bytes"], Value::Null);
+    }
+
+    #[test]
+    fn records_tcp_connects() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let target = format!("tcp:{}", listener.local_addr().unwrap());
+        let open = Prober::new(target.parse().unwrap(), None).check().unwrap();
+        assert_eq!((open.value.as_str(), open.exit_code), ("1", 0));
+        assert!(open.fields["latency_ms"].is_number());
+        assert_eq!(open.fields["error"], Value::Null);
+
+        drop(listener);
+        let closed = Prober::new(target.parse().unwrap(), None).check().unwrap();
+        assert_eq!((closed.value.as_str(), closed.exit_code), ("0", 1));
+        assert!(closed.fields["error"].is_string());
+    }
+
+    /// Serve one TLS handshake with a self-signed certificate for localhost.
+    fn serve_tls_once() -> String {
+        let mut params = rcgen::CertificateParams::new(vec!["localhost".to_string()]).unwrap();
+        params
+            .distinguished_name
+            .push(rcgen::DnType::CommonName, "trep test CA");
+        params.not_after = rcgen::date_time_ymd(2099, 1, 1);
+        let key = rcgen::KeyPair::generate().unwrap();
+        let cert = params.self_signed(&key).unwrap();
+        let config =
+            rustls::ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+                .with_safe_default_protocol_versions()
+                .unwrap()
+                .with_no_client_auth()
+                .with_single_cert(
+                    vec![cert.der().clone()],
+                    rustls::pki_types::PrivateKeyDer::Pkcs8(key.serialize_der().into()),
+                )
+                .unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut conn = rustls::ServerConnection::new(Arc::new(config)).unwrap();
+            while conn.is_handshaking() {
+                if conn.complete_io(&mut stream).is_err() {
+                    return;
+                }
+            }
+        });
+        format!("tls:localhost:{port}")
+    }
+
+    #[test]
+    fn records_certificate_expiry() {
+        let target = serve_tls_once();
+        let result = Prober::new(target.parse().unwrap(), None).check().unwrap();
+        let days: i64 = result.value.parse().unwrap();
+        assert!(days > 365 * 50);
+        assert_eq!(result.fields["expires"], "2099-01-01T00:00:00+00:00");
+        assert_eq!(result.fields["issuer"], "trep test CA");
+        assert_eq!(result.fields["trusted"], false);
+        assert!(result.fields["error"].is_string());
+        assert_eq!(result.exit_code, 2);
+
+        let refused = Prober::new("tls:127.0.0.1:1".parse().unwrap(), None)
+            .check()
+            .unwrap();
+        assert_eq!(refused.exit_code, -1);
+        assert_eq!(refused.fields["expires"], Value::Null);
+
+        let expiry = ExpiryThresholds::default();
+        assert_eq!(expiry.exit_code(90, true), 0);
+        assert_eq!(expiry.exit_code(29, true), 1);
+        assert_eq!(expiry.exit_code(6, true), 2);
+        assert_eq!(expiry.exit_code(-3, true), 2);
+        assert_eq!(expiry.exit_code(90, false), 2);
+    }
+
+    #[test]
+    fn parses_checks() {
+        assert_eq!(
+            "tcp:[::1]:22".parse::<Probe>().unwrap(),
+            Probe::Tcp("::1".to_string(), 22)
+        );
+        assert_eq!(
+            "tcp:db.local:5432".parse::<Probe>().unwrap(),
+            Probe::Tcp("db.local".to_string(), 5432)
+        );
+        assert!("tcp:db.local".parse::<Probe>().is_err());
+        assert!("tcp::80".parse::<Probe>().is_err());
+        assert_eq!(
+            "tls:example.com:443".parse::<Probe>().unwrap(),
+            Probe::Tls("example.com".to_string(), 443)
+        );
+        assert!("ftp://example.com".parse::<Probe>().is_err());
+        assert!("example.com".parse::<Probe>().is_err());
+    }
+}
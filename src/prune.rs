@@ -0,0 +1,67 @@
+use std::io;
+use std::path::Path;
+
+use chrono::Local;
+
+use crate::cli::PruneOpts;
+use crate::retention::{self, Action, Retention};
+use crate::storage;
+
+/// Apply a retention policy to a job's data directory now, as `trep run
+/// --retain/--retain-files/--compress` does when the day rotates. Only past
+/// days are touched, so the job may keep running meanwhile.
+pub fn prune(opts: PruneOpts) -> io::Result<()> {
+    let policy = Retention::from_opts(opts.retain.as_deref(), opts.retain_files, opts.compress)?;
+    let dir = storage::job_dir(&opts.name);
+    if !dir.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no data for job '{}' in {}", opts.name, dir.display()),
+        ));
+    }
+    let actions = policy.plan(&dir, Local::now().date_naive())?;
+    let (mut files, mut records) = (0, 0);
+    for action in &actions {
+        let removed = match (action, opts.dry_run) {
+            (Action::Trim { path, before }, true) => retention::count_trimmed(path, *before)?,
+            (_, true) => 1,
+            (_, false) => retention::execute(action)?,
+        };
+        match action {
+            Action::Trim { .. } => records += removed,
+            _ => files += 1,
+        }
+        let (verb, done, what) = match action {
+            Action::Compress(path) => ("compress", "Compressed", shown(path)),
+            Action::Delete(path) => ("delete", "Deleted", shown(path)),
+            Action::Trim { path, before } => (
+                "delete",
+                "Deleted",
+                format!(
+                    "{removed} records before {} from {}",
+                    before.date_naive(),
+                    shown(path)
+                ),
+            ),
+        };
+        if opts.dry_run {
+            println!("Would {verb} {what}");
+        } else {
+            println!("{done} {what}");
+        }
+    }
+    println!(
+        "{} '{}': {files} files, {records} database records",
+        if opts.dry_run {
+            "Would prune"
+        } else {
+            "Pruned"
+        },
+        opts.name
+    );
+    Ok(())
+}
+
+fn shown(path: &Path) -> String {
+    dunce::simplified(path).display().to_string()
+}
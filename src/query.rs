@@ -0,0 +1,290 @@
+use std::io;
+use std::str::FromStr;
+
+use serde_json::{Map, Value};
+
+use crate::storage::{self, DataFile, Record};
+
+/// Records per page when the request gives no `limit`.
+const DEFAULT_LIMIT: usize = 1000;
+/// The largest page a request may ask for.
+const MAX_LIMIT: usize = 10_000;
+
+/// A comparison in a filter such as `exit_code=ne:0` or `value=gt:90`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+/// A filter on one record field.
+#[derive(Debug, Clone, PartialEq)]
+struct Filter {
+    op: Op,
+    operand: String,
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    /// `<op>:<operand>` with op one of eq, ne, gt, ge, lt, le; a bare
+    /// operand means eq.
+    fn from_str(s: &str) -> Result<Self, String> {
+        let (op, operand) = match s.split_once(':') {
+            Some(("eq", rest)) => (Op::Eq, rest),
+            Some(("ne", rest)) => (Op::Ne, rest),
+            Some(("gt", rest)) => (Op::Gt, rest),
+            Some(("ge", rest)) => (Op::Ge, rest),
+            Some(("lt", rest)) => (Op::Lt, rest),
+            Some(("le", rest)) => (Op::Le, rest),
+            _ => (Op::Eq, s),
+        };
+        Ok(Filter {
+            op,
+            operand: operand.to_string(),
+        })
+    }
+}
+
+impl Filter {
+    /// Numbers compare numerically. Otherwise only eq and ne apply, comparing
+    /// text; ordering a value that is not a number never matches.
+    fn matches(&self, value: &str) -> bool {
+        let numbers = value
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .zip(self.operand.trim().parse::<f64>().ok());
+        match (self.op, numbers) {
+            (Op::Eq, Some((a, b))) => a == b,
+            (Op::Ne, Some((a, b))) => a != b,
+            (Op::Eq, None) => value == self.operand,
+            (Op::Ne, None) => value != self.operand,
+            (Op::Gt, Some((a, b))) => a > b,
+            (Op::Ge, Some((a, b))) => a >= b,
+            (Op::Lt, Some((a, b))) => a < b,
+            (Op::Le, Some((a, b))) => a <= b,
+            (_, None) => false,
+        }
+    }
+}
+
+/// Where a page starts: the data file and the record's index in it.
+#[derive(Debug, Clone, PartialEq)]
+struct Cursor {
+    file: String,
+    index: usize,
+}
+
+impl FromStr for Cursor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        s.rsplit_once(':')
+            .and_then(|(file, index)| {
+                Some(Cursor {
+                    file: file.to_string(),
+                    index: index.parse().ok()?,
+                })
+            })
+            .ok_or_else(|| format!("invalid cursor '{s}'"))
+    }
+}
+
+impl std::fmt::Display for Cursor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.file, self.index)
+    }
+}
+
+/// What a request for a job's records asks for, from its query string:
+/// `limit`, `cursor`, `fields` (comma-separated) and filters on
+/// `exit_code` and `value`.
+#[derive(Debug, PartialEq)]
+pub struct RecordQuery {
+    limit: usize,
+    cursor: Option<Cursor>,
+    fields: Option<Vec<String>>,
+    exit_code: Vec<Filter>,
+    value: Vec<Filter>,
+}
+
+impl Default for RecordQuery {
+    fn default() -> Self {
+        Self {
+            limit: DEFAULT_LIMIT,
+            cursor: None,
+            fields: None,
+            exit_code: Vec::new(),
+            value: Vec::new(),
+        }
+    }
+}
+
+impl RecordQuery {
+    /// Parse decoded query parameters. Unknown parameters are errors, so a
+    /// typo does not silently return everything.
+    pub fn from_params<'a>(
+        params: impl IntoIterator<Item = (&'a str, &'a str)>,
+    ) -> Result<Self, String> {
+        let mut query = RecordQuery::default();
+        for (key, value) in params {
+            match key {
+                "limit" => {
+                    query.limit = value
+                        .parse()
+                        .ok()
+                        .filter(|n| (1..=MAX_LIMIT).contains(n))
+                        .ok_or_else(|| format!("limit must be between 1 and {MAX_LIMIT}"))?;
+                }
+                "cursor" => query.cursor = Some(value.parse()?),
+                "fields" => {
+                    query.fields = Some(
+                        value
+                            .split(',')
+                            .filter(|f| !f.is_empty())
+                            .map(str::to_string)
+                            .collect(),
+                    )
+                }
+                "exit_code" => query.exit_code.push(value.parse()?),
+                "value" => query.value.push(value.parse()?),
+                _ => return Err(format!("unknown parameter '{key}'")),
+            }
+        }
+        Ok(query)
+    }
+
+    fn matches(&self, record: &Record) -> bool {
+        let exit_code = record.exit_code.to_string();
+        self.exit_code.iter().all(|f| f.matches(&exit_code))
+            && self.value.iter().all(|f| f.matches(&record.value))
+    }
+
+    /// The record as JSON, keeping only the selected fields.
+    fn select(&self, record: &Record) -> io::Result<Value> {
+        let Value::Object(all) = serde_json::to_value(record)? else {
+            unreachable!("records serialize to objects");
+        };
+        let Some(fields) = &self.fields else {
+            return Ok(Value::Object(all));
+        };
+        let mut selected = Map::new();
+        for field in fields {
+            if let Some(value) = all.get(field) {
+                selected.insert(field.clone(), value.clone());
+            }
+        }
+        Ok(Value::Object(selected))
+    }
+}
+
+/// One page of matching records, oldest first.
+#[derive(Debug, PartialEq)]
+pub struct Page {
+    pub records: Vec<Value>,
+    /// Pass as `cursor` to get the next page; `None` on the last page.
+    pub next_cursor: Option<String>,
+}
+
+/// Read matching records from `files` (oldest first), starting at the
+/// query's cursor, until the page is full. Files before the cursor's file
+/// are not read.
+pub fn page(files: &[DataFile], query: &RecordQuery) -> io::Result<Page> {
+    let mut records = Vec::new();
+    for file in files {
+        let name = file_name(file);
+        let start = match &query.cursor {
+            Some(cursor) if name.as_str() < cursor.file.as_str() => continue,
+            Some(cursor) if name == cursor.file => cursor.index,
+            _ => 0,
+        };
+        for (index, record) in storage::read_records(&file.path)?
+            .iter()
+            .enumerate()
+            .skip(start)
+        {
+            if record.is_marker() || !query.matches(record) {
+                continue;
+            }
+            if records.len() == query.limit {
+                let next = Cursor { file: name, index };
+                return Ok(Page {
+                    records,
+                    next_cursor: Some(next.to_string()),
+                });
+            }
+            records.push(query.select(record)?);
+        }
+    }
+    Ok(Page {
+        records,
+        next_cursor: None,
+    })
+}
+
+/// Data files are named `<date>.<ext>`, so their names sort like the files.
+fn file_name(file: &DataFile) -> String {
+    file.path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(params: &[(&str, &str)]) -> RecordQuery {
+        RecordQuery::from_params(params.iter().copied()).unwrap()
+    }
+
+    #[test]
+    fn pages_through_files_with_a_cursor() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("2025-01-01.csv"), "a,1,0\nb,2,1\nc,3,0\n").unwrap();
+        std::fs::write(dir.path().join("2025-01-02.csv"), "d,4,0\ne,5,0\n").unwrap();
+        let files = storage::list_data_files(dir.path()).unwrap();
+
+        let first = page(&files, &query(&[("limit", "2"), ("fields", "value")])).unwrap();
+        assert_eq!(
+            first.records,
+            [
+                serde_json::json!({"value": "1"}),
+                serde_json::json!({"value": "2"})
+            ]
+        );
+        let cursor = first.next_cursor.unwrap();
+        assert_eq!(cursor, "2025-01-01.csv:2");
+        let second = page(&files, &query(&[("limit", "2"), ("cursor", &cursor)])).unwrap();
+        assert_eq!(second.records.len(), 2);
+        assert_eq!(second.records[1]["timestamp"], "d");
+        let last = page(
+            &files,
+            &query(&[("cursor", second.next_cursor.as_deref().unwrap())]),
+        )
+        .unwrap();
+        assert_eq!(last.records.len(), 1);
+        assert_eq!(last.next_cursor, None);
+
+        let failed = page(&files, &query(&[("exit_code", "ne:0")])).unwrap();
+        assert_eq!(failed.records.len(), 1);
+        let high = page(&files, &query(&[("value", "gt:2"), ("value", "le:4")])).unwrap();
+        assert_eq!(high.records.len(), 2);
+    }
+
+    #[test]
+    fn parses_filters_and_rejects_bad_parameters() {
+        let f: Filter = "ge:90".parse().unwrap();
+        assert!(f.matches("90") && f.matches("100.5") && !f.matches("8") && !f.matches("n/a"));
+        let f: Filter = "ok".parse().unwrap();
+        assert!(f.matches("ok") && !f.matches("ko"));
+        assert!(RecordQuery::from_params([("limit", "0")]).is_err());
+        assert!(RecordQuery::from_params([("cursor", "nope")]).is_err());
+        assert!(RecordQuery::from_params([("exitcode", "0")]).is_err());
+    }
+}
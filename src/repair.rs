@@ -0,0 +1,336 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use chrono::DateTime;
+
+use crate::check::{self, Previous};
+use crate::cli::RepairOpts;
+use crate::storage::{self, Record};
+
+/// What was changed in one file.
+#[derive(Debug, Default, PartialEq)]
+struct Fixes {
+    /// Header rows after the first, e.g. from two writers both seeing an
+    /// empty file.
+    headers_removed: usize,
+    /// A cut-off last line that was dropped.
+    incomplete_dropped: bool,
+    /// A complete last line that only lacked its line ending.
+    newline_added: bool,
+    /// Rows padded with empty cells to the file's widest row.
+    rows_padded: usize,
+    /// Columns added to the header for rows wider than it.
+    header_extended: usize,
+    /// Rows or lines that could not be read at all.
+    rows_dropped: usize,
+}
+
+impl Fixes {
+    fn is_empty(&self) -> bool {
+        *self == Fixes::default()
+    }
+
+    fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if self.headers_removed > 0 {
+            parts.push(format!(
+                "removed {} extra header rows",
+                self.headers_removed
+            ));
+        }
+        if self.incomplete_dropped {
+            parts.push("dropped the incomplete last line".to_string());
+        }
+        if self.newline_added {
+            parts.push("ended the last line".to_string());
+        }
+        if self.header_extended > 0 {
+            parts.push(format!("added {} header columns", self.header_extended));
+        }
+        if self.rows_padded > 0 {
+            parts.push(format!("padded {} short rows", self.rows_padded));
+        }
+        if self.rows_dropped > 0 {
+            parts.push(format!("dropped {} unreadable rows", self.rows_dropped));
+        }
+        parts.join(", ")
+    }
+}
+
+pub fn repair(opts: RepairOpts) -> io::Result<()> {
+    let files: Vec<_> = storage::job_data_files(&opts.name)?
+        .into_iter()
+        .filter(|f| !f.is_database() && !f.is_compressed())
+        .collect();
+    // Hold the job's lock so a running job cannot append while files are rewritten.
+    let Some(_lock) = storage::try_lock(&storage::lock_path(&opts.name))? else {
+        return Err(io::Error::new(
+            io::ErrorKind::WouldBlock,
+            format!(
+                "job '{}' is running; stop it before repairing its files",
+                opts.name
+            ),
+        ));
+    };
+    let mut repaired = 0;
+    for file in &files {
+        let contents = fs::read_to_string(&file.path).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!(
+                    "cannot read {}: {e}",
+                    dunce::simplified(&file.path).display()
+                ),
+            )
+        })?;
+        let (fixed, fixes) = if file.path.extension().is_some_and(|e| e == "jsonl") {
+            repair_jsonl(&contents)
+        } else {
+            repair_csv(&contents)?
+        };
+        if fixes.is_empty() {
+            continue;
+        }
+        repaired += 1;
+        if opts.dry_run {
+            println!(
+                "Would repair {}: {}",
+                dunce::simplified(&file.path).display(),
+                fixes.describe()
+            );
+            continue;
+        }
+        let backup = backup_path(&file.path);
+        fs::copy(&file.path, &backup)?;
+        let tmp = file.path.with_extension("repair.tmp");
+        fs::write(&tmp, fixed)?;
+        fs::rename(&tmp, &file.path)?;
+        println!(
+            "Repaired {}: {} (backup: {})",
+            dunce::simplified(&file.path).display(),
+            fixes.describe(),
+            dunce::simplified(&backup).display()
+        );
+    }
+    println!(
+        "{} {repaired} of {} files of '{}'",
+        if opts.dry_run {
+            "Would repair"
+        } else {
+            "Repaired"
+        },
+        files.len(),
+        opts.name
+    );
+    if !opts.dry_run {
+        let mut previous = Previous::default();
+        let mut remaining = 0;
+        for file in &files {
+            remaining += check::check_file(&file.path, &mut previous)?.1.len();
+        }
+        if remaining > 0 {
+            println!(
+                "{remaining} problems need manual attention; see `trep validate {}`",
+                opts.name
+            );
+        }
+    }
+    Ok(())
+}
+
+/// `<file>.bak`, or `<file>.bak.N` if earlier backups exist.
+fn backup_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".bak");
+    let first = PathBuf::from(&name);
+    if !first.exists() {
+        return first;
+    }
+    (1..)
+        .map(|n| {
+            let mut numbered = name.clone();
+            numbered.push(format!(".{n}"));
+            PathBuf::from(numbered)
+        })
+        .find(|p| !p.exists())
+        .expect("some backup number is free")
+}
+
+/// Rewrite a CSV data file with a single leading header (if it had one),
+/// without cut-off or unreadable rows, and with every row as wide as the
+/// widest. The byte order mark and line endings are kept.
+fn repair_csv(contents: &str) -> io::Result<(String, Fixes)> {
+    let mut fixes = Fixes::default();
+    let bom = contents.starts_with('\u{feff}');
+    let body = contents.strip_prefix('\u{feff}').unwrap_or(contents);
+    let incomplete = !body.is_empty() && !body.ends_with('\n');
+    let mut rdr = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(body.as_bytes());
+    let mut header: Option<csv::StringRecord> = None;
+    let mut rows = Vec::new();
+    for row in rdr.records() {
+        let Ok(row) = row else {
+            fixes.rows_dropped += 1;
+            continue;
+        };
+        if row.get(0) == Some("timestamp") {
+            if header.is_some() || !rows.is_empty() {
+                fixes.headers_removed += 1;
+            }
+            header.get_or_insert(row);
+            continue;
+        }
+        rows.push(row);
+    }
+    if incomplete {
+        let width = header.as_ref().or(rows.first()).map(|r| r.len());
+        let complete = rows.last().is_some_and(|last| {
+            Some(last.len()) == width
+                && last[2].parse::<i32>().is_ok()
+                && DateTime::parse_from_rfc3339(&last[0]).is_ok()
+        });
+        if complete {
+            fixes.newline_added = true;
+        } else if rows.pop().is_some() {
+            fixes.incomplete_dropped = true;
+        }
+    }
+    let before = rows.len();
+    rows.retain(|r| r.len() >= 3);
+    fixes.rows_dropped += before - rows.len();
+
+    let width = rows
+        .iter()
+        .chain(header.as_ref())
+        .map(|r| r.len())
+        .max()
+        .unwrap_or(0);
+    let terminator = if body.contains("\r\n") {
+        csv::Terminator::CRLF
+    } else {
+        csv::Terminator::Any(b'\n')
+    };
+    let mut wtr = csv::WriterBuilder::new()
+        .terminator(terminator)
+        .flexible(true)
+        .from_writer(Vec::new());
+    if let Some(mut header) = header {
+        for col in header.len()..width {
+            header.push_field(&format!("col{}", col + 1));
+            fixes.header_extended += 1;
+        }
+        wtr.write_record(&header)?;
+    }
+    for mut row in rows {
+        if row.len() < width {
+            fixes.rows_padded += 1;
+            while row.len() < width {
+                row.push_field("");
+            }
+        }
+        wtr.write_record(&row)?;
+    }
+    let mut out = if bom {
+        "\u{feff}".to_string()
+    } else {
+        String::new()
+    };
+    let written = wtr
+        .into_inner()
+        .map_err(|e| io::Error::other(e.to_string()))?;
+    out.push_str(&String::from_utf8_lossy(&written));
+    Ok((out, fixes))
+}
+
+/// Drop JSONL lines that are not complete records, including a cut-off last
+/// line, and end the file with a newline. Valid lines are kept verbatim.
+fn repair_jsonl(contents: &str) -> (String, Fixes) {
+    let mut fixes = Fixes::default();
+    let lines: Vec<&str> = contents.lines().filter(|l| !l.trim().is_empty()).collect();
+    let last = lines.len().saturating_sub(1);
+    let incomplete = !contents.is_empty() && !contents.ends_with('\n');
+    let mut out = String::new();
+    for (i, line) in lines.into_iter().enumerate() {
+        let valid = serde_json::from_str::<Record>(line).is_ok();
+        match (valid, incomplete && i == last) {
+            (true, true) => fixes.newline_added = true,
+            (true, false) => {}
+            (false, true) => {
+                fixes.incomplete_dropped = true;
+                continue;
+            }
+            (false, false) => {
+                fixes.rows_dropped += 1;
+                continue;
+            }
+        }
+        out.push_str(line);
+        out.push('\n');
+    }
+    (out, fixes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repairs_csv_corruptions() {
+        let (fixed, fixes) = repair_csv(
+            "\u{feff}timestamp,value,exit_code,unit\r\n\
+             timestamp,value,exit_code,unit\r\n\
+             2025-01-01T00:00:00Z,1,0,s\r\n\
+             2025-01-01T00:01:00Z,2,0\r\n\
+             2025-01-01T00:02:00Z,3,0,s,extra\r\n\
+             2025-01-01T00:03:00Z,4",
+        )
+        .unwrap();
+        assert_eq!(
+            fixed,
+            "\u{feff}timestamp,value,exit_code,unit,col5\r\n\
+             2025-01-01T00:00:00Z,1,0,s,\r\n\
+             2025-01-01T00:01:00Z,2,0,,\r\n\
+             2025-01-01T00:02:00Z,3,0,s,extra\r\n"
+        );
+        assert_eq!(
+            fixes,
+            Fixes {
+                headers_removed: 1,
+                incomplete_dropped: true,
+                rows_padded: 2,
+                header_extended: 1,
+                ..Fixes::default()
+            }
+        );
+
+        let clean = "t,1,0\nt,2,0\n";
+        assert!(repair_csv(clean).unwrap().1.is_empty());
+        let (fixed, fixes) = repair_csv("t,1,0\n2025-01-01T00:00:00Z,2,0").unwrap();
+        assert_eq!(fixed, "t,1,0\n2025-01-01T00:00:00Z,2,0\n");
+        assert!(fixes.newline_added);
+    }
+
+    #[test]
+    fn repairs_jsonl_corruptions() {
+        let good = r#"{"timestamp":"2025-01-01T00:00:00Z","value":"1","exit_code":0}"#;
+        let contents = format!("{good}\n{{\"timest{good}\n{{\"timestamp\":\"2025");
+        let (fixed, fixes) = repair_jsonl(&contents);
+        assert_eq!(fixed, format!("{good}\n"));
+        assert_eq!(
+            fixes.describe(),
+            "dropped the incomplete last line, dropped 1 unreadable rows"
+        );
+    }
+
+    #[test]
+    fn backups_do_not_overwrite_each_other() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("2025-01-01.csv");
+        assert_eq!(backup_path(&path), dir.path().join("2025-01-01.csv.bak"));
+        fs::write(dir.path().join("2025-01-01.csv.bak"), "").unwrap();
+        assert_eq!(backup_path(&path), dir.path().join("2025-01-01.csv.bak.1"));
+    }
+}
@@ -0,0 +1,347 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use std::fmt::Write;
+
+use chrono::{DateTime, NaiveDate};
+use serde_json::{json, Value};
+
+use crate::cli::{ReportFormat, ReportOpts};
+use crate::coverage;
+use crate::storage::{self, DataFile, Record};
+
+/// Timestamp format of the chart data file: local time as recorded, which
+/// both Vega-Lite and gnuplot's `timefmt` parse without extra configuration.
+const DATA_TIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+pub fn report(opts: ReportOpts) -> io::Result<()> {
+    let ReportOpts {
+        name,
+        vega,
+        gnuplot,
+        data,
+        since,
+        until,
+        failed_only,
+        format,
+    } = opts;
+    let files = storage::job_data_files(&name)?;
+    let records = select_records(&files, since, until, failed_only)?;
+    let Some(first_spec) = vega.as_ref().or(gnuplot.as_ref()) else {
+        return print_records(&records, format);
+    };
+    let data = data.unwrap_or_else(|| first_spec.with_extension("csv"));
+    let points = write_chart_data(&data, &records)?;
+    println!("Wrote {points} data points to {}", data.display());
+    if let Some(path) = &vega {
+        let spec = vega_lite_spec(&name, &data_ref(path, &data));
+        std::fs::write(path, serde_json::to_string_pretty(&spec)? + "\n")?;
+        println!("Wrote Vega-Lite spec to {}", path.display());
+    }
+    if let Some(path) = &gnuplot {
+        std::fs::write(path, gnuplot_script(&name, &data_ref(path, &data)))?;
+        println!("Wrote gnuplot script to {}", path.display());
+    }
+    Ok(())
+}
+
+/// Read the records of `files` from `since` through `until` (local days),
+/// merged in timestamp order. Records whose timestamp does not parse are
+/// kept only when no range is given.
+pub(crate) fn select_records(
+    files: &[DataFile],
+    since: Option<NaiveDate>,
+    until: Option<NaiveDate>,
+    failed_only: bool,
+) -> io::Result<Vec<Record>> {
+    let from = since.map(coverage::start_of_day);
+    let to = until.and_then(|d| d.succ_opt()).map(coverage::start_of_day);
+    let mut records = Vec::new();
+    for file in files {
+        // Day files are named by local day; allow a day either side for
+        // records written across midnight.
+        let day = chrono::Days::new(1);
+        let outside = since.is_some_and(|d| file.date < d - day)
+            || until.is_some_and(|d| file.date > d + day);
+        if outside && !file.is_database() {
+            continue;
+        }
+        for record in storage::read_records(&file.path)? {
+            if record.is_marker() || failed_only && record.exit_code == 0 {
+                continue;
+            }
+            let at = DateTime::parse_from_rfc3339(&record.timestamp).ok();
+            let in_range = match at {
+                Some(at) => from.is_none_or(|f| at >= f) && to.is_none_or(|t| at < t),
+                None => from.is_none() && to.is_none(),
+            };
+            if in_range {
+                records.push((at, record));
+            }
+        }
+    }
+    records.sort_by_key(|(at, _)| *at);
+    Ok(records.into_iter().map(|(_, record)| record).collect())
+}
+
+fn print_records(records: &[Record], format: ReportFormat) -> io::Result<()> {
+    match format {
+        ReportFormat::Table if records.is_empty() => println!("No matching records"),
+        ReportFormat::Table => print!("{}", table(records)),
+        ReportFormat::Csv => {
+            let mut wtr = csv::Writer::from_writer(io::stdout().lock());
+            for row in rows(records) {
+                wtr.write_record(&row)?;
+            }
+            wtr.flush()?;
+        }
+        ReportFormat::Json => println!("{}", serde_json::to_string_pretty(records)?),
+    }
+    Ok(())
+}
+
+/// The header and one row per record: the core columns, then every extra
+/// field in the order they first appear.
+fn rows(records: &[Record]) -> Vec<Vec<String>> {
+    let mut header = vec![
+        "timestamp".to_string(),
+        "value".to_string(),
+        "exit_code".to_string(),
+    ];
+    for record in records {
+        for field in record.fields.keys() {
+            if !header.contains(field) {
+                header.push(field.clone());
+            }
+        }
+    }
+    let mut rows = Vec::with_capacity(records.len() + 1);
+    for record in records {
+        let mut row = vec![
+            record.timestamp.clone(),
+            record.value.clone(),
+            record.exit_code.to_string(),
+        ];
+        row.extend(header[3..].iter().map(|f| {
+            record
+                .fields
+                .get(f)
+                .map(storage::field_to_string)
+                .unwrap_or_default()
+        }));
+        rows.push(row);
+    }
+    rows.insert(0, header);
+    rows
+}
+
+/// Rows as left-aligned columns separated by two spaces. Line breaks in
+/// values are shown as spaces so each record stays on one line.
+fn table(records: &[Record]) -> String {
+    let rows: Vec<Vec<String>> = rows(records)
+        .into_iter()
+        .map(|row| {
+            row.into_iter()
+                .map(|c| c.replace(['\n', '\r'], " "))
+                .collect()
+        })
+        .collect();
+    let mut widths = vec![0; rows[0].len()];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let mut out = String::new();
+    for row in &rows {
+        let last = row.len() - 1;
+        for (i, cell) in row.iter().enumerate() {
+            if i == last {
+                out.push_str(cell);
+            } else {
+                let _ = write!(out, "{cell:<width$}  ", width = widths[i]);
+            }
+        }
+        out.truncate(out.trim_end().len());
+        out.push('\n');
+    }
+    out
+}
+
+/// How a spec at `spec` should refer to the data file: by file name when
+/// they share a directory, so the pair can be moved together, otherwise by
+/// absolute path.
+fn data_ref(spec: &Path, data: &Path) -> String {
+    if spec.parent() == data.parent() {
+        if let Some(name) = data.file_name() {
+            return name.to_string_lossy().into_owned();
+        }
+    }
+    std::path::absolute(data)
+        .unwrap_or_else(|_| PathBuf::from(data))
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Write the plottable records (successful runs with a numeric value) as a
+/// `timestamp,value` CSV file. Returns the number of rows written.
+fn write_chart_data(path: &Path, records: &[Record]) -> io::Result<usize> {
+    let mut wtr = csv::Writer::from_path(path)?;
+    wtr.write_record(["timestamp", "value"])?;
+    let mut rows = 0;
+    for record in records.iter().filter(|r| r.exit_code == 0) {
+        let (Ok(at), Ok(value)) = (
+            DateTime::parse_from_rfc3339(&record.timestamp),
+            record.value.trim().parse::<f64>(),
+        ) else {
+            continue;
+        };
+        if !value.is_finite() {
+            continue;
+        }
+        let at = at.naive_local().format(DATA_TIME_FORMAT).to_string();
+        wtr.write_record([at, value.to_string()])?;
+        rows += 1;
+    }
+    wtr.flush()?;
+    Ok(rows)
+}
+
+/// A Vega-Lite line chart of `value` over time, loading `data_url`.
+fn vega_lite_spec(name: &str, data_url: &str) -> Value {
+    json!({
+        "$schema": "https://vega.github.io/schema/vega-lite/v5.json",
+        "title": name,
+        "width": 800,
+        "height": 300,
+        "data": {
+            "url": data_url,
+            "format": {
+                "type": "csv",
+                "parse": { "timestamp": "date", "value": "number" }
+            }
+        },
+        "mark": { "type": "line", "tooltip": true },
+        "encoding": {
+            "x": { "field": "timestamp", "type": "temporal", "title": "time" },
+            "y": { "field": "value", "type": "quantitative", "title": name }
+        }
+    })
+}
+
+/// A gnuplot script plotting `value` over time from `data_file`.
+fn gnuplot_script(name: &str, data_file: &str) -> String {
+    let quote = |s: &str| format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""));
+    format!(
+        "# trep report for job {title}\n\
+         # Interactive: gnuplot -p <this file>\n\
+         # PNG: gnuplot -e \"set terminal png size 1000,400; set output 'chart.png'\" <this file>\n\
+         set datafile separator \",\"\n\
+         set xdata time\n\
+         set timefmt \"{timefmt}\"\n\
+         set format x \"%m-%d\\n%H:%M\"\n\
+         set title {title}\n\
+         set ylabel {title}\n\
+         set grid\n\
+         set key off\n\
+         plot {data} using 1:2 every ::1 with lines\n",
+        title = quote(name),
+        timefmt = DATA_TIME_FORMAT,
+        data = quote(data_file),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chart_data_keeps_numeric_successes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.csv");
+        let records = [
+            Record::new("2025-01-01T09:00:00+09:00", "1.5", 0),
+            Record::new("2025-01-01T09:01:00+09:00", "timeout", -1),
+            Record::new("2025-01-01T09:02:00+09:00", "n/a", 0),
+            Record::new("2025-01-01T09:03:00+09:00", "2", 0),
+        ];
+        assert_eq!(write_chart_data(&path, &records).unwrap(), 2);
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "timestamp,value\n2025-01-01T09:00:00,1.5\n2025-01-01T09:03:00,2\n"
+        );
+    }
+
+    #[test]
+    fn selects_records_in_timestamp_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let day = |d: u32, h: u32| {
+            coverage::start_of_day(NaiveDate::from_ymd_opt(2025, 1, d).unwrap())
+                + chrono::Duration::hours(h.into())
+        };
+        let line = |at: DateTime<chrono::Local>, value: &str, code: i32| {
+            format!("{},{value},{code}\n", at.to_rfc3339())
+        };
+        std::fs::write(
+            dir.path().join("2025-01-01.csv"),
+            line(day(1, 10), "b", 0) + line(day(1, 9), "a", 1).as_str(),
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("2025-01-02.csv"),
+            line(day(2, 0), "c", 2) + line(day(2, 12), "d", 0).as_str(),
+        )
+        .unwrap();
+        let files = storage::list_data_files(dir.path()).unwrap();
+        let values = |records: Vec<Record>| -> Vec<String> {
+            records.into_iter().map(|r| r.value).collect()
+        };
+
+        let all = select_records(&files, None, None, false).unwrap();
+        assert_eq!(values(all), ["a", "b", "c", "d"]);
+        let since = NaiveDate::from_ymd_opt(2025, 1, 2);
+        assert_eq!(
+            values(select_records(&files, since, None, false).unwrap()),
+            ["c", "d"]
+        );
+        let until = NaiveDate::from_ymd_opt(2025, 1, 1);
+        assert_eq!(
+            values(select_records(&files, None, until, true).unwrap()),
+            ["a"]
+        );
+        assert_eq!(
+            values(select_records(&files, None, None, true).unwrap()),
+            ["a", "c"]
+        );
+    }
+
+    #[test]
+    fn prints_records_as_a_table() {
+        let mut slow = Record::new("2025-01-01T09:00:01+00:00", "line\nbreak", 1);
+        slow.fields.insert("slow".into(), true.into());
+        let records = [Record::new("2025-01-01T09:00:00+00:00", "42", 0), slow];
+        assert_eq!(
+            table(&records),
+            "timestamp                  value       exit_code  slow\n\
+             2025-01-01T09:00:00+00:00  42          0\n\
+             2025-01-01T09:00:01+00:00  line break  1          true\n"
+        );
+    }
+
+    #[test]
+    fn specs_reference_the_data_file() {
+        let spec = vega_lite_spec("cpu", "cpu.csv");
+        assert_eq!(spec["data"]["url"], "cpu.csv");
+        assert_eq!(spec["encoding"]["x"]["type"], "temporal");
+
+        let script = gnuplot_script("say \"hi\"", "cpu.csv");
+        assert!(script.contains("set title \"say \\\"hi\\\"\"\n"));
+        assert!(script.contains("plot \"cpu.csv\" using 1:2"));
+
+        assert_eq!(
+            data_ref(Path::new("out/a.json"), Path::new("out/a.csv")),
+            "a.csv"
+        );
+        assert!(Path::new(&data_ref(Path::new("a.json"), Path::new("/tmp/a.csv"))).is_absolute());
+    }
+}
@@ -0,0 +1,345 @@
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{DateTime, Days, Local, NaiveDate};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+use crate::storage::DATABASE_FILE;
+use crate::{coverage, sqlite};
+
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// How long a job keeps its records, and whether past day files are
+/// gzipped. Today's files are never touched, as the job still appends to
+/// them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Retention {
+    /// Keep this many days, today included; older day files are deleted
+    /// and older records removed from the database.
+    pub days: Option<u32>,
+    /// Keep the day files of this many most recent days that have any.
+    pub files: Option<u32>,
+    /// Gzip the day files of past days.
+    pub compress: bool,
+}
+
+/// One step of applying a [`Retention`] to a data directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    /// Replace a day file with `<file>.gz`.
+    Compress(PathBuf),
+    /// Delete a day file.
+    Delete(PathBuf),
+    /// Delete the records of the database that are older than `before`.
+    Trim {
+        path: PathBuf,
+        before: DateTime<Local>,
+    },
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Compress(path) => write!(f, "compress {}", dunce::simplified(path).display()),
+            Action::Delete(path) => write!(f, "delete {}", dunce::simplified(path).display()),
+            Action::Trim { path, before } => write!(
+                f,
+                "delete records before {} from {}",
+                before.date_naive(),
+                dunce::simplified(path).display()
+            ),
+        }
+    }
+}
+
+/// A day file in a data directory: records or rejects, gzipped or not.
+struct DayFile {
+    date: NaiveDate,
+    path: PathBuf,
+    compressed: bool,
+}
+
+impl Retention {
+    /// The policy of the `--retain`, `--retain-files` and `--compress`
+    /// options. `--retain` must be a whole number of days, such as "30d".
+    pub fn from_opts(retain: Option<&str>, files: Option<u32>, compress: bool) -> io::Result<Self> {
+        let days = match retain {
+            Some(s) => Some(days_arg(s)?),
+            None => None,
+        };
+        Ok(Self {
+            days,
+            files,
+            compress,
+        })
+    }
+
+    /// Whether the policy keeps everything as it is.
+    pub fn is_empty(&self) -> bool {
+        self.days.is_none() && self.files.is_none() && !self.compress
+    }
+
+    /// What applying the policy to the data directory `dir` on `today`
+    /// would do: day files first, oldest first, then the database.
+    pub fn plan(&self, dir: &Path, today: NaiveDate) -> io::Result<Vec<Action>> {
+        let files = day_files(dir)?;
+        // The oldest day kept by each limit; files of earlier days go.
+        let by_days = self
+            .days
+            .and_then(|days| today.checked_sub_days(Days::new(u64::from(days).saturating_sub(1))));
+        let by_files = self.files.and_then(|n| {
+            let mut dates: Vec<NaiveDate> = files.iter().map(|f| f.date).collect();
+            dates.dedup();
+            dates
+                .iter()
+                .rev()
+                .nth((n as usize).saturating_sub(1))
+                .copied()
+        });
+        let keep_from = by_days.max(by_files);
+        let mut actions = Vec::new();
+        for file in files {
+            if file.date >= today {
+                continue;
+            }
+            if keep_from.is_some_and(|from| file.date < from) {
+                actions.push(Action::Delete(file.path));
+            } else if self.compress && !file.compressed {
+                actions.push(Action::Compress(file.path));
+            }
+        }
+        let database = dir.join(DATABASE_FILE);
+        if let Some(from) = by_days.filter(|_| database.is_file()) {
+            actions.push(Action::Trim {
+                path: database,
+                before: coverage::start_of_day(from),
+            });
+        }
+        Ok(actions)
+    }
+
+    /// Apply the policy to the data directory `dir` on `today`, logging
+    /// each step. A running job calls this when the day rotates; failures
+    /// are only logged so the job keeps collecting.
+    pub fn enforce(&self, dir: &Path, today: NaiveDate) {
+        let actions = match self.plan(dir, today) {
+            Ok(actions) => actions,
+            Err(e) => {
+                tracing::warn!(error = %e, "could not apply the retention policy");
+                return;
+            }
+        };
+        for action in &actions {
+            match execute(action) {
+                Ok(0) => {}
+                Ok(removed) => tracing::info!(%action, removed, "retention"),
+                Err(e) => tracing::warn!(%action, error = %e, "retention step failed"),
+            }
+        }
+    }
+}
+
+/// Carry out one step. Returns the number of records removed from the
+/// database, or 1 for a file.
+pub fn execute(action: &Action) -> io::Result<usize> {
+    match action {
+        Action::Compress(path) => compress(path).map(|()| 1),
+        Action::Delete(path) => fs::remove_file(path).map(|()| 1),
+        Action::Trim { path, before } => sqlite::delete_before(path, *before),
+    }
+}
+
+/// The number of records an [`Action::Trim`] would remove, for a dry run.
+pub fn count_trimmed(path: &Path, before: DateTime<Local>) -> io::Result<usize> {
+    sqlite::count_before(path, before)
+}
+
+/// Gzip `path` to `<path>.gz` and delete it. Should the archive already
+/// exist (records written to the day after it was compressed), the file is
+/// appended to it as another gzip member.
+fn compress(path: &Path) -> io::Result<()> {
+    let mut target = path.as_os_str().to_owned();
+    target.push(".gz");
+    let out = OpenOptions::new().create(true).append(true).open(&target)?;
+    let mut encoder = GzEncoder::new(out, Compression::default());
+    io::copy(&mut File::open(path)?, &mut encoder)?;
+    encoder.finish()?.sync_all()?;
+    fs::remove_file(path)
+}
+
+/// The day files in `dir`, oldest first: `<date>.csv`, `<date>.jsonl` and
+/// their rejects twins, each possibly gzipped.
+fn day_files(dir: &Path) -> io::Result<Vec<DayFile>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        let (name, compressed) = match name.strip_suffix(".gz") {
+            Some(name) => (name, true),
+            None => (name, false),
+        };
+        let Some((date, kind)) = name.split_once('.') else {
+            continue;
+        };
+        if !["csv", "jsonl", "rejects.csv", "rejects.jsonl"].contains(&kind) {
+            continue;
+        }
+        if let Ok(date) = NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+            files.push(DayFile {
+                date,
+                path,
+                compressed,
+            });
+        }
+    }
+    files.sort_by(|a, b| (a.date, &a.path).cmp(&(b.date, &b.path)));
+    Ok(files)
+}
+
+/// Parse a retention period such as "30d" into whole days.
+fn days_arg(s: &str) -> io::Result<u32> {
+    let period = humantime::parse_duration(s).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid retention '{s}': {e}"),
+        )
+    })?;
+    let days = period.as_secs() / DAY.as_secs();
+    if period.is_zero() || period.as_nanos() % DAY.as_nanos() != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid retention '{s}': must be a whole number of days, such as 30d"),
+        ));
+    }
+    u32::try_from(days).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid retention '{s}': too long"),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{self, Record};
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    fn touch(dir: &Path, name: &str) -> PathBuf {
+        let path = dir.join(name);
+        fs::write(&path, "2025-01-01T00:00:00Z,1,0\n").unwrap();
+        path
+    }
+
+    #[test]
+    fn parses_whole_days() {
+        assert_eq!(days_arg("30d").unwrap(), 30);
+        assert_eq!(days_arg("2weeks").unwrap(), 14);
+        assert_eq!(days_arg("48h").unwrap(), 2);
+        for bad in ["0d", "36h", "soon"] {
+            let err = days_arg(bad).unwrap_err().to_string();
+            assert!(
+                err.starts_with(&format!("invalid retention '{bad}'")),
+                "{err}"
+            );
+        }
+    }
+
+    #[test]
+    fn plans_deletions_and_compression() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        let old = touch(dir, "2025-01-01.csv");
+        let old_rejects = touch(dir, "2025-01-01.rejects.csv");
+        let kept = touch(dir, "2025-01-03.csv");
+        let zipped = touch(dir, "2025-01-04.csv.gz");
+        let yesterday = touch(dir, "2025-01-05.csv");
+        let today = touch(dir, "2025-01-06.csv");
+        touch(dir, "notes.csv");
+        let on = date("2025-01-06");
+
+        let by_days = Retention {
+            days: Some(4),
+            ..Retention::default()
+        };
+        assert_eq!(
+            by_days.plan(dir, on).unwrap(),
+            [
+                Action::Delete(old.clone()),
+                Action::Delete(old_rejects.clone())
+            ]
+        );
+
+        // The three most recent days with files, today included.
+        let by_files = Retention {
+            files: Some(3),
+            compress: true,
+            ..Retention::default()
+        };
+        assert_eq!(
+            by_files.plan(dir, on).unwrap(),
+            [
+                Action::Delete(old),
+                Action::Delete(old_rejects),
+                Action::Delete(kept),
+                Action::Compress(yesterday.clone()),
+            ]
+        );
+        assert!(!by_files
+            .plan(dir, on)
+            .unwrap()
+            .contains(&Action::Delete(zipped)));
+
+        // Today's file is never touched, even with a one-day window.
+        let one_day = Retention {
+            days: Some(1),
+            compress: true,
+            ..Retention::default()
+        };
+        let plan = one_day.plan(dir, on).unwrap();
+        assert!(plan.contains(&Action::Delete(yesterday)));
+        assert!(!plan
+            .iter()
+            .any(|a| matches!(a, Action::Delete(p) | Action::Compress(p) if *p == today)));
+    }
+
+    #[test]
+    fn compressed_files_read_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("2025-01-01.jsonl");
+        let mut writer = storage::RecordWriter::new("jsonl", storage::CsvOptions::default());
+        writer
+            .write(&path, &Record::new("2025-01-01T00:00:00Z", "1", 0))
+            .unwrap();
+        writer.reopen();
+        execute(&Action::Compress(path.clone())).unwrap();
+        // A late record for the day goes to a new plain file, which is
+        // appended to the archive on the next rotation.
+        writer
+            .write(&path, &Record::new("2025-01-01T23:59:59Z", "2", 0))
+            .unwrap();
+        writer.reopen();
+        execute(&Action::Compress(path.clone())).unwrap();
+        assert!(!path.exists());
+
+        let files = storage::list_data_files(dir.path()).unwrap();
+        assert_eq!(files.len(), 1);
+        assert!(files[0].is_compressed());
+        assert_eq!(files[0].date, date("2025-01-01"));
+        let values: Vec<_> = storage::read_records(&files[0].path)
+            .unwrap()
+            .into_iter()
+            .map(|r| r.value)
+            .collect();
+        assert_eq!(values, ["1", "2"]);
+    }
+}
@@ -0,0 +1,326 @@
+use std::io;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Local, NaiveDate};
+use croner::Cron;
+
+use crate::coverage;
+
+/// The largest backward step of the wall clock that does not move the day
+/// file back to the previous day or repeat a wall clock run: DST shifts and
+/// NTP corrections stay well below it, while a clock that was set days
+/// ahead is still corrected.
+const MAX_STEP_BACK: Duration = Duration::from_secs(2 * 3600);
+
+/// The shortest interval `--align` accepts. Shorter ones cannot be told
+/// apart on the wall clock, and would need more steps to reach the end of
+/// a day than the run times are counted in.
+const MIN_ALIGN: Duration = Duration::from_millis(1);
+
+/// Start-to-start spacing of runs on the monotonic clock, so wall clock
+/// changes (DST, NTP corrections, manual resets) neither shorten nor
+/// stretch an interval. The monotonic clock does not advance while the
+/// machine is suspended, so a suspended job continues its interval after
+/// resuming instead of running a burst of missed ticks.
+#[derive(Debug)]
+pub struct IntervalSchedule {
+    interval: Duration,
+    next: Instant,
+}
+
+impl IntervalSchedule {
+    /// A schedule whose first run is due at `start`.
+    pub fn new(start: Instant, interval: Duration) -> Self {
+        Self {
+            interval,
+            next: start,
+        }
+    }
+
+    /// Advance past the run that just finished at `now` and return when the
+    /// next one is due, with how many ticks were skipped because the run
+    /// overran them. Missed ticks are skipped, not caught up.
+    pub fn advance(&mut self, now: Instant) -> (Instant, u32) {
+        let mut skipped = 0;
+        self.next += self.interval;
+        while self.next < now && !self.interval.is_zero() {
+            self.next += self.interval;
+            skipped += 1;
+        }
+        (self.next, skipped)
+    }
+}
+
+/// When a job runs: every interval on the monotonic clock, or at wall clock
+/// times (a cron expression or an interval aligned to its boundaries).
+#[derive(Debug)]
+pub enum Schedule {
+    Every(IntervalSchedule),
+    Wall(WallSchedule),
+}
+
+impl Schedule {
+    /// Whether the first run waits for the schedule, rather than happening
+    /// at once.
+    pub fn waits_for_first_run(&self) -> bool {
+        matches!(self, Schedule::Wall(_))
+    }
+
+    /// Time left at `now` / `wall` until the next run is due.
+    pub fn until_next(&self, now: Instant, wall: DateTime<Local>) -> Duration {
+        match self {
+            Schedule::Every(every) => every.next.saturating_duration_since(now),
+            Schedule::Wall(times) => (times.next - wall).to_std().unwrap_or_default(),
+        }
+    }
+
+    /// Move past the run that just finished and return how many runs were
+    /// skipped because it overran them.
+    pub fn advance(&mut self, now: Instant, wall: DateTime<Local>) -> u32 {
+        match self {
+            Schedule::Every(every) => every.advance(now).1,
+            Schedule::Wall(times) => times.advance(wall),
+        }
+    }
+}
+
+/// Run times on the wall clock, following it when it changes.
+#[derive(Debug)]
+pub struct WallSchedule {
+    times: WallTimes,
+    next: DateTime<Local>,
+}
+
+#[derive(Debug)]
+enum WallTimes {
+    Cron(Box<Cron>),
+    /// Multiples of the interval from local midnight.
+    Aligned(Duration),
+}
+
+impl WallSchedule {
+    /// Runs at the times of a 5-field cron expression (or 6 with seconds
+    /// first), in local time.
+    pub fn cron(expr: &str, now: DateTime<Local>) -> io::Result<Self> {
+        let cron: Cron = expr.parse().map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid cron expression '{expr}': {e}"),
+            )
+        })?;
+        Self::starting(WallTimes::Cron(Box::new(cron)), now)
+    }
+
+    /// Runs every `interval` on its boundaries, counted from local midnight:
+    /// a minute interval runs at every whole minute.
+    pub fn aligned(interval: Duration, now: DateTime<Local>) -> io::Result<Self> {
+        if interval < MIN_ALIGN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "--align needs an interval of at least 1ms",
+            ));
+        }
+        Self::starting(WallTimes::Aligned(interval), now)
+    }
+
+    fn starting(times: WallTimes, now: DateTime<Local>) -> io::Result<Self> {
+        let next = times.after(now).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "the schedule never runs")
+        })?;
+        Ok(Self { times, next })
+    }
+
+    /// The next run after the one due at `self.next`, given the clock reads
+    /// `wall`. Runs the clock has already passed are skipped and counted. A
+    /// small backward clock step does not repeat a run that already happened.
+    fn advance(&mut self, wall: DateTime<Local>) -> u32 {
+        let behind = (self.next - wall).to_std().unwrap_or_default();
+        let from = if behind > MAX_STEP_BACK {
+            wall
+        } else {
+            self.next
+        };
+        let mut skipped = 0;
+        let mut next = self.times.after(from);
+        while let Some(time) = next.filter(|&t| t < wall) {
+            skipped += 1;
+            next = self.times.after(time);
+        }
+        // A schedule that ran once can always run again; keep the last
+        // time if the library disagrees rather than spinning.
+        self.next = next.unwrap_or(self.next);
+        skipped
+    }
+}
+
+impl WallTimes {
+    /// The first run time strictly after `time`.
+    fn after(&self, time: DateTime<Local>) -> Option<DateTime<Local>> {
+        match self {
+            WallTimes::Cron(cron) => cron.find_next_occurrence(&time, false).ok(),
+            WallTimes::Aligned(interval) => {
+                let midnight = coverage::start_of_day(time.date_naive());
+                let since = (time - midnight).to_std().ok()?;
+                let steps = since.as_nanos() / interval.as_nanos() + 1;
+                let next = midnight
+                    + chrono::Duration::from_std(interval.checked_mul(u32::try_from(steps).ok()?)?)
+                        .ok()?;
+                let tomorrow = coverage::start_of_day(time.date_naive().succ_opt()?);
+                Some(next.min(tomorrow))
+            }
+        }
+    }
+}
+
+/// The local date whose file records go to. It follows the wall clock but
+/// does not step back to the previous day for a small backward clock jump,
+/// so a DST change at midnight or an NTP correction just after it does not
+/// reopen yesterday's file.
+#[derive(Debug)]
+pub struct DayTracker {
+    current: NaiveDate,
+    /// The clock is behind the current day, which has been logged.
+    held: bool,
+}
+
+impl DayTracker {
+    pub fn new(now: DateTime<Local>) -> Self {
+        Self {
+            current: now.date_naive(),
+            held: false,
+        }
+    }
+
+    pub fn current(&self) -> NaiveDate {
+        self.current
+    }
+
+    /// Time from `now` until the next local midnight, when the day file
+    /// rotates.
+    pub fn until_next_day(&self, now: DateTime<Local>) -> Duration {
+        let next = self.current.succ_opt().unwrap_or(self.current);
+        (coverage::start_of_day(next) - now)
+            .to_std()
+            .unwrap_or_default()
+    }
+
+    /// Update from the wall clock at `now` and return the date to write to.
+    pub fn update(&mut self, now: DateTime<Local>) -> NaiveDate {
+        let date = now.date_naive();
+        if date < self.current {
+            let behind = (coverage::start_of_day(self.current) - now)
+                .to_std()
+                .unwrap_or_default();
+            if behind <= MAX_STEP_BACK {
+                if !std::mem::replace(&mut self.held, true) {
+                    tracing::warn!(
+                    clock = %now.to_rfc3339(),
+                    date = %self.current,
+                    "wall clock stepped back before midnight; keeping the current day file"
+                    );
+                }
+                return self.current;
+            }
+            tracing::warn!(
+                clock = %now.to_rfc3339(),
+                "wall clock moved back by more than {}; following it",
+                humantime::format_duration(MAX_STEP_BACK)
+            );
+        }
+        self.held = false;
+        self.current = date;
+        date
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(day: u32, h: u32, m: u32) -> DateTime<Local> {
+        let date = NaiveDate::from_ymd_opt(2025, 3, day).unwrap();
+        Local
+            .from_local_datetime(&date.and_hms_opt(h, m, 0).unwrap())
+            .earliest()
+            .unwrap()
+    }
+
+    #[test]
+    fn spaces_runs_from_start_to_start() {
+        let start = Instant::now();
+        let minute = Duration::from_secs(60);
+        let mut schedule = IntervalSchedule::new(start, minute);
+        // A run taking 10s does not push the next one back.
+        assert_eq!(
+            schedule.advance(start + Duration::from_secs(10)),
+            (start + minute, 0)
+        );
+        // A run overrunning two ticks skips them instead of bursting.
+        let late = start + minute * 3 + Duration::from_secs(5);
+        assert_eq!(schedule.advance(late), (start + minute * 4, 2));
+    }
+
+    #[test]
+    fn aligns_runs_to_interval_boundaries() {
+        let now = at(1, 10, 7) + chrono::Duration::seconds(30);
+        let mut aligned = WallSchedule::aligned(Duration::from_secs(60), now).unwrap();
+        assert_eq!(aligned.next, at(1, 10, 8));
+        // The run at 10:08 overran the runs at 10:09 and 10:10.
+        let overran = at(1, 10, 10) + chrono::Duration::seconds(30);
+        assert_eq!(aligned.advance(overran), 2);
+        assert_eq!(aligned.next, at(1, 10, 11));
+        // A run finishing just as the next one is due does not skip it.
+        assert_eq!(aligned.advance(at(1, 10, 12)), 0);
+        assert_eq!(aligned.next, at(1, 10, 12));
+        // Aligning restarts at midnight.
+        let late = WallSchedule::aligned(Duration::from_secs(7 * 3600), at(1, 22, 0)).unwrap();
+        assert_eq!(late.next, at(2, 0, 0));
+
+        assert!(WallSchedule::aligned(Duration::from_micros(10), now).is_err());
+        // Too many steps to count gives no time rather than a wrong one.
+        let tiny = WallTimes::Aligned(Duration::from_nanos(1));
+        assert_eq!(tiny.after(at(1, 10, 0)), None);
+    }
+
+    #[test]
+    fn follows_cron_on_the_wall_clock() {
+        let mut cron = WallSchedule::cron("0 9 * * 1-5", at(1, 12, 0)).unwrap();
+        // 2025-03-01 is a Saturday, so the next weekday run is on Monday.
+        assert_eq!(cron.next, at(3, 9, 0));
+        let mut schedule = Schedule::Wall(WallSchedule::cron("*/15 * * * *", at(3, 9, 1)).unwrap());
+        assert_eq!(
+            schedule.until_next(Instant::now(), at(3, 9, 1)),
+            Duration::from_secs(14 * 60)
+        );
+        // A clock stepped back after the 09:15 run does not repeat it.
+        assert_eq!(schedule.advance(Instant::now(), at(3, 9, 2)), 0);
+        assert_eq!(
+            schedule.until_next(Instant::now(), at(3, 9, 2)),
+            Duration::from_secs(28 * 60)
+        );
+        // A clock moved forward skips the runs it jumped over.
+        assert_eq!(cron.advance(at(5, 10, 0)), 2);
+        assert_eq!(cron.next, at(6, 9, 0));
+        assert!(WallSchedule::cron("0 25 * * *", at(1, 0, 0)).is_err());
+    }
+
+    #[test]
+    fn ignores_small_backward_clock_steps_across_midnight() {
+        let mut day = DayTracker::new(at(1, 23, 59));
+        assert_eq!(day.update(at(2, 0, 1)), at(2, 0, 0).date_naive());
+        // An NTP correction or DST shift back to just before midnight.
+        assert_eq!(day.update(at(1, 23, 30)), at(2, 0, 0).date_naive());
+        assert_eq!(day.update(at(2, 0, 2)), at(2, 0, 0).date_naive());
+        // A clock that was a day ahead is corrected.
+        assert_eq!(day.update(at(1, 12, 0)), at(1, 12, 0).date_naive());
+        assert_eq!(day.current(), at(1, 0, 0).date_naive());
+    }
+
+    #[test]
+    fn times_the_next_rotation() {
+        let day = DayTracker::new(at(1, 23, 58));
+        assert_eq!(day.until_next_day(at(1, 23, 58)), Duration::from_secs(120));
+        assert_eq!(day.until_next_day(at(2, 0, 1)), Duration::ZERO);
+    }
+}
@@ -0,0 +1,460 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use rust_embed::RustEmbed;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, Response, Server, SslConfig};
+
+use crate::cli::ServeOpts;
+use crate::config;
+use crate::metrics;
+use crate::query::{self, RecordQuery};
+use crate::storage::{self, JobMeta};
+use crate::top;
+
+/// The web dashboard, built into the binary from `web/`.
+#[derive(RustEmbed)]
+#[folder = "web/"]
+struct Assets;
+
+/// An HTTP response before it is sent.
+#[derive(Debug, PartialEq)]
+struct Reply {
+    status: u16,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Reply {
+    fn json(status: u16, value: &Value) -> Self {
+        Self {
+            status,
+            content_type: "application/json",
+            body: value.to_string().into_bytes(),
+        }
+    }
+
+    fn error(status: u16, message: impl Into<String>) -> Self {
+        Self::json(status, &json!({ "error": message.into() }))
+    }
+}
+
+pub fn serve(opts: ServeOpts) -> io::Result<()> {
+    let config_path = opts.config.unwrap_or_else(config::default_path);
+    let auth = Auth {
+        tokens: config::load(&config_path)?.serve.tokens,
+    };
+    let tls = match (&opts.tls_cert, &opts.tls_key) {
+        (Some(cert), Some(key)) => Some(tls_config(cert, key)?),
+        _ => None,
+    };
+    let scheme = if tls.is_some() { "https" } else { "http" };
+    let server = start(&opts.listen, tls)?;
+    if auth.tokens.is_empty() {
+        let local = server
+            .server_addr()
+            .to_ip()
+            .is_some_and(|addr| addr.ip().is_loopback());
+        if !local {
+            tracing::warn!(
+                config = %config_path.display(),
+                "the API is open to anyone who can reach {}; add [serve] tokens to the config file",
+                opts.listen
+            );
+        }
+    }
+    println!(
+        "Serving {} on {scheme}://{}",
+        dunce::simplified(&storage::base_dir()).display(),
+        opts.listen
+    );
+    for request in server.incoming_requests() {
+        let authorization = request
+            .headers()
+            .iter()
+            .find(|h| h.field.equiv("Authorization"))
+            .map(|h| h.value.as_str());
+        let reply = handle(&auth, request.method(), request.url(), authorization);
+        tracing::debug!(url = request.url(), status = reply.status, "request");
+        let header = Header::from_bytes("Content-Type", reply.content_type)
+            .expect("content types are valid headers");
+        let mut response = Response::from_data(reply.body)
+            .with_status_code(reply.status)
+            .with_header(header);
+        if reply.status == 401 {
+            response.add_header(
+                Header::from_bytes("WWW-Authenticate", "Bearer").expect("valid header"),
+            );
+        }
+        if let Err(e) = request.respond(response) {
+            tracing::warn!(error = %e, "could not send response");
+        }
+    }
+    Ok(())
+}
+
+/// Listen on `addr`, speaking TLS when a configuration is given.
+fn start(addr: &str, tls: Option<SslConfig>) -> io::Result<Server> {
+    let server = match tls {
+        Some(tls) => Server::https(addr, tls),
+        None => Server::http(addr),
+    };
+    server.map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::AddrNotAvailable,
+            format!("cannot listen on {addr}: {e}"),
+        )
+    })
+}
+
+/// Read a PEM certificate chain and private key. The key must be PKCS#8 or
+/// PKCS#1 (RSA); they are checked here because the server cannot report a
+/// bad key, only crash on it.
+fn tls_config(cert: &Path, key: &Path) -> io::Result<SslConfig> {
+    let read = |path: &Path| {
+        fs::read(path).map_err(|e| io::Error::new(e.kind(), format!("{}: {e}", path.display())))
+    };
+    let invalid = |path: &Path, msg: &str| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {msg}", path.display()),
+        )
+    };
+    let certificate = read(cert)?;
+    let chain = CertificateDer::pem_slice_iter(&certificate)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| invalid(cert, &format!("invalid PEM certificate: {e}")))?;
+    if chain.is_empty() {
+        return Err(invalid(cert, "no PEM certificate found"));
+    }
+    let private_key = read(key)?;
+    match PrivateKeyDer::from_pem_slice(&private_key) {
+        Ok(PrivateKeyDer::Pkcs8(_) | PrivateKeyDer::Pkcs1(_)) => {}
+        Ok(_) => {
+            return Err(invalid(
+                key,
+                "unsupported key type; convert it with `openssl pkcs8 -topk8 -nocrypt`",
+            ))
+        }
+        Err(e) => return Err(invalid(key, &format!("invalid PEM private key: {e}"))),
+    }
+    Ok(SslConfig {
+        certificate,
+        private_key,
+    })
+}
+
+/// Bearer tokens accepted by the API.
+struct Auth {
+    tokens: Vec<String>,
+}
+
+impl Auth {
+    /// Whether an `Authorization` header carries a configured token. Without
+    /// tokens everything is allowed.
+    fn allows(&self, authorization: Option<&str>) -> bool {
+        if self.tokens.is_empty() {
+            return true;
+        }
+        let Some(given) = authorization.and_then(|h| h.strip_prefix("Bearer ")) else {
+            return false;
+        };
+        // Check every token rather than stopping at the first match.
+        self.tokens.iter().fold(false, |found, token| {
+            found | constant_time_eq(token, given.trim())
+        })
+    }
+}
+
+/// Compare without stopping at the first difference, so the time taken does
+/// not reveal how much of a guess was right.
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.bytes()
+        .zip(b.bytes())
+        .fold(0, |diff, (x, y)| diff | (x ^ y))
+        == 0
+}
+
+/// Check the token for API and metrics requests, then answer. The
+/// dashboard's own files hold no data and are served to anyone, so it can
+/// ask for a token.
+fn handle(auth: &Auth, method: &Method, url: &str, authorization: Option<&str>) -> Reply {
+    let path = url.split_once('?').map_or(url, |(path, _)| path);
+    let protected = path.starts_with("/api/") || path == "/metrics";
+    if protected && !auth.allows(authorization) {
+        return Reply::error(401, "missing or invalid bearer token");
+    }
+    route(method, url)
+}
+
+/// Answer one request:
+///
+/// - `GET /api/jobs`: every job with its latest record, last failure and
+///   next run.
+/// - `GET /api/jobs/<name>/records`: a page of a job's records, oldest
+///   first, selected by the query string (see [`RecordQuery`]).
+/// - `GET /metrics`: every job's latest state in the OpenMetrics format.
+/// - `GET /<file>`: the dashboard's files (`/` is `index.html`).
+fn route(method: &Method, url: &str) -> Reply {
+    if *method != Method::Get {
+        return Reply::error(405, "only GET is supported");
+    }
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    match segments.as_slice() {
+        ["api", "jobs"] => jobs(),
+        ["metrics"] => metrics(),
+        ["api", "jobs", name, "records"] => {
+            let Some(name) = job_name(name) else {
+                return Reply::error(400, format!("invalid job name '{name}'"));
+            };
+            let query = query_params(query)
+                .ok_or_else(|| "malformed query string".to_string())
+                .and_then(|params| {
+                    RecordQuery::from_params(params.iter().map(|(k, v)| (k.as_str(), v.as_str())))
+                });
+            match query {
+                Ok(query) => records(&name, &query),
+                Err(e) => Reply::error(400, e),
+            }
+        }
+        ["api", ..] => Reply::error(404, format!("no such endpoint {path}")),
+        _ => asset(path),
+    }
+}
+
+/// A job's name, summary (or why it could not be read) and the metadata of
+/// its running `trep run`, if any.
+type JobState = (String, io::Result<top::JobSummary>, Option<JobMeta>);
+
+/// Every job under the data directory.
+fn job_states() -> io::Result<Vec<JobState>> {
+    let names = match storage::job_names(&storage::base_dir()) {
+        Ok(names) => names,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(e),
+    };
+    Ok(names
+        .into_iter()
+        .map(|name| {
+            let meta = storage::read_job_meta(&storage::meta_path(&name))
+                .ok()
+                .flatten();
+            let summary = storage::job_data_files(&name).and_then(|files| top::summarize(&files));
+            (name, summary, meta)
+        })
+        .collect())
+}
+
+fn jobs() -> Reply {
+    let states = match job_states() {
+        Ok(states) => states,
+        Err(e) => return Reply::error(500, e.to_string()),
+    };
+    let jobs: Vec<Value> = states
+        .into_iter()
+        .map(|(name, summary, meta)| match summary {
+            Ok(summary) => json!({
+                "name": name,
+                "latest": summary.latest,
+                "last_failure": summary.last_failure,
+                "next_run": meta.and_then(|meta| meta.next_run),
+            }),
+            Err(e) => json!({ "name": name, "error": e.to_string() }),
+        })
+        .collect();
+    Reply::json(200, &Value::Array(jobs))
+}
+
+/// Jobs that cannot be read are left out rather than failing the scrape.
+fn metrics() -> Reply {
+    let states = match job_states() {
+        Ok(states) => states,
+        Err(e) => return Reply::error(500, e.to_string()),
+    };
+    let jobs: Vec<metrics::JobState> = states
+        .into_iter()
+        .filter_map(|(name, summary, meta)| {
+            Some(metrics::JobState {
+                name,
+                summary: summary.ok()?,
+                meta,
+            })
+        })
+        .collect();
+    Reply {
+        status: 200,
+        content_type: metrics::CONTENT_TYPE,
+        body: metrics::render(&jobs).into_bytes(),
+    }
+}
+
+fn records(name: &str, query: &RecordQuery) -> Reply {
+    match storage::job_data_files(name).and_then(|files| query::page(&files, query)) {
+        Ok(page) => Reply::json(
+            200,
+            &json!({ "records": page.records, "next_cursor": page.next_cursor }),
+        ),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Reply::error(404, e.to_string()),
+        Err(e) => Reply::error(500, e.to_string()),
+    }
+}
+
+fn asset(path: &str) -> Reply {
+    let file = match path.trim_start_matches('/') {
+        "" => "index.html",
+        file => file,
+    };
+    match Assets::get(file) {
+        Some(content) => Reply {
+            status: 200,
+            content_type: content_type(file),
+            body: content.data.into_owned(),
+        },
+        None => Reply::error(404, format!("no such file /{file}")),
+    }
+}
+
+fn content_type(file: &str) -> &'static str {
+    match file.rsplit_once('.').map(|(_, ext)| ext) {
+        Some("html") => "text/html; charset=utf-8",
+        Some("js") => "text/javascript; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("svg") => "image/svg+xml",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Decode a job name from a URL path segment. Names that could reach outside
+/// the data directory are refused.
+fn job_name(segment: &str) -> Option<String> {
+    let name = percent_decode(segment)?;
+    let unsafe_name = name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\']);
+    (!unsafe_name).then_some(name)
+}
+
+/// Split and decode `key=value&...`, where `+` stands for a space.
+fn query_params(query: &str) -> Option<Vec<(String, String)>> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let decode = |s: &str| percent_decode(&s.replace('+', " "));
+            Some((decode(key)?, decode(value)?))
+        })
+        .collect()
+}
+
+/// Decode `%XX` escapes; `None` for malformed escapes or invalid UTF-8.
+fn percent_decode(s: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut rest = s.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        if b == b'%' {
+            let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(b);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serves_the_embedded_dashboard() {
+        let index = route(&Method::Get, "/");
+        assert_eq!(index.status, 200);
+        assert_eq!(index.content_type, "text/html; charset=utf-8");
+        assert!(String::from_utf8(index.body).unwrap().contains("app.js"));
+        assert_eq!(route(&Method::Get, "/app.js?v=1").status, 200);
+        assert_eq!(route(&Method::Get, "/missing.png").status, 404);
+        assert_eq!(route(&Method::Get, "/api/nothing").status, 404);
+        assert_eq!(route(&Method::Post, "/api/jobs").status, 405);
+        assert_eq!(route(&Method::Get, "/api/jobs/%2e%2e/records").status, 400);
+        assert_eq!(
+            route(&Method::Get, "/api/jobs/cpu/records?limit=many").status,
+            400
+        );
+    }
+
+    #[test]
+    fn requires_a_configured_token_for_the_api() {
+        let auth = Auth {
+            tokens: vec!["s3cret".to_string(), "other".to_string()],
+        };
+        let get = |url, header| handle(&auth, &Method::Get, url, header).status;
+        assert_eq!(get("/api/jobs/..%2F/records", None), 401);
+        assert_eq!(get("/api/jobs/..%2F/records", Some("Bearer nope")), 401);
+        assert_eq!(get("/api/jobs/..%2F/records", Some("s3cret")), 401);
+        assert_eq!(get("/api/jobs/..%2F/records", Some("Bearer s3cret")), 400);
+        assert_eq!(get("/api/jobs/..%2F/records", Some("Bearer other")), 400);
+        assert_eq!(get("/", None), 200);
+        assert_eq!(get("/metrics", None), 401);
+
+        let open = Auth { tokens: Vec::new() };
+        assert!(open.allows(None));
+        assert!(!constant_time_eq("abc", "abd"));
+        assert!(!constant_time_eq("abc", "ab"));
+    }
+
+    #[test]
+    fn serves_https_with_a_pem_certificate() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = rcgen::KeyPair::generate().unwrap();
+        let cert = rcgen::CertificateParams::new(vec!["localhost".to_string()])
+            .unwrap()
+            .self_signed(&key)
+            .unwrap();
+        let (cert_path, key_path) = (dir.path().join("cert.pem"), dir.path().join("key.pem"));
+        fs::write(&cert_path, cert.pem()).unwrap();
+        fs::write(&key_path, key.serialize_pem()).unwrap();
+
+        let server = start(
+            "127.0.0.1:0",
+            Some(tls_config(&cert_path, &key_path).unwrap()),
+        )
+        .unwrap();
+        let port = server.server_addr().to_ip().unwrap().port();
+        let target = format!("tls:localhost:{port}");
+        let check = crate::probe::Prober::new(target.parse().unwrap(), None)
+            .check()
+            .unwrap();
+        assert_ne!(check.exit_code, -1, "{}", check.value);
+        assert_eq!(check.fields["trusted"], false);
+
+        assert!(tls_config(&cert_path, &cert_path).is_err());
+        assert!(tls_config(&key_path, &key_path).is_err());
+        assert!(tls_config(&dir.path().join("missing.pem"), &key_path).is_err());
+    }
+
+    #[test]
+    fn decodes_job_names() {
+        assert_eq!(job_name("disk%20usage").as_deref(), Some("disk usage"));
+        assert_eq!(job_name("cpu").as_deref(), Some("cpu"));
+        assert_eq!(job_name(".."), None);
+        assert_eq!(job_name("a%2Fb"), None);
+        assert_eq!(job_name("bad%2"), None);
+        assert_eq!(job_name("%ff"), None);
+        assert_eq!(
+            query_params("exit_code=ne%3A0&fields=value,timestamp&x=a+b").unwrap(),
+            [
+                ("exit_code".to_string(), "ne:0".to_string()),
+                ("fields".to_string(), "value,timestamp".to_string()),
+                ("x".to_string(), "a b".to_string()),
+            ]
+        );
+    }
+}
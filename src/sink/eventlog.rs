@@ -0,0 +1,139 @@
+use std::io;
+use std::iter;
+use std::ptr;
+
+use windows_sys::Win32::Foundation::HANDLE;
+use windows_sys::Win32::System::EventLog::{
+    DeregisterEventSource, RegisterEventSourceW, ReportEventW, EVENTLOG_ERROR_TYPE,
+    EVENTLOG_INFORMATION_TYPE, REPORT_EVENT_TYPE,
+};
+
+use super::Sink;
+use crate::storage::Record;
+
+/// Event source name, registered with `New-EventLog -Source tiny-reporter`.
+pub const SOURCE: &str = "tiny-reporter";
+/// Event ID of failed runs (error level).
+const FAILURE_EVENT_ID: u32 = 1;
+/// Event ID of successful records, only reported with `--eventlog all`.
+const RECORD_EVENT_ID: u32 = 2;
+
+/// Writes failed runs, and optionally every record, to the Windows
+/// Application event log.
+pub struct EventLogSink {
+    handle: HANDLE,
+    job: String,
+    all: bool,
+}
+
+// SAFETY: an event source handle is not tied to the thread that registered
+// it, and the sink is only used through `&mut self`.
+unsafe impl Send for EventLogSink {}
+
+fn wide(s: &str) -> Vec<u16> {
+    s.encode_utf16().chain(iter::once(0)).collect()
+}
+
+impl EventLogSink {
+    pub fn new(job: &str, all: bool) -> io::Result<Self> {
+        let source = wide(SOURCE);
+        // SAFETY: `source` is a NUL-terminated UTF-16 string that outlives the call.
+        let handle = unsafe { RegisterEventSourceW(ptr::null(), source.as_ptr()) };
+        if handle.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self {
+            handle,
+            job: job.to_string(),
+            all,
+        })
+    }
+
+    fn report(&self, kind: REPORT_EVENT_TYPE, event_id: u32, message: &str) -> io::Result<()> {
+        let message = wide(message);
+        let strings = [message.as_ptr()];
+        // SAFETY: `handle` is a live event source and `strings` points to one
+        // NUL-terminated UTF-16 string; no raw data is passed.
+        let ok = unsafe {
+            ReportEventW(
+                self.handle,
+                kind,
+                0,
+                event_id,
+                ptr::null_mut(),
+                1,
+                0,
+                strings.as_ptr(),
+                ptr::null(),
+            )
+        };
+        if ok == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+/// The event text: a summary line followed by the record's columns.
+fn event_message(job: &str, record: &Record) -> String {
+    let summary = if record.is_marker() {
+        format!("trep job '{job}' stopped")
+    } else if record.exit_code == 0 {
+        format!("trep job '{job}' recorded a value")
+    } else {
+        format!(
+            "trep job '{job}' failed with exit code {}",
+            record.exit_code
+        )
+    };
+    let mut message = format!(
+        "{summary}\r\ntimestamp: {}\r\nvalue: {}",
+        record.timestamp, record.value
+    );
+    if !record.fields.is_empty() {
+        let fields = serde_json::Value::Object(record.fields.clone());
+        message.push_str(&format!("\r\nfields: {fields}"));
+    }
+    message
+}
+
+impl Sink for EventLogSink {
+    fn name(&self) -> &str {
+        "eventlog"
+    }
+
+    fn send(&mut self, record: &Record) -> io::Result<()> {
+        let message = event_message(&self.job, record);
+        if record.exit_code != 0 && !record.is_marker() {
+            self.report(EVENTLOG_ERROR_TYPE, FAILURE_EVENT_ID, &message)
+        } else if self.all {
+            self.report(EVENTLOG_INFORMATION_TYPE, RECORD_EVENT_ID, &message)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl Drop for EventLogSink {
+    fn drop(&mut self) {
+        // SAFETY: `handle` came from RegisterEventSourceW and is released once.
+        unsafe {
+            DeregisterEventSource(self.handle);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_failure_message() {
+        let mut record = Record::new("2025-01-01T00:00:00Z", "timeout", 124);
+        record.fields.insert("unit".into(), "s".into());
+        assert_eq!(
+            event_message("backup", &record),
+            "trep job 'backup' failed with exit code 124\r\ntimestamp: 2025-01-01T00:00:00Z\r\nvalue: timeout\r\nfields: {\"unit\":\"s\"}"
+        );
+    }
+}
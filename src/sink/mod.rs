@@ -0,0 +1,25 @@
+use std::io;
+
+use crate::storage::Record;
+
+#[cfg(windows)]
+mod eventlog;
+mod nats;
+mod pipe;
+mod redis;
+mod syslog;
+
+#[cfg(windows)]
+pub use self::eventlog::EventLogSink;
+pub use self::nats::NatsSink;
+pub use self::pipe::PipeSink;
+pub use self::redis::RedisSink;
+pub use self::syslog::SyslogSink;
+
+/// A secondary destination that receives every record after it has been
+/// appended to the job's data file. Failures are reported but never stop the job.
+pub trait Sink: Send {
+    /// Short label used when reporting delivery failures.
+    fn name(&self) -> &str;
+    fn send(&mut self, record: &Record) -> io::Result<()>;
+}
@@ -0,0 +1,193 @@
+use std::io::{self, Write};
+use std::process::{Child, ChildStdin, Stdio};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::Sink;
+use crate::exec;
+use crate::storage::Record;
+
+/// How long a pipe process may take to exit after its stdin is closed.
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(2);
+
+/// How many lines may wait for a pipe process that is not reading before
+/// further records are dropped.
+const QUEUE_LINES: usize = 1024;
+
+/// Streams records as JSON lines to the stdin of a long-lived child process,
+/// restarting the process whenever it has exited. Lines are written by a
+/// thread of their own, so a process that stops reading costs records
+/// rather than blocking the job.
+pub struct PipeSink {
+    command: String,
+    child: Option<Piped>,
+}
+
+/// A running pipe process and the queue of lines for its stdin.
+struct Piped {
+    child: Child,
+    lines: SyncSender<Vec<u8>>,
+}
+
+impl PipeSink {
+    pub fn new(command: &str) -> io::Result<Self> {
+        if command.trim().is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "pipe command must not be empty",
+            ));
+        }
+        Ok(Self {
+            command: command.to_string(),
+            child: None,
+        })
+    }
+
+    fn spawn(&self) -> io::Result<Piped> {
+        let mut child = exec::shell_command(&self.command)
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|e| io::Error::other(format!("failed to start '{}': {e}", self.command)))?;
+        let stdin = child
+            .stdin
+            .take()
+            .ok_or_else(|| io::Error::other("pipe command has no stdin"))?;
+        let (lines, queue) = mpsc::sync_channel(QUEUE_LINES);
+        thread::Builder::new()
+            .name("pipe-writer".to_string())
+            .spawn(move || write_lines(stdin, queue))?;
+        Ok(Piped { child, lines })
+    }
+
+    /// Return a running child, starting (or restarting) it if needed.
+    fn running_child(&mut self) -> io::Result<&mut Piped> {
+        if let Some(piped) = self.child.as_mut() {
+            if let Some(status) = piped.child.try_wait()? {
+                tracing::warn!(%status, "pipe command exited; restarting");
+                self.child = None;
+            }
+        }
+        if self.child.is_none() {
+            self.child = Some(self.spawn()?);
+        }
+        Ok(self.child.as_mut().expect("child started above"))
+    }
+
+    /// Queue `line` for the child. A full queue means the child has stopped
+    /// reading, and the line is dropped.
+    fn write_line(&mut self, line: Vec<u8>) -> io::Result<()> {
+        let piped = self.running_child()?;
+        piped.lines.try_send(line).map_err(|e| match e {
+            TrySendError::Full(_) => io::Error::new(
+                io::ErrorKind::WouldBlock,
+                format!("pipe command is not reading; dropped the record ({QUEUE_LINES} queued)"),
+            ),
+            TrySendError::Disconnected(_) => {
+                io::Error::new(io::ErrorKind::BrokenPipe, "pipe command stopped reading")
+            }
+        })
+    }
+
+    fn stop_child(&mut self) {
+        if let Some(Piped { mut child, lines }) = self.child.take() {
+            // Once the queued lines are written, the writer closes stdin,
+            // which signals EOF so the process can flush and exit.
+            drop(lines);
+            let deadline = Instant::now() + SHUTDOWN_GRACE;
+            while Instant::now() < deadline {
+                match child.try_wait() {
+                    Ok(Some(_)) | Err(_) => return,
+                    Ok(None) => thread::sleep(Duration::from_millis(50)),
+                }
+            }
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+impl Sink for PipeSink {
+    fn name(&self) -> &str {
+        "pipe"
+    }
+
+    fn send(&mut self, record: &Record) -> io::Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        match self.write_line(line.clone()) {
+            Err(e) if e.kind() != io::ErrorKind::WouldBlock => {
+                // The process may have died between ticks; retry once with a fresh one.
+                tracing::warn!(error = %e, "pipe command failed; restarting");
+                self.stop_child();
+                self.write_line(line)
+            }
+            result => result,
+        }
+    }
+}
+
+/// Write queued lines to a pipe process until the queue closes or a write
+/// fails, which means the process is gone.
+fn write_lines(mut stdin: ChildStdin, queue: mpsc::Receiver<Vec<u8>>) {
+    for line in queue {
+        if let Err(e) = stdin.write_all(&line).and_then(|()| stdin.flush()) {
+            tracing::warn!(error = %e, "could not write to the pipe command");
+            return;
+        }
+    }
+}
+
+impl Drop for PipeSink {
+    fn drop(&mut self) {
+        self.stop_child();
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn record(value: &str) -> Record {
+        Record::new("2025-01-01T00:00:00Z", value, 0)
+    }
+
+    #[test]
+    fn writes_json_lines_and_restarts_dead_process() {
+        let dir = tempdir().unwrap();
+        let out = dir.path().join("out.jsonl");
+        // Each process handles a single line and then exits.
+        let cmd = format!("head -n 1 >> '{}'", out.display());
+        let mut sink = PipeSink::new(&cmd).unwrap();
+        sink.send(&record("first")).unwrap();
+        if let Some(piped) = sink.child.as_mut() {
+            piped.child.wait().unwrap();
+        }
+        sink.send(&record("second")).unwrap();
+        // Wait for the restarted process itself; the shutdown grace period
+        // can be shorter than a slow login shell takes to start.
+        let Piped { mut child, lines } = sink.child.take().unwrap();
+        drop(lines);
+        child.wait().unwrap();
+
+        let contents = std::fs::read_to_string(&out).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains("\"value\":\"first\""));
+        assert!(lines[1].contains("\"value\":\"second\""));
+    }
+
+    #[test]
+    fn drops_records_for_a_process_that_does_not_read() {
+        let mut sink = PipeSink::new("exec sleep 30").unwrap();
+        let started = Instant::now();
+        // Enough to fill the pipe buffer and the queue behind it.
+        let dropped = (0..4 * QUEUE_LINES)
+            .filter_map(|i| sink.send(&record(&i.to_string())).err())
+            .inspect(|e| assert_eq!(e.kind(), io::ErrorKind::WouldBlock))
+            .count();
+        assert!(dropped > 0);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
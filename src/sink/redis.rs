@@ -0,0 +1,138 @@
+use std::io;
+use std::time::Duration;
+
+use super::Sink;
+use crate::storage::{self, Record};
+
+/// Limit for connecting and for each command, so a Redis that stops
+/// answering fails the send instead of blocking the job.
+const IO_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Appends each record to a Redis stream with `XADD`.
+pub struct RedisSink {
+    client: redis::Client,
+    stream: String,
+    conn: Option<redis::Connection>,
+}
+
+impl RedisSink {
+    pub fn new(url: &str, stream: &str) -> io::Result<Self> {
+        let client = redis::Client::open(url).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid redis url '{url}': {e}"),
+            )
+        })?;
+        Ok(Self {
+            client,
+            stream: stream.to_string(),
+            conn: None,
+        })
+    }
+}
+
+fn xadd_cmd(stream: &str, record: &Record) -> redis::Cmd {
+    let mut cmd = redis::cmd("XADD");
+    cmd.arg(stream)
+        .arg("*")
+        .arg("timestamp")
+        .arg(&record.timestamp)
+        .arg("value")
+        .arg(&record.value)
+        .arg("exit_code")
+        .arg(record.exit_code);
+    for (key, value) in &record.fields {
+        cmd.arg(key).arg(storage::field_to_string(value));
+    }
+    cmd
+}
+
+fn redis_error(e: redis::RedisError) -> io::Error {
+    io::Error::other(format!("redis: {e}"))
+}
+
+impl Sink for RedisSink {
+    fn name(&self) -> &str {
+        "redis"
+    }
+
+    fn send(&mut self, record: &Record) -> io::Result<()> {
+        if self.conn.is_none() {
+            let conn = self
+                .client
+                .get_connection_with_timeout(IO_TIMEOUT)
+                .map_err(redis_error)?;
+            conn.set_read_timeout(Some(IO_TIMEOUT))
+                .map_err(redis_error)?;
+            conn.set_write_timeout(Some(IO_TIMEOUT))
+                .map_err(redis_error)?;
+            self.conn = Some(conn);
+        }
+        let conn = self.conn.as_mut().expect("connection established above");
+        let res: redis::RedisResult<String> = xadd_cmd(&self.stream, record).query(conn);
+        if let Err(e) = res {
+            // Drop the connection so the next record reconnects.
+            self.conn = None;
+            return Err(redis_error(e));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn xadd_includes_all_record_fields() {
+        let record = Record::new("2025-01-01T00:00:00Z", "42", 0);
+        let packed = xadd_cmd("trep:cpu", &record).get_packed_command();
+        let text = String::from_utf8(packed).unwrap();
+        for part in [
+            "XADD",
+            "trep:cpu",
+            "*",
+            "timestamp",
+            "2025-01-01T00:00:00Z",
+            "value",
+            "42",
+            "exit_code",
+            "0",
+        ] {
+            assert!(text.contains(&format!("\r\n{part}\r\n")), "missing {part}");
+        }
+    }
+
+    #[test]
+    fn times_out_on_a_server_that_stops_answering() {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("redis://{}", listener.local_addr().unwrap());
+        // Answer the two CLIENT SETINFO commands of the handshake, then
+        // never answer XADD.
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut seen = Vec::new();
+            let mut buf = [0; 1024];
+            while !String::from_utf8_lossy(&seen).contains("LIB-VER") {
+                let n = stream.read(&mut buf).unwrap();
+                seen.extend(&buf[..n]);
+            }
+            stream.write_all(b"+OK\r\n+OK\r\n").unwrap();
+            while stream.read(&mut buf).is_ok_and(|n| n > 0) {}
+        });
+        let mut sink = RedisSink::new(&url, "s").unwrap();
+        let started = std::time::Instant::now();
+        let record = Record::new("2025-01-01T00:00:00Z", "1", 0);
+        assert!(sink.send(&record).is_err());
+        assert!(started.elapsed() < IO_TIMEOUT * 2);
+        // The failed send dropped the connection, which ends the server.
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn rejects_invalid_redis_url() {
+        assert!(RedisSink::new("not a url", "s").is_err());
+    }
+}
@@ -0,0 +1,322 @@
+use std::io::{self, Write};
+use std::net::{TcpStream, ToSocketAddrs, UdpSocket};
+#[cfg(unix)]
+use std::os::unix::net::UnixDatagram;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+use chrono::DateTime;
+
+use super::Sink;
+use crate::storage::{self, Record};
+
+const DEFAULT_PORT: u16 = 514;
+const IO_TIMEOUT: Duration = Duration::from_secs(5);
+/// Structured data ID for record fields. 32473 is the private enterprise
+/// number reserved for documentation (RFC 5612).
+const SD_ID: &str = "trep@32473";
+const SEVERITY_WARNING: u8 = 4;
+const SEVERITY_INFO: u8 = 6;
+
+/// Sends each record as an RFC 5424 syslog message to the local syslog
+/// socket or a remote collector over UDP or TCP (octet-counting framing).
+pub struct SyslogSink {
+    target: Target,
+    facility: u8,
+    hostname: String,
+    msgid: String,
+    conn: Option<Connection>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Target {
+    Unix(PathBuf),
+    Udp(String),
+    Tcp(String),
+}
+
+enum Connection {
+    #[cfg(unix)]
+    Unix(UnixDatagram),
+    Udp(UdpSocket),
+    Tcp(TcpStream),
+}
+
+/// Parse `local`, `unix:<path>`, `udp://host[:port]` or `tcp://host[:port]`.
+fn parse_target(target: &str) -> io::Result<Target> {
+    let with_port = |host: &str| -> io::Result<String> {
+        if host.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid syslog target '{target}': missing host"),
+            ));
+        }
+        let has_port = host
+            .rsplit_once(':')
+            .is_some_and(|(_, p)| p.parse::<u16>().is_ok());
+        Ok(if has_port {
+            host.to_string()
+        } else {
+            format!("{host}:{DEFAULT_PORT}")
+        })
+    };
+    if target == "local" {
+        let path = ["/dev/log", "/var/run/syslog"]
+            .into_iter()
+            .map(Path::new)
+            .find(|p| p.exists())
+            .unwrap_or(Path::new("/dev/log"));
+        return Ok(Target::Unix(path.to_path_buf()));
+    }
+    if let Some(path) = target.strip_prefix("unix:") {
+        return Ok(Target::Unix(PathBuf::from(path)));
+    }
+    if let Some(host) = target.strip_prefix("udp://") {
+        return Ok(Target::Udp(with_port(host.trim_end_matches('/'))?));
+    }
+    if let Some(host) = target.strip_prefix("tcp://") {
+        return Ok(Target::Tcp(with_port(host.trim_end_matches('/'))?));
+    }
+    Err(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!(
+            "invalid syslog target '{target}': expected local, unix:<path>, udp://host[:port] or tcp://host[:port]"
+        ),
+    ))
+}
+
+/// Keep printable ASCII without spaces, as RFC 5424 header fields require,
+/// truncated to `max` characters; empty values become the nil value `-`.
+fn header_field(value: &str, max: usize) -> String {
+    let clean: String = value
+        .chars()
+        .filter(|c| c.is_ascii_graphic())
+        .take(max)
+        .collect();
+    if clean.is_empty() {
+        "-".to_string()
+    } else {
+        clean
+    }
+}
+
+fn hostname() -> String {
+    let name = std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .ok()
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .or_else(|| std::env::var("COMPUTERNAME").ok())
+        .or_else(|| {
+            let out = Command::new("hostname").output().ok()?;
+            Some(String::from_utf8_lossy(&out.stdout).into_owned())
+        })
+        .unwrap_or_default();
+    header_field(name.trim(), 255)
+}
+
+/// Escape a structured data parameter value.
+fn sd_escape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '"' | '\\' | ']') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Structured data parameter names are limited to 32 characters without
+/// `=`, space, `]` and `"`.
+fn sd_name(name: &str) -> String {
+    let clean: String = name
+        .chars()
+        .filter(|c| c.is_ascii_graphic() && !matches!(c, '=' | ']' | '"'))
+        .take(32)
+        .collect();
+    if clean.is_empty() {
+        "_".to_string()
+    } else {
+        clean
+    }
+}
+
+impl SyslogSink {
+    /// `msgid` identifies the job in each message (normally the job name).
+    pub fn new(target: &str, facility: u8, msgid: &str) -> io::Result<Self> {
+        let target = parse_target(target)?;
+        if cfg!(not(unix)) && matches!(target, Target::Unix(_)) {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "local syslog sockets are only available on Unix; use udp:// or tcp://",
+            ));
+        }
+        Ok(Self {
+            target,
+            facility,
+            hostname: hostname(),
+            msgid: header_field(msgid, 32),
+            conn: None,
+        })
+    }
+
+    fn format(&self, record: &Record) -> String {
+        let severity = if record.exit_code == 0 || record.is_marker() {
+            SEVERITY_INFO
+        } else {
+            SEVERITY_WARNING
+        };
+        let pri = self.facility * 8 + severity;
+        // RFC 5424 allows at most microsecond precision.
+        let timestamp = DateTime::parse_from_rfc3339(&record.timestamp)
+            .map(|t| t.format("%Y-%m-%dT%H:%M:%S%.6f%:z").to_string())
+            .unwrap_or_else(|_| "-".to_string());
+        let mut sd = format!("[{SD_ID} exit_code=\"{}\"", record.exit_code);
+        for (key, value) in &record.fields {
+            let value = sd_escape(&storage::field_to_string(value));
+            sd.push_str(&format!(" {}=\"{value}\"", sd_name(key)));
+        }
+        sd.push(']');
+        format!(
+            "<{pri}>1 {timestamp} {} trep {} {} {sd} {}",
+            self.hostname,
+            std::process::id(),
+            self.msgid,
+            record.value
+        )
+    }
+
+    fn connect(&self) -> io::Result<Connection> {
+        let resolve = |addr: &str| {
+            addr.to_socket_addrs()?
+                .next()
+                .ok_or_else(|| io::Error::other(format!("cannot resolve {addr}")))
+        };
+        match &self.target {
+            #[cfg(unix)]
+            Target::Unix(path) => {
+                let socket = UnixDatagram::unbound()?;
+                socket.connect(path)?;
+                Ok(Connection::Unix(socket))
+            }
+            #[cfg(not(unix))]
+            Target::Unix(_) => unreachable!("rejected in SyslogSink::new"),
+            Target::Udp(addr) => {
+                let addr = resolve(addr)?;
+                let bind = if addr.is_ipv4() {
+                    "0.0.0.0:0"
+                } else {
+                    "[::]:0"
+                };
+                let socket = UdpSocket::bind(bind)?;
+                socket.connect(addr)?;
+                Ok(Connection::Udp(socket))
+            }
+            Target::Tcp(addr) => {
+                let stream = TcpStream::connect_timeout(&resolve(addr)?, IO_TIMEOUT)?;
+                stream.set_write_timeout(Some(IO_TIMEOUT))?;
+                Ok(Connection::Tcp(stream))
+            }
+        }
+    }
+}
+
+impl Connection {
+    fn send(&mut self, message: &str) -> io::Result<()> {
+        match self {
+            #[cfg(unix)]
+            Connection::Unix(socket) => socket.send(message.as_bytes()).map(drop),
+            Connection::Udp(socket) => socket.send(message.as_bytes()).map(drop),
+            // RFC 6587 octet counting: "<length> <message>".
+            Connection::Tcp(stream) => {
+                write!(stream, "{} {message}", message.len())?;
+                stream.flush()
+            }
+        }
+    }
+}
+
+impl Sink for SyslogSink {
+    fn name(&self) -> &str {
+        "syslog"
+    }
+
+    fn send(&mut self, record: &Record) -> io::Result<()> {
+        let message = self.format(record);
+        let mut conn = match self.conn.take() {
+            Some(conn) => conn,
+            None => self.connect()?,
+        };
+        conn.send(&message)?;
+        // Only keep connections that delivered the message; a failure reconnects next time.
+        self.conn = Some(conn);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::net::TcpListener;
+
+    #[test]
+    fn parses_syslog_targets() {
+        assert_eq!(
+            parse_target("udp://logs.example.com").unwrap(),
+            Target::Udp("logs.example.com:514".to_string())
+        );
+        assert_eq!(
+            parse_target("tcp://10.0.0.1:6514").unwrap(),
+            Target::Tcp("10.0.0.1:6514".to_string())
+        );
+        assert_eq!(
+            parse_target("unix:/run/log").unwrap(),
+            Target::Unix(PathBuf::from("/run/log"))
+        );
+        assert!(parse_target("udp://").is_err());
+        assert!(parse_target("http://example.com").is_err());
+    }
+
+    #[test]
+    fn formats_rfc5424_message() {
+        let mut sink = SyslogSink::new("udp://127.0.0.1", 16, "disk usage").unwrap();
+        sink.hostname = "host1".to_string();
+        let mut record = Record::new("2025-01-01T00:00:00.123456789+09:00", "95%", 1);
+        record.fields.insert("note".into(), "a \"b\" ]".into());
+        let message = sink.format(&record);
+        let expected = format!(
+            "<132>1 2025-01-01T00:00:00.123456+09:00 host1 trep {} diskusage [trep@32473 exit_code=\"1\" note=\"a \\\"b\\\" \\]\"] 95%",
+            std::process::id()
+        );
+        assert_eq!(message, expected);
+    }
+
+    #[test]
+    fn sends_over_udp_and_tcp() {
+        let record = Record::new("2025-01-01T00:00:00Z", "42", 0);
+
+        let udp = UdpSocket::bind("127.0.0.1:0").unwrap();
+        udp.set_read_timeout(Some(IO_TIMEOUT)).unwrap();
+        let target = format!("udp://{}", udp.local_addr().unwrap());
+        let mut sink = SyslogSink::new(&target, 1, "job").unwrap();
+        sink.send(&record).unwrap();
+        let mut buf = [0u8; 1024];
+        let n = udp.recv(&mut buf).unwrap();
+        let message = String::from_utf8_lossy(&buf[..n]).into_owned();
+        assert!(message.starts_with("<14>1 2025-01-01T00:00:00.000000+00:00 "));
+        assert!(message.ends_with(" job [trep@32473 exit_code=\"0\"] 42"));
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let target = format!("tcp://{}", listener.local_addr().unwrap());
+        let mut sink = SyslogSink::new(&target, 1, "job").unwrap();
+        sink.send(&record).unwrap();
+        drop(sink);
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut received = String::new();
+        stream.read_to_string(&mut received).unwrap();
+        let (len, rest) = received.split_once(' ').unwrap();
+        assert_eq!(len.parse::<usize>().unwrap(), rest.len());
+        assert!(rest.starts_with("<14>1 "));
+    }
+}
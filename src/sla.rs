@@ -0,0 +1,280 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::time::Duration;
+
+use chrono::{DateTime, Datelike, Days, Local, Months, NaiveDate};
+
+use crate::cli::{SlaOpts, SlaPeriod};
+use crate::coverage;
+use crate::storage::{self, Record};
+
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// One observation: when it was taken and whether the run succeeded.
+type Sample = (DateTime<Local>, bool);
+
+/// Up and down time accumulated in one period.
+#[derive(Debug, Default, PartialEq)]
+struct PeriodStats {
+    up: Duration,
+    down: Duration,
+}
+
+impl PeriodStats {
+    fn availability(&self) -> Option<f64> {
+        let total = self.up + self.down;
+        (!total.is_zero()).then(|| self.up.as_secs_f64() / total.as_secs_f64() * 100.0)
+    }
+}
+
+/// Consecutive failed runs, from the first failure to the next success.
+#[derive(Debug, PartialEq)]
+struct Outage {
+    start: DateTime<Local>,
+    end: DateTime<Local>,
+    failed_runs: usize,
+}
+
+pub fn sla(opts: SlaOpts) -> io::Result<()> {
+    let records = storage::read_job_records(&opts.name)?;
+    let samples = samples(&records);
+    if samples.len() < 2 {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!(
+                "job '{}' needs at least two records to measure availability",
+                opts.name
+            ),
+        ));
+    }
+    let from = opts.since.map(coverage::start_of_day);
+    let to = opts
+        .until
+        .map(|d| d.succ_opt().map_or(Local::now(), coverage::start_of_day));
+    let periods = period_stats(&samples, opts.period, from, to);
+    let outages: Vec<Outage> = outages(&samples)
+        .into_iter()
+        .filter(|o| from.is_none_or(|f| o.end > f) && to.is_none_or(|t| o.start < t))
+        .collect();
+    if periods.is_empty() {
+        println!("No records in the selected period");
+        return Ok(());
+    }
+
+    println!(
+        "{:<10} {:>12} {:>14} {:>14}",
+        "period", "availability", "uptime", "downtime"
+    );
+    let mut total = PeriodStats::default();
+    for (start, stats) in &periods {
+        print_row(&period_label(*start, opts.period), stats);
+        total.up += stats.up;
+        total.down += stats.down;
+    }
+    if periods.len() > 1 {
+        print_row("total", &total);
+    }
+    if outages.is_empty() {
+        println!("\nNo outages");
+    } else {
+        println!("\nOutages:");
+        for outage in &outages {
+            let length = (outage.end - outage.start).to_std().unwrap_or_default();
+            println!(
+                "{} - {}  {:>12}  ({} failed runs)",
+                outage.start.format(TIME_FORMAT),
+                outage.end.format(TIME_FORMAT),
+                format_duration(length),
+                outage.failed_runs
+            );
+        }
+    }
+    Ok(())
+}
+
+fn print_row(label: &str, stats: &PeriodStats) {
+    let availability = stats
+        .availability()
+        .map_or("-".to_string(), |a| format!("{a:.3}%"));
+    println!(
+        "{label:<10} {availability:>12} {:>14} {:>14}",
+        format_duration(stats.up),
+        format_duration(stats.down)
+    );
+}
+
+fn format_duration(d: Duration) -> String {
+    humantime::format_duration(Duration::from_secs(d.as_secs())).to_string()
+}
+
+/// Records with a valid timestamp, oldest first.
+fn samples(records: &[Record]) -> Vec<Sample> {
+    let mut samples: Vec<Sample> = records
+        .iter()
+        .filter_map(|r| {
+            let at = DateTime::parse_from_rfc3339(&r.timestamp).ok()?;
+            Some((at.with_timezone(&Local), r.exit_code == 0))
+        })
+        .collect();
+    samples.sort_by_key(|s| s.0);
+    samples
+}
+
+/// The start of the period containing `date`.
+fn period_start(date: NaiveDate, period: SlaPeriod) -> NaiveDate {
+    match period {
+        SlaPeriod::Day => date,
+        SlaPeriod::Week => date.week(chrono::Weekday::Mon).first_day(),
+        SlaPeriod::Month => date.with_day(1).expect("day 1 exists"),
+    }
+}
+
+fn next_period(start: NaiveDate, period: SlaPeriod) -> NaiveDate {
+    match period {
+        SlaPeriod::Day => start + Days::new(1),
+        SlaPeriod::Week => start + Days::new(7),
+        SlaPeriod::Month => start + Months::new(1),
+    }
+}
+
+fn period_label(start: NaiveDate, period: SlaPeriod) -> String {
+    match period {
+        SlaPeriod::Day => start.format("%Y-%m-%d").to_string(),
+        SlaPeriod::Week => start.format("%G-W%V").to_string(),
+        SlaPeriod::Month => start.format("%Y-%m").to_string(),
+    }
+}
+
+/// Up and down time per period. Each run's result holds until the next run,
+/// and stretches crossing a period boundary are split at it. Only time
+/// between `from` and `to` counts.
+fn period_stats(
+    samples: &[Sample],
+    period: SlaPeriod,
+    from: Option<DateTime<Local>>,
+    to: Option<DateTime<Local>>,
+) -> BTreeMap<NaiveDate, PeriodStats> {
+    let mut periods: BTreeMap<NaiveDate, PeriodStats> = BTreeMap::new();
+    for pair in samples.windows(2) {
+        let ((mut start, up), (end, _)) = (pair[0], pair[1]);
+        if let Some(from) = from {
+            start = start.max(from);
+        }
+        let end = to.map_or(end, |to| end.min(to));
+        while start < end {
+            let key = period_start(start.date_naive(), period);
+            let boundary = coverage::start_of_day(next_period(key, period));
+            let piece_end = end.min(boundary);
+            let piece = (piece_end - start).to_std().unwrap_or_default();
+            let stats = periods.entry(key).or_default();
+            if up {
+                stats.up += piece;
+            } else {
+                stats.down += piece;
+            }
+            start = piece_end;
+        }
+    }
+    periods
+}
+
+fn outages(samples: &[Sample]) -> Vec<Outage> {
+    let mut outages = Vec::new();
+    let mut current: Option<Outage> = None;
+    for &(at, up) in samples {
+        match (&mut current, up) {
+            (None, false) => {
+                current = Some(Outage {
+                    start: at,
+                    end: at,
+                    failed_runs: 1,
+                })
+            }
+            (Some(outage), false) => {
+                outage.end = at;
+                outage.failed_runs += 1;
+            }
+            (Some(_), true) => {
+                let mut outage = current.take().expect("checked above");
+                outage.end = at;
+                outages.push(outage);
+            }
+            (None, true) => {}
+        }
+    }
+    outages.extend(current);
+    outages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(month: u32, day: u32, h: u32) -> DateTime<Local> {
+        let date = NaiveDate::from_ymd_opt(2025, month, day).unwrap();
+        Local
+            .from_local_datetime(&date.and_hms_opt(h, 0, 0).unwrap())
+            .unwrap()
+    }
+
+    #[test]
+    fn splits_up_and_down_time_by_period() {
+        let hour = Duration::from_secs(3600);
+        let samples = [
+            (at(1, 31, 20), true),
+            (at(1, 31, 22), false),
+            (at(2, 1, 1), true),
+            (at(2, 1, 5), true),
+        ];
+        let periods = period_stats(&samples, SlaPeriod::Month, None, None);
+        let jan = NaiveDate::from_ymd_opt(2025, 1, 1).unwrap();
+        let feb = NaiveDate::from_ymd_opt(2025, 2, 1).unwrap();
+        assert_eq!(
+            periods[&jan],
+            PeriodStats {
+                up: hour * 2,
+                down: hour * 2
+            }
+        );
+        assert_eq!(
+            periods[&feb],
+            PeriodStats {
+                up: hour * 4,
+                down: hour
+            }
+        );
+        assert_eq!(periods[&feb].availability(), Some(80.0));
+
+        let clipped = period_stats(&samples, SlaPeriod::Day, Some(at(2, 1, 0)), None);
+        assert_eq!(clipped.len(), 1);
+        assert_eq!(period_label(feb, SlaPeriod::Week), "2025-W05");
+        assert_eq!(period_start(feb, SlaPeriod::Week), jan + Days::new(26));
+    }
+
+    #[test]
+    fn groups_consecutive_failures_into_outages() {
+        let samples = [
+            (at(1, 1, 0), true),
+            (at(1, 1, 1), false),
+            (at(1, 1, 2), false),
+            (at(1, 1, 3), true),
+            (at(1, 1, 4), false),
+        ];
+        assert_eq!(
+            outages(&samples),
+            [
+                Outage {
+                    start: at(1, 1, 1),
+                    end: at(1, 1, 3),
+                    failed_runs: 2
+                },
+                Outage {
+                    start: at(1, 1, 4),
+                    end: at(1, 1, 4),
+                    failed_runs: 1
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,258 @@
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+use chrono::{DateTime, Local, NaiveDate};
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
+use serde_json::{Map, Value};
+
+use crate::storage::{Record, RecordStore};
+
+/// Version of the schema below, kept in `PRAGMA user_version`. Bump it and
+/// add a step to [`migrate`] when the schema changes.
+const SCHEMA_VERSION: i64 = 1;
+
+/// The records table and its rejects twin. `at` is the timestamp in Unix
+/// milliseconds, for ordering records written in different UTC offsets;
+/// `fields` holds the extra fields as a JSON object.
+const SCHEMA: &str = "
+CREATE TABLE records (
+    id INTEGER PRIMARY KEY,
+    timestamp TEXT NOT NULL,
+    at INTEGER,
+    value TEXT NOT NULL,
+    exit_code INTEGER NOT NULL,
+    fields TEXT
+);
+CREATE INDEX records_at ON records (at);
+CREATE TABLE rejects (
+    id INTEGER PRIMARY KEY,
+    timestamp TEXT NOT NULL,
+    at INTEGER,
+    value TEXT NOT NULL,
+    exit_code INTEGER NOT NULL,
+    fields TEXT
+);
+";
+
+/// How long to wait for another connection (e.g. `trep serve` reading)
+/// to release the database.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The table a store appends to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Table {
+    Records,
+    Rejects,
+}
+
+/// All of a job's records in one SQLite database, `records.db`.
+pub struct SqliteStore {
+    conn: Connection,
+    insert: &'static str,
+}
+
+impl SqliteStore {
+    /// Open or create the database at `path` and bring its schema up to date.
+    pub fn open(path: &Path, table: Table) -> io::Result<Self> {
+        let mut conn = Connection::open(path).map_err(|e| error(path, e))?;
+        conn.busy_timeout(BUSY_TIMEOUT)
+            .and_then(|()| conn.pragma_update(None, "journal_mode", "WAL"))
+            .map_err(|e| error(path, e))?;
+        migrate(&mut conn, path)?;
+        let insert = match table {
+            Table::Records => {
+                "INSERT INTO records (timestamp, at, value, exit_code, fields) VALUES (?1, ?2, ?3, ?4, ?5)"
+            }
+            Table::Rejects => {
+                "INSERT INTO rejects (timestamp, at, value, exit_code, fields) VALUES (?1, ?2, ?3, ?4, ?5)"
+            }
+        };
+        Ok(Self { conn, insert })
+    }
+}
+
+impl RecordStore for SqliteStore {
+    /// Every day goes to the same table, so `date` is not needed.
+    fn write(&mut self, _date: NaiveDate, record: &Record) -> io::Result<()> {
+        let at = DateTime::parse_from_rfc3339(&record.timestamp)
+            .ok()
+            .map(|t| t.timestamp_millis());
+        let fields = if record.fields.is_empty() {
+            None
+        } else {
+            Some(serde_json::to_string(&record.fields)?)
+        };
+        self.conn
+            .prepare_cached(self.insert)
+            .and_then(|mut insert| {
+                insert.execute(params![
+                    record.timestamp,
+                    at,
+                    record.value,
+                    record.exit_code,
+                    fields
+                ])
+            })
+            .map(drop)
+            .map_err(io::Error::other)
+    }
+
+    /// Each insert is committed on its own, so the marker is as durable as
+    /// any record; columns cannot misalign.
+    fn write_marker(&mut self, date: NaiveDate, record: Record) -> io::Result<()> {
+        self.write(date, &record)
+    }
+
+    /// Nothing to rotate: the connection stays open.
+    fn reopen(&mut self) {}
+}
+
+/// Create the schema in a new database, or check that an existing one is
+/// one this version understands.
+fn migrate(conn: &mut Connection, path: &Path) -> io::Result<()> {
+    let tx = conn.transaction().map_err(|e| error(path, e))?;
+    let version: i64 = tx
+        .pragma_query_value(None, "user_version", |row| row.get(0))
+        .map_err(|e| error(path, e))?;
+    if version > SCHEMA_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "{} was created by a newer trep (schema version {version})",
+                dunce::simplified(path).display()
+            ),
+        ));
+    }
+    if version < 1 {
+        tx.execute_batch(SCHEMA).map_err(|e| error(path, e))?;
+    }
+    tx.pragma_update(None, "user_version", SCHEMA_VERSION)
+        .and_then(|()| tx.commit())
+        .map_err(|e| error(path, e))
+}
+
+/// Read every record of the database at `path`, oldest first. Records
+/// with a timestamp that does not parse come first, in insertion order.
+pub fn read_records(path: &Path) -> io::Result<Vec<Record>> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|e| error(path, e))?;
+    conn.busy_timeout(BUSY_TIMEOUT)
+        .map_err(|e| error(path, e))?;
+    let has_records = conn
+        .query_row(
+            "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'records'",
+            [],
+            |_| Ok(()),
+        )
+        .optional()
+        .map_err(|e| error(path, e))?;
+    if has_records.is_none() {
+        return Ok(Vec::new());
+    }
+    let mut select = conn
+        .prepare("SELECT timestamp, value, exit_code, fields FROM records ORDER BY at, id")
+        .map_err(|e| error(path, e))?;
+    let rows = select
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, i32>(2)?,
+                row.get::<_, Option<String>>(3)?,
+            ))
+        })
+        .map_err(|e| error(path, e))?;
+    let mut records = Vec::new();
+    for row in rows {
+        let (timestamp, value, exit_code, fields) = row.map_err(|e| error(path, e))?;
+        let mut record = Record::new(timestamp, value, exit_code);
+        if let Some(fields) = fields {
+            record.fields = serde_json::from_str::<Map<String, Value>>(&fields)?;
+        }
+        records.push(record);
+    }
+    Ok(records)
+}
+
+/// Delete the records and rejects older than `before` from the database at
+/// `path`, returning how many were deleted. Records whose timestamp does not
+/// parse are kept.
+pub fn delete_before(path: &Path, before: DateTime<Local>) -> io::Result<usize> {
+    let store = SqliteStore::open(path, Table::Records)?;
+    let at = before.timestamp_millis();
+    let mut deleted = 0;
+    for table in ["records", "rejects"] {
+        deleted += store
+            .conn
+            .execute(&format!("DELETE FROM {table} WHERE at < ?1"), params![at])
+            .map_err(|e| error(path, e))?;
+    }
+    Ok(deleted)
+}
+
+/// How many records and rejects [`delete_before`] would delete.
+pub fn count_before(path: &Path, before: DateTime<Local>) -> io::Result<usize> {
+    let store = SqliteStore::open(path, Table::Records)?;
+    let at = before.timestamp_millis();
+    let mut count = 0;
+    for table in ["records", "rejects"] {
+        count += store
+            .conn
+            .query_row(
+                &format!("SELECT COUNT(*) FROM {table} WHERE at < ?1"),
+                params![at],
+                |row| row.get::<_, i64>(0),
+            )
+            .map_err(|e| error(path, e))? as usize;
+    }
+    Ok(count)
+}
+
+fn error(path: &Path, e: rusqlite::Error) -> io::Error {
+    io::Error::other(format!("{}: {e}", dunce::simplified(path).display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stores_records_in_timestamp_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("records.db");
+        let day = NaiveDate::from_ymd_opt(2025, 1, 1).unwrap();
+        let mut store = SqliteStore::open(&path, Table::Records).unwrap();
+        let mut late = Record::new("2025-01-01T10:00:00+01:00", "2", 0);
+        late.fields.insert("unit".into(), "s".into());
+        store.write(day, &late).unwrap();
+        // Earlier, although written later and in another offset.
+        store
+            .write(day, &Record::new("2025-01-01T08:30:00+00:00", "1", 3))
+            .unwrap();
+        SqliteStore::open(&path, Table::Rejects)
+            .unwrap()
+            .write(day, &Record::new("2025-01-01T09:00:00+00:00", "x", 0))
+            .unwrap();
+
+        let records = read_records(&path).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].value, "1");
+        assert_eq!(records[0].exit_code, 3);
+        assert_eq!(records[1].fields["unit"], "s");
+
+        let cutoff = DateTime::parse_from_rfc3339("2025-01-01T08:45:00+00:00").unwrap();
+        let cutoff = cutoff.with_timezone(&Local);
+        assert_eq!(count_before(&path, cutoff).unwrap(), 1);
+        assert_eq!(delete_before(&path, cutoff).unwrap(), 1);
+        let records = read_records(&path).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].value, "2");
+
+        let conn = Connection::open(&path).unwrap();
+        conn.pragma_update(None, "user_version", SCHEMA_VERSION + 1)
+            .unwrap();
+        let err = SqliteStore::open(&path, Table::Records).err().unwrap();
+        assert!(err.to_string().contains("newer trep"), "{err}");
+    }
+}
@@ -0,0 +1,167 @@
+use std::io;
+
+use clap::Parser;
+
+use crate::cli::{Cli, Commands, RunOpts, StartOpts};
+use crate::config::{self, JobConfig};
+use crate::job::{Job, Scheduler, Stop};
+use crate::util;
+
+/// Run every job of the jobs file in this process, each on its own thread
+/// with its own lock and data directory, until they are done or Ctrl-C /
+/// SIGTERM stops them all. A job that fails is logged while the others keep
+/// running.
+pub fn start(opts: StartOpts) -> io::Result<()> {
+    let path = opts.config.unwrap_or_else(config::default_jobs_path);
+    let jobs = config::load_jobs(&path)?;
+    // Check every job before starting any, so a typo does not leave half
+    // of them running.
+    let mut scheduler = Scheduler::new();
+    for spec in &jobs {
+        let job = Job::from_opts(run_opts(spec)?).map_err(|e| {
+            let message = format!("job '{}': {e}", spec.name);
+            io::Error::new(io::Error::from(e).kind(), message)
+        })?;
+        scheduler.add(job);
+    }
+    let stop = Stop::on_signals()?;
+    println!(
+        "Starting {} jobs from {}",
+        scheduler.len(),
+        dunce::simplified(&path).display()
+    );
+    let failed: Vec<String> = scheduler
+        .run(&stop)
+        .into_iter()
+        .filter_map(|(name, result)| {
+            let e = result.err()?;
+            tracing::error!(job = %name, error = %e, "job failed");
+            Some(name)
+        })
+        .collect();
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!(
+            "jobs failed: {}",
+            failed.join(", ")
+        )))
+    }
+}
+
+/// The `trep run` options for a job, parsed like the command line so they
+/// get the same defaults and checks, with its durations validated.
+fn run_opts(job: &JobConfig) -> io::Result<RunOpts> {
+    let invalid = |msg: String| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("job '{}': {msg}", job.name),
+        )
+    };
+    for (what, value) in [("interval", &job.every), ("timeout", &job.timeout)] {
+        if let Some(value) = value {
+            util::duration_arg(value, what).map_err(|e| invalid(e.to_string()))?;
+        }
+    }
+    let alert = job.alert.as_ref();
+    let above = alert.and_then(|a| a.above).map(|n| n.to_string());
+    let below = alert.and_then(|a| a.below).map(|n| n.to_string());
+    let none = None;
+    let env: Vec<String> = job.env.iter().map(|(k, v)| format!("{k}={v}")).collect();
+    let mut args = vec!["trep", "run", "--as", &job.name];
+    for pair in &env {
+        args.extend(["--env", pair]);
+    }
+    let optional = [
+        ("--every", &job.every),
+        ("--format", &job.format),
+        ("--timeout", &job.timeout),
+        ("--shell", &job.shell),
+        ("--cwd", &job.cwd),
+        ("--alert-above", &above),
+        ("--alert-below", &below),
+        ("--alert-webhook", alert.map_or(&none, |a| &a.webhook)),
+        ("--alert-command", alert.map_or(&none, |a| &a.command)),
+        ("--alert-cooldown", alert.map_or(&none, |a| &a.cooldown)),
+    ];
+    for (flag, value) in optional {
+        if let Some(value) = value {
+            args.extend([flag, value]);
+        }
+    }
+    args.extend(["--", &job.command]);
+    match Cli::try_parse_from(args) {
+        Ok(Cli {
+            command: Commands::Run(opts),
+            ..
+        }) => Ok(*opts),
+        Ok(_) => unreachable!("parsed a run command"),
+        Err(e) => {
+            let message = e.to_string();
+            let first = message.lines().next().unwrap_or_default();
+            Err(invalid(first.trim_start_matches("error: ").to_string()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AlertConfig;
+
+    #[test]
+    fn builds_run_options_for_a_job() {
+        let job = JobConfig {
+            name: "load".to_string(),
+            command: "cat /proc/loadavg".to_string(),
+            every: Some("1m".to_string()),
+            format: None,
+            timeout: Some("5s".to_string()),
+            shell: Some("sh".to_string()),
+            cwd: None,
+            env: [("LANG".to_string(), "C".to_string())].into(),
+            alert: Some(AlertConfig {
+                below: Some(-1.5),
+                webhook: Some("http://localhost/hook".to_string()),
+                ..AlertConfig::default()
+            }),
+        };
+        let opts = run_opts(&job).unwrap();
+        assert_eq!(opts.name, "load");
+        assert_eq!(opts.every.as_deref(), Some("1m"));
+        assert_eq!(opts.format, "csv");
+        assert_eq!(opts.timeout.as_deref(), Some("5s"));
+        assert_eq!(opts.cmd, ["cat /proc/loadavg"]);
+        assert_eq!(opts.shell, Some(crate::cli::Shell::Sh));
+        assert_eq!(opts.env, ["LANG=C"]);
+        assert_eq!(opts.alert_below, Some(-1.5));
+        assert_eq!(opts.alert_webhook.as_deref(), Some("http://localhost/hook"));
+
+        let bad = JobConfig {
+            every: Some("soon".to_string()),
+            ..job
+        };
+        let err = run_opts(&bad).unwrap_err().to_string();
+        assert!(
+            err.starts_with("job 'load': invalid interval 'soon'"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn rejects_a_jobs_file_with_a_bad_job_before_running_any() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("jobs.toml");
+        let ran = dir.path().join("ran");
+        let jobs = format!(
+            "[[job]]\nname = \"good\"\ncommand = \"touch '{}'\"\n\n\
+             [[job]]\nname = \"bad\"\ncommand = \"true\"\nformat = \"xml\"\n",
+            ran.display()
+        );
+        std::fs::write(&path, jobs).unwrap();
+        let err = start(StartOpts { config: Some(path) }).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(err.to_string().starts_with("job 'bad': "), "{err}");
+        assert!(!ran.exists());
+    }
+}
@@ -0,0 +1,268 @@
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Local};
+
+use crate::cli::{ListOpts, StatusOpts, StopOpts};
+use crate::storage::{self, JobMeta};
+use crate::top::{local_time, next_run, truncate};
+use crate::util;
+
+/// How often `stop` checks whether the job has let go of its lock.
+const STOP_POLL: Duration = Duration::from_millis(100);
+
+pub fn list(opts: ListOpts) -> io::Result<()> {
+    let names = match storage::job_names(&storage::base_dir()) {
+        Ok(names) => names,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(e),
+    };
+    let mut jobs = Vec::new();
+    for name in names {
+        // One unreadable state file must not hide the other jobs.
+        let meta = storage::running_job(&name).unwrap_or_else(|e| {
+            tracing::warn!(job = %name, error = %e, "cannot read the job's state");
+            None
+        });
+        if meta.is_some() || opts.all {
+            jobs.push((name, meta));
+        }
+    }
+    if jobs.is_empty() {
+        println!("No running jobs");
+    } else {
+        print!("{}", render_list(&jobs, Local::now()));
+    }
+    Ok(())
+}
+
+pub fn status(opts: StatusOpts) -> io::Result<()> {
+    let dir = storage::job_dir(&opts.name);
+    if !dir.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no data for job '{}' in {}", opts.name, dir.display()),
+        ));
+    }
+    match storage::running_job(&opts.name)? {
+        Some(meta) => print!("{}", describe(&opts.name, &meta, Local::now())),
+        None => println!("{}: not running", opts.name),
+    }
+    Ok(())
+}
+
+/// Send the process running the job SIGTERM, so it finishes the run in
+/// progress and writes its stop marker, and wait until it has let go of the
+/// job. A `trep start` process stops all of its jobs, which are listed.
+pub fn stop(opts: StopOpts) -> io::Result<()> {
+    let timeout = util::duration_arg(&opts.timeout, "timeout")?;
+    let Some(meta) = storage::running_job(&opts.name)? else {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("job '{}' is not running", opts.name),
+        ));
+    };
+    let jobs = jobs_of(meta.pid, &opts.name)?;
+    terminate(meta.pid)?;
+    let deadline = Instant::now() + timeout;
+    for name in &jobs {
+        let lock = storage::lock_path(name);
+        while storage::is_locked(&lock)? {
+            if Instant::now() >= deadline {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!(
+                        "job '{name}' (pid {}) did not stop within {}",
+                        meta.pid,
+                        humantime::format_duration(timeout)
+                    ),
+                ));
+            }
+            thread::sleep(STOP_POLL);
+        }
+    }
+    let names: Vec<String> = jobs.iter().map(|name| format!("'{name}'")).collect();
+    if jobs.len() == 1 {
+        println!("Stopped {} (pid {})", names[0], meta.pid);
+    } else {
+        println!(
+            "Stopped {} (pid {}, which runs them all)",
+            names.join(", "),
+            meta.pid
+        );
+    }
+    Ok(())
+}
+
+/// The job `name` followed by the other running jobs of process `pid`,
+/// which `trep start` runs together.
+fn jobs_of(pid: u32, name: &str) -> io::Result<Vec<String>> {
+    let names = match storage::job_names(&storage::base_dir()) {
+        Ok(names) => names,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(e),
+    };
+    let mut jobs = vec![name.to_string()];
+    for other in names {
+        let same = other != name
+            && storage::running_job(&other)
+                .ok()
+                .flatten()
+                .is_some_and(|meta| meta.pid == pid);
+        if same {
+            jobs.push(other);
+        }
+    }
+    Ok(jobs)
+}
+
+#[cfg(unix)]
+fn terminate(pid: u32) -> io::Result<()> {
+    let pid =
+        libc::pid_t::try_from(pid).map_err(|_| io::Error::other(format!("invalid pid {pid}")))?;
+    // SAFETY: kill takes no pointers; it only sends a signal.
+    if unsafe { libc::kill(pid, libc::SIGTERM) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!(
+            "could not signal pid {pid}: {}",
+            io::Error::last_os_error()
+        )))
+    }
+}
+
+#[cfg(not(unix))]
+fn terminate(_pid: u32) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "stop is only available on Unix; press Ctrl-C in the job's console instead",
+    ))
+}
+
+/// One row per job: its process, when it started, how its last run went
+/// and when it runs next. Jobs that are not running only get their name.
+fn render_list(jobs: &[(String, Option<JobMeta>)], now: DateTime<Local>) -> String {
+    let mut table = format!(
+        "{:<16} {:>8} {:<19} {:<19} {:>4} {:<12} {}\n",
+        "job", "pid", "started", "last run", "exit", "next run", "source"
+    );
+    for (name, meta) in jobs {
+        let Some(meta) = meta else {
+            table.push_str(&format!(
+                "{:<16} {:>8} {:<19} {:<19} {:>4} {:<12} -\n",
+                truncate(name, 16),
+                "-",
+                "-",
+                "-",
+                "-",
+                "-"
+            ));
+            continue;
+        };
+        let time = |t: &Option<String>| t.as_deref().map_or("-".to_string(), local_time);
+        table.push_str(&format!(
+            "{:<16} {:>8} {:<19} {:<19} {:>4} {:<12} {}\n",
+            truncate(name, 16),
+            meta.pid,
+            time(&meta.started_at),
+            time(&meta.last_run),
+            meta.last_exit_code
+                .map_or("-".to_string(), |code| code.to_string()),
+            next_run(Some(meta), now),
+            truncate(&meta.source, 40)
+        ));
+    }
+    table
+}
+
+/// The status of a running job, one field per line.
+fn describe(name: &str, meta: &JobMeta, now: DateTime<Local>) -> String {
+    let mut text = format!("{name}: running (pid {})\n", meta.pid);
+    let mut field = |label: &str, value: String| {
+        text.push_str(&format!("  {:<10} {value}\n", format!("{label}:")));
+    };
+    if let Some(started) = &meta.started_at {
+        let up = DateTime::parse_from_rfc3339(started)
+            .ok()
+            .and_then(|t| (now - t.with_timezone(&Local)).to_std().ok())
+            .map(|up| {
+                let up = Duration::from_secs(up.as_secs());
+                format!(" (up {})", humantime::format_duration(up))
+            });
+        field(
+            "started",
+            format!("{}{}", local_time(started), up.unwrap_or_default()),
+        );
+    }
+    field("source", meta.source.clone());
+    let schedule = match (&meta.cron, meta.interval_secs) {
+        (Some(expr), _) => format!("cron {expr}"),
+        (None, Some(secs)) => format!(
+            "every {}",
+            humantime::format_duration(Duration::from_secs_f64(secs))
+        ),
+        (None, None) => "once".to_string(),
+    };
+    field("schedule", schedule);
+    let last_run = match (&meta.last_run, meta.last_exit_code) {
+        (Some(at), Some(code)) => {
+            let took = meta
+                .last_duration_secs
+                .map(|secs| format!(", took {secs:.2}s"))
+                .unwrap_or_default();
+            format!("{}, exit code {code}{took}", local_time(at))
+        }
+        _ => "-".to_string(),
+    };
+    field("last run", last_run);
+    field("next run", next_run(Some(meta), now));
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_and_describes_jobs() {
+        let now = Local::now();
+        let started = now - chrono::Duration::seconds(3725);
+        let meta = JobMeta {
+            pid: 4242,
+            source: "cat /proc/loadavg".to_string(),
+            interval_secs: Some(60.0),
+            next_run: Some((now + chrono::Duration::seconds(30)).to_rfc3339()),
+            last_duration_secs: Some(0.25),
+            started_at: Some(started.to_rfc3339()),
+            last_run: Some(started.to_rfc3339()),
+            last_exit_code: Some(2),
+            ..JobMeta::default()
+        };
+        let table = render_list(
+            &[
+                ("load".to_string(), Some(meta.clone())),
+                ("old".to_string(), None),
+            ],
+            now,
+        );
+        let rows: Vec<&str> = table.lines().collect();
+        assert!(rows[0].starts_with("job"), "{table}");
+        assert!(rows[1].starts_with("load"), "{table}");
+        assert!(rows[1].contains(" 4242 "), "{table}");
+        assert!(rows[1].contains(&started.format("%Y-%m-%d %H:%M:%S").to_string()));
+        assert!(rows[1].contains("in 30s"), "{table}");
+        assert!(rows[1].ends_with("cat /proc/loadavg"), "{table}");
+        assert!(
+            rows[2].starts_with("old") && rows[2].ends_with(" -"),
+            "{table}"
+        );
+
+        let text = describe("load", &meta, now);
+        assert!(text.starts_with("load: running (pid 4242)\n"), "{text}");
+        assert!(text.contains("(up 1h 2m 5s)\n"), "{text}");
+        assert!(text.contains("  schedule:  every 1m\n"), "{text}");
+        assert!(text.contains(", exit code 2, took 0.25s\n"), "{text}");
+        assert!(text.contains("  next run:  in 30s\n"), "{text}");
+    }
+}
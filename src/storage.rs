@@ -1,73 +1,877 @@
-use chrono::Local;
+use chrono::NaiveDate;
+use flate2::read::MultiGzDecoder;
 use fs2::FileExt;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::borrow::Cow;
 use std::fs::{self, File, OpenOptions};
-use std::io::{self, Write};
+use std::io::{self, Read, Write};
 use std::path::{Path, PathBuf};
 
-#[derive(Serialize)]
-struct JsonRecord<'a> {
-    timestamp: &'a str,
-    value: &'a str,
-    exit_code: i32,
-}
-
-pub fn write_csv_record(
-    path: &Path,
-    timestamp: &str,
-    value: &str,
-    exit_code: i32,
-) -> io::Result<()> {
-    let file_exists = path.exists();
-    let file = OpenOptions::new().create(true).append(true).open(path)?;
+use crate::{sqlite, util};
+
+/// Value prefix of the record written when a job is stopped.
+pub const STOPPED_MARKER: &str = "reporter_stopped";
+
+/// Exit code of the record written when a job is stopped. Commands exit
+/// with 0 to 255, or -1 when they could not run or timed out.
+pub const STOPPED_EXIT_CODE: i32 = -2;
+
+/// A single sample produced by one run of the job's command.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Record {
+    pub timestamp: String,
+    pub value: String,
+    pub exit_code: i32,
+    /// Additional named fields, stored after the core columns in insertion order.
+    #[serde(flatten)]
+    pub fields: Map<String, Value>,
+}
+
+impl Record {
+    pub fn new(timestamp: impl Into<String>, value: impl Into<String>, exit_code: i32) -> Self {
+        Self {
+            timestamp: timestamp.into(),
+            value: value.into(),
+            exit_code,
+            fields: Map::new(),
+        }
+    }
+
+    /// Whether trep added this record itself to mark a stopped job, rather
+    /// than a run producing it. Readers that look at runs skip it.
+    pub fn is_marker(&self) -> bool {
+        self.exit_code == STOPPED_EXIT_CODE && self.value.starts_with(STOPPED_MARKER)
+    }
+}
+
+/// Render an extra field for a text column: strings as-is, everything else as JSON.
+pub fn field_to_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+/// Options that only affect CSV output.
+#[derive(Debug, Clone)]
+pub struct CsvOptions {
+    /// Neutralize cells that spreadsheet applications would run as formulas.
+    pub escape_formulas: bool,
+    /// End rows with `\r\n` instead of `\n`.
+    pub crlf: bool,
+    /// Start new files with a UTF-8 byte order mark so Excel detects the encoding.
+    pub bom: bool,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self {
+            escape_formulas: true,
+            crlf: false,
+            bom: false,
+        }
+    }
+}
+
+/// Prefix a cell with `'` if it starts like a spreadsheet formula (`=`, `+`,
+/// `-`, `@`, tab or carriage return). Plain numbers such as `-5` are kept.
+pub fn escape_formula(cell: &str) -> Cow<'_, str> {
+    let risky = cell.starts_with(['=', '+', '-', '@', '\t', '\r']);
+    if risky && cell.trim().parse::<f64>().is_err() {
+        Cow::Owned(format!("'{cell}"))
+    } else {
+        Cow::Borrowed(cell)
+    }
+}
+
+/// Writes records in the job's configured format, keeping the current file
+/// open between writes. The file is reopened when the path changes (daily
+/// rotation) or after [`RecordWriter::reopen`], e.g. once logrotate has
+/// moved it away.
+#[derive(Debug)]
+pub struct RecordWriter {
+    format: String,
+    csv: CsvOptions,
+    open: Option<OpenFile>,
+    /// Extra field names of the last record written.
+    last_fields: Vec<String>,
+}
+
+#[derive(Debug)]
+struct OpenFile {
+    path: PathBuf,
+    file: File,
+    /// Nothing has been written to the file yet, so it still needs a BOM
+    /// and header row.
+    empty: bool,
+}
+
+impl OpenFile {
+    fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let empty = file.metadata()?.len() == 0;
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            empty,
+        })
+    }
+}
+
+impl RecordWriter {
+    pub fn new(format: impl Into<String>, csv: CsvOptions) -> Self {
+        Self {
+            format: format.into(),
+            csv,
+            open: None,
+            last_fields: Vec::new(),
+        }
+    }
+
+    pub fn write(&mut self, path: &Path, record: &Record) -> io::Result<()> {
+        let _span = tracing::info_span!("write", path = %path.display()).entered();
+        let open = match &mut self.open {
+            Some(open) if open.path == path => open,
+            slot => slot.insert(tracing::debug_span!("open").in_scope(|| OpenFile::open(path))?),
+        };
+        if self.format == "csv" {
+            write_csv_row(&mut open.file, open.empty, record, &self.csv)?;
+        } else {
+            write_jsonl_row(&mut open.file, record)?;
+        }
+        open.empty = false;
+        self.last_fields = record.fields.keys().cloned().collect();
+        Ok(())
+    }
+
+    /// Write a record that trep itself adds to the data, such as the marker
+    /// for a stopped job, and flush it to disk. It gets the last record's
+    /// extra fields as nulls so CSV columns stay aligned.
+    pub fn write_marker(&mut self, path: &Path, mut record: Record) -> io::Result<()> {
+        for field in &self.last_fields {
+            record.fields.entry(field.clone()).or_insert(Value::Null);
+        }
+        self.write(path, &record)?;
+        match &self.open {
+            Some(open) => open.file.sync_all(),
+            None => Ok(()),
+        }
+    }
+
+    /// Close the current file; the next write opens its path again.
+    pub fn reopen(&mut self) {
+        self.open = None;
+    }
+}
+
+/// Where a running job appends its records: day files or a database.
+pub trait RecordStore: Send {
+    /// Append a record belonging to the local day `date`.
+    fn write(&mut self, date: NaiveDate, record: &Record) -> io::Result<()>;
+    /// Append a record that trep itself adds, such as the marker for a
+    /// stopped job, and make sure it reaches the disk.
+    fn write_marker(&mut self, date: NaiveDate, record: Record) -> io::Result<()>;
+    /// Close whatever is open, so the next write opens it again (after
+    /// rotation, or when logrotate moved a file away).
+    fn reopen(&mut self);
+}
+
+/// Open the store for a job's data directory in `format` ("csv", "jsonl"
+/// or "sqlite"). With `rejects`, records go to the rejects files or table.
+pub fn open_store(
+    dir: &Path,
+    format: &str,
+    csv: CsvOptions,
+    rejects: bool,
+) -> io::Result<Box<dyn RecordStore>> {
+    if format == "sqlite" {
+        let table = if rejects {
+            sqlite::Table::Rejects
+        } else {
+            sqlite::Table::Records
+        };
+        return Ok(Box::new(sqlite::SqliteStore::open(
+            &dir.join(DATABASE_FILE),
+            table,
+        )?));
+    }
+    Ok(Box::new(FileStore {
+        dir: dir.to_path_buf(),
+        format: format.to_string(),
+        rejects,
+        writer: RecordWriter::new(format, csv),
+    }))
+}
+
+/// One CSV or JSONL file per day.
+#[derive(Debug)]
+struct FileStore {
+    dir: PathBuf,
+    format: String,
+    rejects: bool,
+    writer: RecordWriter,
+}
+
+impl FileStore {
+    fn path(&self, date: NaiveDate) -> PathBuf {
+        if self.rejects {
+            util::rejects_file_path(&self.dir, &date, &self.format)
+        } else {
+            util::record_file_path(&self.dir, &date, &self.format)
+        }
+    }
+}
+
+impl RecordStore for FileStore {
+    fn write(&mut self, date: NaiveDate, record: &Record) -> io::Result<()> {
+        let path = self.path(date);
+        self.writer.write(&path, record)
+    }
+
+    fn write_marker(&mut self, date: NaiveDate, record: Record) -> io::Result<()> {
+        let path = self.path(date);
+        self.writer.write_marker(&path, record)
+    }
+
+    fn reopen(&mut self) {
+        self.writer.reopen();
+    }
+}
+
+/// Append a record as a CSV row. A header row is only written when the
+/// record carries extra fields and the file is new, so plain
+/// `timestamp,value,exit_code` files stay headerless.
+fn write_csv_row(file: &mut File, new: bool, record: &Record, opts: &CsvOptions) -> io::Result<()> {
+    if new && opts.bom {
+        file.write_all("\u{feff}".as_bytes())?;
+    }
+    let terminator = if opts.crlf {
+        csv::Terminator::CRLF
+    } else {
+        csv::Terminator::Any(b'\n')
+    };
     let mut wtr = csv::WriterBuilder::new()
-        .has_headers(!file_exists)
+        .terminator(terminator)
         .from_writer(file);
-    wtr.write_record([timestamp, value, &exit_code.to_string()])?;
+    if new && !record.fields.is_empty() {
+        let mut header = vec!["timestamp", "value", "exit_code"];
+        header.extend(record.fields.keys().map(String::as_str));
+        wtr.write_record(&header)?;
+    }
+    let mut row = vec![
+        record.timestamp.clone(),
+        record.value.clone(),
+        record.exit_code.to_string(),
+    ];
+    row.extend(record.fields.values().map(field_to_string));
+    if opts.escape_formulas {
+        for cell in row.iter_mut() {
+            if let Cow::Owned(escaped) = escape_formula(cell) {
+                *cell = escaped;
+            }
+        }
+    }
+    wtr.write_record(&row)?;
     wtr.flush()?;
     Ok(())
 }
 
-pub fn write_jsonl_record(
-    path: &Path,
-    timestamp: &str,
-    value: &str,
-    exit_code: i32,
-) -> io::Result<()> {
-    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
-    let record = JsonRecord {
-        timestamp,
-        value,
-        exit_code,
-    };
-    let json = serde_json::to_string(&record)?;
+fn write_jsonl_row(file: &mut File, record: &Record) -> io::Result<()> {
+    let json = serde_json::to_string(record)?;
     writeln!(file, "{json}")?;
     Ok(())
 }
 
-pub fn ensure_data_dir(name: &str) -> io::Result<PathBuf> {
-    // Determine base directory: ~/.tiny-reporter/<name>
-    let base = match directories::BaseDirs::new() {
-        Some(b) => b.home_dir().to_path_buf(),
-        None => PathBuf::from("."),
+/// Directory holding every job's data directory: ~/.tiny-reporter, or
+/// `TREP_HOME` when set (for example a network share on Windows). On
+/// Windows it is made absolute with a verbatim prefix, so data files and
+/// locks beyond 260 characters and on UNC shares work.
+pub fn base_dir() -> PathBuf {
+    let dir = match std::env::var_os("TREP_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => home_dir().join(".tiny-reporter"),
     };
-    let dir = base.join(".tiny-reporter").join(name);
+    long_path(&dir)
+}
+
+/// The user's profile folder (the known folder on Windows), or `USERPROFILE`
+/// / `HOME` when it cannot be looked up, as for some service accounts.
+fn home_dir() -> PathBuf {
+    if let Some(dirs) = directories::BaseDirs::new() {
+        return dirs.home_dir().to_path_buf();
+    }
+    let var = if cfg!(windows) { "USERPROFILE" } else { "HOME" };
+    std::env::var_os(var)
+        .filter(|home| !home.is_empty())
+        .map_or_else(|| PathBuf::from("."), PathBuf::from)
+}
+
+/// `path` in the form Windows accepts beyond 260 characters: absolute, with
+/// a `\\?\` prefix. Other platforms have no such limit and get it unchanged.
+fn long_path(path: &Path) -> PathBuf {
+    #[cfg(windows)]
+    if let Ok(abs) = std::path::absolute(path) {
+        let verbatim = abs.to_str().and_then(verbatim);
+        return verbatim.map_or(abs, PathBuf::from);
+    }
+    path.to_path_buf()
+}
+
+/// The verbatim form of an absolute Windows path: `C:\x` becomes `\\?\C:\x`
+/// and `\\server\share\x` becomes `\\?\UNC\server\share\x`. Relative,
+/// device and already verbatim paths give `None`.
+#[cfg(any(windows, test))]
+fn verbatim(path: &str) -> Option<String> {
+    if path.starts_with(r"\\?\") || path.starts_with(r"\\.\") {
+        return None;
+    }
+    if let Some(unc) = path.strip_prefix(r"\\") {
+        return Some(format!(r"\\?\UNC\{unc}"));
+    }
+    match path.as_bytes() {
+        [drive, b':', b'\\', ..] if drive.is_ascii_alphabetic() => Some(format!(r"\\?\{path}")),
+        _ => None,
+    }
+}
+
+/// Directory holding a job's data files: ~/.tiny-reporter/<name>.
+pub fn data_dir(name: &str) -> PathBuf {
+    base_dir().join(name)
+}
+
+/// Names of the jobs with a data directory under `base`, sorted.
+pub fn job_names(base: &Path) -> io::Result<Vec<String>> {
+    let mut names = Vec::new();
+    for entry in fs::read_dir(base)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            if let Some(name) = entry.file_name().to_str() {
+                names.push(name.to_string());
+            }
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// What a running job publishes about itself for other trep commands, in
+/// a state file next to its lock.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct JobMeta {
+    pub pid: u32,
+    /// The command, collector or check the job samples.
+    pub source: String,
+    /// Seconds between runs; absent for a single run.
+    pub interval_secs: Option<f64>,
+    /// Cron expression the job runs at, instead of an interval.
+    #[serde(default)]
+    pub cron: Option<String>,
+    /// When the next run is due (RFC 3339), once the current one finished.
+    pub next_run: Option<String>,
+    /// How long the last run took, in seconds.
+    #[serde(default)]
+    pub last_duration_secs: Option<f64>,
+    /// When the job started (RFC 3339).
+    #[serde(default)]
+    pub started_at: Option<String>,
+    /// Timestamp of the last run's record.
+    #[serde(default)]
+    pub last_run: Option<String>,
+    /// Exit code of the last run.
+    #[serde(default)]
+    pub last_exit_code: Option<i32>,
+}
+
+/// Metadata file of a running job: ~/.tiny-reporter/<name>/<name>.meta.json,
+/// or in the data directory the job runs with (see [`job_dir`]). It exists
+/// only while `trep run` holds the job.
+pub fn meta_path(name: &str) -> PathBuf {
+    meta_path_in(&job_dir(name), name)
+}
+
+/// Metadata file of the job `name` keeping its data in `dir`.
+pub fn meta_path_in(dir: &Path, name: &str) -> PathBuf {
+    dir.join(format!("{name}.meta.json"))
+}
+
+/// Where `--on-change` keeps the last stored sample of the job `name`
+/// keeping its data in `dir`, so it survives restarts.
+pub fn last_path_in(dir: &Path, name: &str) -> PathBuf {
+    dir.join(format!("{name}.last.json"))
+}
+
+/// Replace the metadata file in one step, so readers never see half of it.
+pub fn write_job_meta(path: &Path, meta: &JobMeta) -> io::Result<()> {
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, serde_json::to_vec(meta)?)?;
+    fs::rename(&tmp, path)
+}
+
+/// The job's metadata, or `None` when no `trep run` publishes any.
+pub fn read_job_meta(path: &Path) -> io::Result<Option<JobMeta>> {
+    match fs::read(path) {
+        Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// The state of the job `name` if a `trep run` holds it now. A state file
+/// left behind by a job that did not exit cleanly is ignored.
+pub fn running_job(name: &str) -> io::Result<Option<JobMeta>> {
+    let Some(meta) = read_job_meta(&meta_path(name))? else {
+        return Ok(None);
+    };
+    Ok(is_locked(&lock_path(name))?.then_some(meta))
+}
+
+/// Whether a process holds the lock file at `lock_path`.
+pub fn is_locked(lock_path: &Path) -> io::Result<bool> {
+    let file = match File::open(lock_path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e),
+    };
+    match FileExt::try_lock_shared(&file) {
+        Ok(()) => Ok(false),
+        Err(e) if e.raw_os_error() == fs2::lock_contended_error().raw_os_error() => Ok(true),
+        Err(e) => Err(e),
+    }
+}
+
+/// Lock file held by a running job: ~/.tiny-reporter/<name>/<name>.lock,
+/// or in the data directory the job runs with (see [`job_dir`]).
+pub fn lock_path(name: &str) -> PathBuf {
+    lock_path_in(&job_dir(name), name)
+}
+
+/// The directory the job `name` last ran with: its directory under the
+/// base directory, unless it ran with a data directory of its own, which
+/// it then records there, so the commands that read a job's state or
+/// records find it.
+pub fn job_dir(name: &str) -> PathBuf {
+    job_dir_in(&data_dir(name), name)
+}
+
+fn job_dir_in(default: &Path, name: &str) -> PathBuf {
+    match fs::read_to_string(location_path_in(default, name)) {
+        Ok(dir) if !dir.trim_end().is_empty() => PathBuf::from(dir.trim_end()),
+        _ => default.to_path_buf(),
+    }
+}
+
+/// Record `dir` as the data directory of the job `name`, for [`job_dir`].
+pub fn record_job_dir(name: &str, dir: &Path) -> io::Result<()> {
+    record_job_dir_in(&data_dir(name), name, dir)
+}
+
+fn record_job_dir_in(default: &Path, name: &str, dir: &Path) -> io::Result<()> {
+    let path = location_path_in(default, name);
+    if dir == default {
+        return match fs::remove_file(&path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        };
+    }
+    fs::create_dir_all(default)?;
+    fs::write(path, format!("{}\n", std::path::absolute(dir)?.display()))
+}
+
+/// Where a job running with a data directory of its own records it:
+/// ~/.tiny-reporter/<name>/<name>.location.
+fn location_path_in(default: &Path, name: &str) -> PathBuf {
+    default.join(format!("{name}.location"))
+}
+
+/// Lock file of the job `name` keeping its data in `dir`.
+pub fn lock_path_in(dir: &Path, name: &str) -> PathBuf {
+    dir.join(format!("{name}.lock"))
+}
+
+pub fn ensure_data_dir(name: &str) -> io::Result<PathBuf> {
+    let dir = data_dir(name);
     fs::create_dir_all(&dir)?;
     Ok(dir)
 }
 
-pub fn acquire_lock(lock_path: &Path) -> io::Result<File> {
+/// Name of a job's SQLite database in its data directory.
+pub const DATABASE_FILE: &str = "records.db";
+
+/// One day's records file of a job, or its database.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DataFile {
+    /// The day of a day file; the database holds any day and gets the
+    /// latest date, so it sorts last.
+    pub date: NaiveDate,
+    pub path: PathBuf,
+}
+
+impl DataFile {
+    /// Whether this is the job's SQLite database rather than a day file.
+    pub fn is_database(&self) -> bool {
+        self.path.extension().is_some_and(|e| e == "db")
+    }
+
+    /// Whether this is a day file gzipped by `--compress`.
+    pub fn is_compressed(&self) -> bool {
+        self.path.extension().is_some_and(|e| e == "gz")
+    }
+}
+
+/// List the `<date>.csv` and `<date>.jsonl` files in a job's data
+/// directory, gzipped or not, oldest first, followed by its database when it
+/// has one (it holds the newest records of a job moved to `--format
+/// sqlite`). Rejects files and other entries are skipped.
+pub fn list_data_files(dir: &Path) -> io::Result<Vec<DataFile>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let Some((stem, ext)) = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .map(|n| n.strip_suffix(".gz").unwrap_or(n))
+            .and_then(|n| n.rsplit_once('.'))
+        else {
+            continue;
+        };
+        if ext != "csv" && ext != "jsonl" {
+            continue;
+        }
+        if let Ok(date) = NaiveDate::parse_from_str(stem, "%Y-%m-%d") {
+            files.push(DataFile { date, path });
+        }
+    }
+    files.sort();
+    let database = dir.join(DATABASE_FILE);
+    if database.is_file() {
+        files.push(DataFile {
+            date: NaiveDate::MAX,
+            path: database,
+        });
+    }
+    Ok(files)
+}
+
+/// The data files of a job, oldest first. Fails if the job has no data
+/// directory.
+pub fn job_data_files(name: &str) -> io::Result<Vec<DataFile>> {
+    let dir = job_dir(name);
+    if !dir.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no data for job '{name}' in {}", dir.display()),
+        ));
+    }
+    list_data_files(&dir)
+}
+
+/// Read every record of a job's runs, oldest first, without stop markers.
+/// Fails if the job has no data.
+pub fn read_job_records(name: &str) -> io::Result<Vec<Record>> {
+    let mut records = Vec::new();
+    for file in job_data_files(name)? {
+        records.extend(
+            read_records(&file.path)?
+                .into_iter()
+                .filter(|r| !r.is_marker()),
+        );
+    }
+    Ok(records)
+}
+
+/// Read the records of a CSV or JSONL data file or of a database, chosen by
+/// extension. Day files ending in `.gz` are decompressed first.
+///
+/// CSV files may start with a byte order mark and a header row; without a
+/// header, columns after `exit_code` are named `col4`, `col5`, ... Extra CSV
+/// cells are typed back into numbers and booleans where they parse, and
+/// formula escapes added on write are removed.
+pub fn read_records(path: &Path) -> io::Result<Vec<Record>> {
+    if path.extension().is_some_and(|e| e == "db") {
+        return sqlite::read_records(path);
+    }
+    let (contents, path) = if path.extension().is_some_and(|e| e == "gz") {
+        let mut contents = String::new();
+        MultiGzDecoder::new(File::open(path)?).read_to_string(&mut contents)?;
+        (contents, path.with_extension(""))
+    } else {
+        (fs::read_to_string(path)?, path.to_path_buf())
+    };
+    let contents = contents.strip_prefix('\u{feff}').unwrap_or(&contents);
+    if path.extension().is_some_and(|e| e == "jsonl") {
+        return contents
+            .lines()
+            .filter(|l| !l.trim().is_empty())
+            .map(|l| serde_json::from_str(l).map_err(io::Error::from))
+            .collect();
+    }
+    let mut rdr = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(contents.as_bytes());
+    let mut header: Option<Vec<String>> = None;
+    let mut records = Vec::new();
+    for (i, row) in rdr.records().enumerate() {
+        let row = row?;
+        if i == 0 && row.get(0) == Some("timestamp") {
+            header = Some(row.iter().map(str::to_string).collect());
+            continue;
+        }
+        let exit_code = row.get(2).and_then(|c| c.parse().ok()).unwrap_or(-1);
+        let mut record = Record::new(
+            row.get(0).unwrap_or_default(),
+            unescape_formula(row.get(1).unwrap_or_default()),
+            exit_code,
+        );
+        for (col, cell) in row.iter().enumerate().skip(3) {
+            let key = header
+                .as_ref()
+                .and_then(|h| h.get(col).cloned())
+                .unwrap_or_else(|| format!("col{}", col + 1));
+            record
+                .fields
+                .insert(key, parse_cell(&unescape_formula(cell)));
+        }
+        records.push(record);
+    }
+    Ok(records)
+}
+
+/// Undo [`escape_formula`].
+fn unescape_formula(cell: &str) -> String {
+    match cell.strip_prefix('\'') {
+        Some(rest) if escape_formula(rest) != rest => rest.to_string(),
+        _ => cell.to_string(),
+    }
+}
+
+/// Type a CSV cell back into the JSON value it was written from.
+fn parse_cell(cell: &str) -> Value {
+    if cell.is_empty() {
+        return Value::Null;
+    }
+    match serde_json::from_str::<Value>(cell) {
+        Ok(v @ (Value::Number(_) | Value::Bool(_) | Value::Array(_) | Value::Object(_))) => v,
+        _ => Value::String(cell.to_string()),
+    }
+}
+
+/// Lock `lock_path` exclusively, or return `None` if another process holds it.
+pub fn try_lock(lock_path: &Path) -> io::Result<Option<File>> {
     let file = OpenOptions::new()
         .read(true)
         .create(true)
         .append(true)
         .open(lock_path)?;
     match FileExt::try_lock_exclusive(&file) {
-        Ok(()) => Ok(file),
-        Err(e) => Err(io::Error::other(format!("failed to acquire lock: {e}"))),
+        Ok(()) => Ok(Some(file)),
+        Err(e) if e.raw_os_error() == fs2::lock_contended_error().raw_os_error() => Ok(None),
+        Err(e) => Err(e),
     }
 }
 
-pub fn current_date() -> chrono::NaiveDate {
-    Local::now().date_naive()
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn sees_whether_a_lock_is_held() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("job.lock");
+        assert!(!is_locked(&path).unwrap());
+        let held = try_lock(&path).unwrap().unwrap();
+        assert!(is_locked(&path).unwrap());
+        drop(held);
+        assert!(!is_locked(&path).unwrap());
+        // Looking does not take the lock.
+        assert!(try_lock(&path).unwrap().is_some());
+    }
+
+    #[test]
+    fn finds_jobs_with_their_own_data_dir() {
+        let base = tempdir().unwrap();
+        let default = base.path().join("disk");
+        assert_eq!(job_dir_in(&default, "disk"), default);
+        let elsewhere = base.path().join("elsewhere");
+        record_job_dir_in(&default, "disk", &elsewhere).unwrap();
+        assert_eq!(job_dir_in(&default, "disk"), elsewhere);
+        // Running from the default directory again forgets it.
+        record_job_dir_in(&default, "disk", &default).unwrap();
+        assert_eq!(job_dir_in(&default, "disk"), default);
+        assert!(!location_path_in(&default, "disk").exists());
+    }
+
+    #[test]
+    fn reads_back_written_records() {
+        let dir = tempdir().unwrap();
+        let mut record = Record::new("2025-01-01T00:00:00Z", "=1+1", 0);
+        record.fields.insert("unit".into(), "B".into());
+        record.fields.insert("delta".into(), Value::Null);
+        record.fields.insert("valid".into(), true.into());
+        let mut next = record.clone();
+        next.value = "42".into();
+        next.fields.insert("delta".into(), 2.5.into());
+
+        let csv_path = dir.path().join("2025-01-01.csv");
+        let opts = CsvOptions {
+            bom: true,
+            ..CsvOptions::default()
+        };
+        let mut writer = RecordWriter::new("csv", opts);
+        writer.write(&csv_path, &record).unwrap();
+        writer.write(&csv_path, &next).unwrap();
+        let jsonl_path = dir.path().join("2025-01-02.jsonl");
+        RecordWriter::new("jsonl", CsvOptions::default())
+            .write(&jsonl_path, &next)
+            .unwrap();
+        fs::write(dir.path().join("2025-01-01.rejects.csv"), "x").unwrap();
+
+        let read = read_records(&csv_path).unwrap();
+        assert_eq!(read.len(), 2);
+        assert_eq!(read[0].value, "=1+1");
+        assert_eq!(read[0].fields, record.fields);
+        assert_eq!(read[1].fields, next.fields);
+        assert_eq!(read_records(&jsonl_path).unwrap()[0].fields, next.fields);
+
+        let files = list_data_files(dir.path()).unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[1].path, jsonl_path);
+    }
+
+    #[test]
+    fn writer_reopens_moved_file() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("2025-01-01.csv");
+        let rotated = dir.path().join("2025-01-01.csv.1");
+        let mut record = Record::new("t", "1", 0);
+        record.fields.insert("unit".into(), "s".into());
+        let mut writer = RecordWriter::new("csv", CsvOptions::default());
+        writer.write(&path, &record).unwrap();
+        fs::rename(&path, &rotated).unwrap();
+        // Until reopened, writes follow the open file like a plain log.
+        writer.write(&path, &record).unwrap();
+        writer.reopen();
+        writer.write(&path, &record).unwrap();
+        assert_eq!(
+            fs::read_to_string(&rotated).unwrap(),
+            "timestamp,value,exit_code,unit\nt,1,0,s\nt,1,0,s\n"
+        );
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "timestamp,value,exit_code,unit\nt,1,0,s\n"
+        );
+    }
+
+    #[test]
+    fn markers_keep_csv_columns_aligned() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("2025-01-01.csv");
+        let mut record = Record::new("t", "1", 0);
+        record.fields.insert("unit".into(), "s".into());
+        record.fields.insert("slow".into(), false.into());
+        let mut writer = RecordWriter::new("csv", CsvOptions::default());
+        writer.write(&path, &record).unwrap();
+        writer
+            .write_marker(&path, Record::new("u", "stopped", 0))
+            .unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "timestamp,value,exit_code,unit,slow\nt,1,0,s,false\nu,stopped,0,,\n"
+        );
+    }
+
+    #[test]
+    fn headerless_csv_names_extra_columns() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("2025-01-01.csv");
+        fs::write(&path, "t,1,0\nt,2,0,extra\n").unwrap();
+        let read = read_records(&path).unwrap();
+        assert!(read[0].fields.is_empty());
+        assert_eq!(read[1].fields["col4"], "extra");
+    }
+
+    #[test]
+    fn verbatim_paths_for_drives_and_shares() {
+        assert_eq!(
+            verbatim(r"C:\Users\me\.tiny-reporter").as_deref(),
+            Some(r"\\?\C:\Users\me\.tiny-reporter")
+        );
+        assert_eq!(
+            verbatim(r"\\files\metrics\trep").as_deref(),
+            Some(r"\\?\UNC\files\metrics\trep")
+        );
+        assert_eq!(verbatim(r"\\?\C:\trep"), None);
+        assert_eq!(verbatim("relative"), None);
+    }
+
+    #[test]
+    fn write_csv_and_jsonl() {
+        let dir = tempdir().unwrap();
+        let csv_path = dir.path().join("out.csv");
+        let jsonl_path = dir.path().join("out.jsonl");
+        let record = Record::new("2025-01-01T00:00:00Z", "hello", 0);
+        RecordWriter::new("csv", CsvOptions::default())
+            .write(&csv_path, &record)
+            .unwrap();
+        let csv_contents = std::fs::read_to_string(&csv_path).unwrap();
+        assert!(csv_contents.contains("2025-01-01T00:00:00Z,hello,0"));
+
+        RecordWriter::new("jsonl", CsvOptions::default())
+            .write(&jsonl_path, &record)
+            .unwrap();
+        let jsonl_contents = std::fs::read_to_string(&jsonl_path).unwrap();
+        assert!(jsonl_contents.trim().starts_with("{"));
+        assert!(jsonl_contents.contains("\"timestamp\":"));
+        assert!(jsonl_contents.contains("\"value\":"));
+        assert!(jsonl_contents.contains("\"exit_code\":"));
+    }
+
+    #[test]
+    fn csv_excel_options_add_bom_and_crlf() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("excel.csv");
+        let opts = CsvOptions {
+            crlf: true,
+            bom: true,
+            ..CsvOptions::default()
+        };
+        let mut writer = RecordWriter::new("csv", opts);
+        writer.write(&path, &Record::new("t", "温度", 0)).unwrap();
+        writer.write(&path, &Record::new("t", "2", 0)).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        assert_eq!(&bytes[..3], b"\xef\xbb\xbf");
+        assert_eq!(&bytes[3..], "t,温度,0\r\nt,2,0\r\n".as_bytes());
+    }
+
+    #[test]
+    fn csv_escapes_formula_values() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("out.csv");
+        let mut writer = RecordWriter::new("csv", CsvOptions::default());
+        writer
+            .write(&path, &Record::new("t", "=HYPERLINK(\"x\")", 0))
+            .unwrap();
+        writer.write(&path, &Record::new("t", "-5", 0)).unwrap();
+        writer
+            .write(&path, &Record::new("t", "@SUM(A1)", 0))
+            .unwrap();
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines[0], "t,\"'=HYPERLINK(\"\"x\"\")\",0");
+        assert_eq!(lines[1], "t,-5,0");
+        assert_eq!(lines[2], "t,'@SUM(A1),0");
+
+        let raw = CsvOptions {
+            escape_formulas: false,
+            ..CsvOptions::default()
+        };
+        let raw_path = dir.path().join("raw.csv");
+        RecordWriter::new("csv", raw)
+            .write(&raw_path, &Record::new("t", "=1+1", 0))
+            .unwrap();
+        assert_eq!(std::fs::read_to_string(&raw_path).unwrap(), "t,=1+1,0\n");
+    }
 }
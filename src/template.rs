@@ -0,0 +1,121 @@
+use std::io;
+
+use crate::storage::{self, Record};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Literal(String),
+    Placeholder(String),
+}
+
+/// A value template such as `{value} °C`. Placeholders name a record
+/// column (`value`, `exit_code`, `timestamp`) or an extra field; `{{` and
+/// `}}` produce literal braces.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValueTemplate {
+    parts: Vec<Part>,
+}
+
+fn invalid(template: &str, msg: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("invalid value template '{template}': {msg}"),
+    )
+}
+
+impl ValueTemplate {
+    pub fn parse(template: &str) -> io::Result<Self> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => return Err(invalid(template, "unclosed '{'")),
+                        }
+                    }
+                    let name = name.trim();
+                    if name.is_empty() {
+                        return Err(invalid(template, "empty placeholder"));
+                    }
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(Part::Placeholder(name.to_string()));
+                }
+                '}' => return Err(invalid(template, "unmatched '}' (use '}}')")),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+        Ok(Self { parts })
+    }
+
+    /// Render the template for `record`. Unknown placeholders render empty.
+    pub fn render(&self, record: &Record) -> String {
+        let mut out = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(s) => out.push_str(s),
+                Part::Placeholder(name) => match name.as_str() {
+                    "value" => out.push_str(&record.value),
+                    "exit_code" => out.push_str(&record.exit_code.to_string()),
+                    "timestamp" => out.push_str(&record.timestamp),
+                    field => {
+                        if let Some(v) = record.fields.get(field) {
+                            out.push_str(&storage::field_to_string(v));
+                        }
+                    }
+                },
+            }
+        }
+        out
+    }
+}
+
+/// Round `value` to `precision` decimal places if it is numeric; other
+/// values are returned unchanged.
+pub fn apply_precision(value: &str, precision: usize) -> String {
+    match value.trim().parse::<f64>() {
+        Ok(n) if n.is_finite() => format!("{n:.precision$}"),
+        _ => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_placeholders_and_escapes() {
+        let t = ValueTemplate::parse("{value} °C {{exit={exit_code}}} {unit}").unwrap();
+        let mut record = Record::new("2025-01-01T00:00:00Z", "21.5", 0);
+        record.fields.insert("unit".into(), "celsius".into());
+        assert_eq!(t.render(&record), "21.5 °C {exit=0} celsius");
+        assert!(ValueTemplate::parse("{value").is_err());
+        assert!(ValueTemplate::parse("{}").is_err());
+        assert!(ValueTemplate::parse("a } b").is_err());
+    }
+
+    #[test]
+    fn precision_only_touches_numbers() {
+        assert_eq!(apply_precision("3.14159", 2), "3.14");
+        assert_eq!(apply_precision("42", 1), "42.0");
+        assert_eq!(apply_precision("n/a", 2), "n/a");
+    }
+}
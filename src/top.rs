@@ -0,0 +1,282 @@
+use std::io::{self, Write};
+use std::thread;
+
+use chrono::{DateTime, Local};
+
+use crate::cli::TopOpts;
+use crate::storage::{self, DataFile, JobMeta, Record};
+use crate::util;
+
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+/// Number of recent values drawn in the trend column.
+const TREND_WIDTH: usize = 20;
+const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// One row of the overview.
+#[derive(Debug, Default)]
+pub struct JobSummary {
+    /// The newest record.
+    pub latest: Option<Record>,
+    /// Values of the newest records, oldest first, for the trend.
+    pub recent: Vec<String>,
+    /// The newest record with a non-zero exit code.
+    pub last_failure: Option<Record>,
+    /// Failed runs since the last successful one.
+    pub consecutive_failures: usize,
+}
+
+pub fn top(opts: TopOpts) -> io::Result<()> {
+    let refresh = util::duration_arg(&opts.refresh, "refresh interval")?;
+    loop {
+        let names = if opts.all {
+            let base = storage::base_dir();
+            match storage::job_names(&base) {
+                Ok(names) => names,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+                Err(e) => return Err(e),
+            }
+        } else {
+            opts.names.clone()
+        };
+        let mut table = render(&names, Local::now());
+        if names.is_empty() {
+            table = format!(
+                "No jobs in {}\n",
+                dunce::simplified(&storage::base_dir()).display()
+            );
+        }
+        let mut out = io::stdout().lock();
+        if !opts.once {
+            // Clear the screen and move the cursor home before redrawing.
+            write!(out, "\x1b[2J\x1b[H")?;
+        }
+        out.write_all(table.as_bytes())?;
+        out.flush()?;
+        if opts.once {
+            return Ok(());
+        }
+        drop(out);
+        thread::sleep(refresh);
+    }
+}
+
+/// The overview table of `names`. A job that cannot be read gets its error
+/// in place of the value, so one broken job does not hide the others.
+fn render(names: &[String], now: DateTime<Local>) -> String {
+    let mut table = format!(
+        "{:<16} {:>12} {:<width$} {:<23} {}\n",
+        "job",
+        "value",
+        "trend",
+        "last failure",
+        "next run",
+        width = TREND_WIDTH
+    );
+    for name in names {
+        let meta = storage::read_job_meta(&storage::meta_path(name)).unwrap_or(None);
+        let row = match storage::job_data_files(name).and_then(|files| summarize(&files)) {
+            Ok(summary) => {
+                let value = summary
+                    .latest
+                    .as_ref()
+                    .map_or("-".to_string(), |r| r.value.clone());
+                let failure = summary.last_failure.as_ref().map_or("-".to_string(), |r| {
+                    format!("{} ({})", local_time(&r.timestamp), r.exit_code)
+                });
+                format!(
+                    "{:>12} {:<width$} {:<23}",
+                    truncate(&value, 12),
+                    sparkline(&summary.recent),
+                    failure,
+                    width = TREND_WIDTH
+                )
+            }
+            Err(e) => format!("error: {e}"),
+        };
+        table.push_str(&format!(
+            "{:<16} {row} {}\n",
+            truncate(name, 16),
+            next_run(meta.as_ref(), now)
+        ));
+    }
+    table
+}
+
+/// Read the newest files until there are enough values for the trend and
+/// both the last failure and the last success are found (or every file was
+/// read).
+pub fn summarize(files: &[DataFile]) -> io::Result<JobSummary> {
+    let mut summary = JobSummary::default();
+    let mut succeeded = false;
+    for file in files.iter().rev() {
+        let mut records = storage::read_records(&file.path)?;
+        records.retain(|r| !r.is_marker());
+        if summary.latest.is_none() {
+            summary.latest = records.last().cloned();
+        }
+        if summary.recent.len() < TREND_WIDTH {
+            let missing = TREND_WIDTH - summary.recent.len();
+            let mut older: Vec<String> = records
+                .iter()
+                .rev()
+                .take(missing)
+                .rev()
+                .map(|r| r.value.clone())
+                .collect();
+            older.append(&mut summary.recent);
+            summary.recent = older;
+        }
+        if summary.last_failure.is_none() {
+            summary.last_failure = records.iter().rev().find(|r| r.exit_code != 0).cloned();
+        }
+        for record in records.iter().rev() {
+            if succeeded {
+                break;
+            }
+            if record.exit_code == 0 {
+                succeeded = true;
+            } else {
+                summary.consecutive_failures += 1;
+            }
+        }
+        if summary.recent.len() >= TREND_WIDTH && summary.last_failure.is_some() && succeeded {
+            break;
+        }
+    }
+    Ok(summary)
+}
+
+/// Bars scaled between the smallest and largest numeric value; values that
+/// are not numbers leave a blank.
+fn sparkline(values: &[String]) -> String {
+    let numbers: Vec<Option<f64>> = values
+        .iter()
+        .map(|v| v.trim().parse::<f64>().ok().filter(|n| n.is_finite()))
+        .collect();
+    let (min, max) = numbers
+        .iter()
+        .flatten()
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &n| {
+            (lo.min(n), hi.max(n))
+        });
+    numbers
+        .iter()
+        .map(|n| match n {
+            None => ' ',
+            Some(_) if max <= min => BARS[BARS.len() / 2],
+            Some(n) => {
+                let step = (n - min) / (max - min) * (BARS.len() - 1) as f64;
+                BARS[step.round() as usize]
+            }
+        })
+        .collect()
+}
+
+/// When the job runs next, relative to `now`: "in 25s", "due" once the time
+/// has passed, "once" for a single run in progress, or "-" when no
+/// `trep run` publishes metadata for the job.
+pub(crate) fn next_run(meta: Option<&JobMeta>, now: DateTime<Local>) -> String {
+    let Some(meta) = meta else {
+        return "-".to_string();
+    };
+    let next = meta
+        .next_run
+        .as_deref()
+        .and_then(|t| DateTime::parse_from_rfc3339(t).ok());
+    let repeats = meta.interval_secs.is_some() || meta.cron.is_some();
+    match (next, repeats) {
+        (Some(next), _) => match (next.with_timezone(&Local) - now).to_std() {
+            Ok(wait) => format!(
+                "in {}",
+                humantime::format_duration(std::time::Duration::from_secs(
+                    wait.as_secs_f64().round() as u64
+                ))
+            ),
+            Err(_) => "due".to_string(),
+        },
+        (None, true) => "running".to_string(),
+        (None, false) => "once".to_string(),
+    }
+}
+
+pub(crate) fn local_time(timestamp: &str) -> String {
+    DateTime::parse_from_rfc3339(timestamp).map_or(timestamp.to_string(), |t| {
+        t.with_timezone(&Local).format(TIME_FORMAT).to_string()
+    })
+}
+
+/// Cut `s` to `width` characters, marking the cut with "…".
+pub(crate) fn truncate(s: &str, width: usize) -> String {
+    let s = s.lines().next().unwrap_or_default();
+    if s.chars().count() <= width {
+        s.to_string()
+    } else {
+        let mut cut: String = s.chars().take(width - 1).collect();
+        cut.push('…');
+        cut
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summarizes_the_newest_records() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("2025-01-01.csv"), "a,9,2\nb,1,0\n").unwrap();
+        let rows: String = (0..25).map(|i| format!("t{i},{i},0\n")).collect();
+        std::fs::write(dir.path().join("2025-01-02.csv"), rows).unwrap();
+        let files = storage::list_data_files(dir.path()).unwrap();
+        let summary = summarize(&files).unwrap();
+        assert_eq!(summary.latest.unwrap().value, "24");
+        assert_eq!(summary.recent.len(), TREND_WIDTH);
+        assert_eq!(summary.recent[0], "5");
+        assert_eq!(summary.last_failure.unwrap().timestamp, "a");
+        assert_eq!(summary.consecutive_failures, 0);
+
+        std::fs::write(dir.path().join("2025-01-03.csv"), "x,1,1\n").unwrap();
+        std::fs::write(dir.path().join("2025-01-04.csv"), "y,1,3\nz,1,1\n").unwrap();
+        let files = storage::list_data_files(dir.path()).unwrap();
+        assert_eq!(summarize(&files).unwrap().consecutive_failures, 3);
+    }
+
+    #[test]
+    fn draws_sparklines() {
+        let values: Vec<String> = ["0", "7", "x", "3.5", "7"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert_eq!(sparkline(&values), "▁█ ▅█");
+        assert_eq!(sparkline(&["2".to_string(), "2".to_string()]), "▅▅");
+        assert_eq!(sparkline(&[]), "");
+    }
+
+    #[test]
+    fn describes_the_next_run() {
+        let now = Local::now();
+        let meta = |next_run: Option<DateTime<Local>>, interval_secs| JobMeta {
+            pid: 1,
+            source: "true".to_string(),
+            interval_secs,
+            cron: None,
+            next_run: next_run.map(|t| t.to_rfc3339()),
+            ..JobMeta::default()
+        };
+        let soon = now + chrono::Duration::seconds(90);
+        assert_eq!(
+            next_run(Some(&meta(Some(soon), Some(120.0))), now),
+            "in 1m 30s"
+        );
+        let past = now - chrono::Duration::seconds(5);
+        assert_eq!(next_run(Some(&meta(Some(past), Some(120.0))), now), "due");
+        assert_eq!(next_run(Some(&meta(None, Some(120.0))), now), "running");
+        assert_eq!(next_run(Some(&meta(None, None)), now), "once");
+        let cron = JobMeta {
+            cron: Some("0 9 * * *".to_string()),
+            ..meta(None, None)
+        };
+        assert_eq!(next_run(Some(&cron), now), "running");
+        assert_eq!(next_run(None, now), "-");
+    }
+}
@@ -0,0 +1,451 @@
+use std::collections::hash_map::RandomState;
+use std::fs::{File, OpenOptions};
+use std::hash::BuildHasher;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use chrono::{DateTime, Utc};
+use serde_json::{json, Map, Value};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::Subscriber;
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+const OTLP_DEFAULT_ENDPOINT: &str = "http://localhost:4318/v1/traces";
+const OTLP_TIMEOUT: Duration = Duration::from_secs(5);
+/// Export pending spans to an OTLP collector once this many have finished,
+/// even if the enclosing trace is still open.
+const OTLP_BATCH: usize = 512;
+
+/// Records finished spans (name, timing, fields and parent) and exports them
+/// to a JSON-lines file or an OTLP/HTTP collector. Every span without a
+/// parent starts a new trace; spans are flushed when their trace's root closes.
+pub struct SpanExportLayer {
+    exporter: Mutex<Exporter>,
+    ids: RandomState,
+    next_id: AtomicU64,
+}
+
+enum Exporter {
+    File(BufWriter<File>),
+    /// Hands spans to the thread that sends them, so a slow or unreachable
+    /// collector never holds up the job whose spans these are.
+    Otlp(Sender<OtlpMessage>),
+}
+
+enum OtlpMessage {
+    /// A finished span, and whether it is its trace's root.
+    Span(FinishedSpan, bool),
+    /// Send whatever is pending, then report back.
+    Flush(Sender<()>),
+}
+
+/// Sends the spans an OTLP exporter still holds when dropped, waiting up to
+/// the export timeout for the collector. Keep it until the program is done.
+#[must_use]
+pub struct TraceGuard(Option<Sender<OtlpMessage>>);
+
+impl Drop for TraceGuard {
+    fn drop(&mut self) {
+        let Some(sender) = &self.0 else { return };
+        let (done, flushed) = mpsc::channel();
+        if sender.send(OtlpMessage::Flush(done)).is_ok() {
+            let _ = flushed.recv_timeout(OTLP_TIMEOUT + Duration::from_secs(1));
+        }
+    }
+}
+
+struct OtlpExporter {
+    agent: ureq::Agent,
+    endpoint: String,
+    service: String,
+    pending: Vec<FinishedSpan>,
+    /// Whether the last export failed, so repeated failures are reported once.
+    failing: bool,
+}
+
+/// Bookkeeping stored in each open span's extensions.
+struct SpanData {
+    trace_id: u128,
+    span_id: u64,
+    parent_id: Option<u64>,
+    start: SystemTime,
+    fields: Map<String, Value>,
+}
+
+struct FinishedSpan {
+    name: &'static str,
+    data: SpanData,
+    end: SystemTime,
+}
+
+/// Build the export layer for `--trace`: "otlp" sends spans to the
+/// collector named by `OTEL_EXPORTER_OTLP_TRACES_ENDPOINT` or
+/// `OTEL_EXPORTER_OTLP_ENDPOINT` (default localhost:4318); anything else is
+/// a file that finished spans are appended to as JSON lines.
+pub fn layer(target: &str) -> io::Result<(SpanExportLayer, TraceGuard)> {
+    if target == "otlp" {
+        let endpoint = std::env::var("OTEL_EXPORTER_OTLP_TRACES_ENDPOINT")
+            .ok()
+            .or_else(|| {
+                let base = std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT").ok()?;
+                Some(format!("{}/v1/traces", base.trim_end_matches('/')))
+            })
+            .unwrap_or_else(|| OTLP_DEFAULT_ENDPOINT.to_string());
+        let service = std::env::var("OTEL_SERVICE_NAME").unwrap_or_else(|_| "trep".to_string());
+        otlp_layer(endpoint, service)
+    } else {
+        let path = PathBuf::from(target);
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|e| {
+                io::Error::new(
+                    e.kind(),
+                    format!("cannot open trace file {}: {e}", path.display()),
+                )
+            })?;
+        let exporter = Exporter::File(BufWriter::new(file));
+        Ok((SpanExportLayer::new(exporter), TraceGuard(None)))
+    }
+}
+
+/// Export to the OTLP/HTTP collector at `endpoint` from a thread of its own.
+fn otlp_layer(endpoint: String, service: String) -> io::Result<(SpanExportLayer, TraceGuard)> {
+    let agent = ureq::Agent::config_builder()
+        .timeout_global(Some(OTLP_TIMEOUT))
+        .build()
+        .into();
+    let mut otlp = OtlpExporter {
+        agent,
+        endpoint,
+        service,
+        pending: Vec::new(),
+        failing: false,
+    };
+    let (sender, spans) = mpsc::channel();
+    thread::Builder::new()
+        .name("otlp-export".to_string())
+        .spawn(move || {
+            for message in spans {
+                match message {
+                    OtlpMessage::Span(span, root) => {
+                        otlp.pending.push(span);
+                        if root || otlp.pending.len() >= OTLP_BATCH {
+                            otlp.flush();
+                        }
+                    }
+                    OtlpMessage::Flush(done) => {
+                        if !otlp.pending.is_empty() {
+                            otlp.flush();
+                        }
+                        let _ = done.send(());
+                    }
+                }
+            }
+        })?;
+    let layer = SpanExportLayer::new(Exporter::Otlp(sender.clone()));
+    Ok((layer, TraceGuard(Some(sender))))
+}
+
+impl SpanExportLayer {
+    fn new(exporter: Exporter) -> Self {
+        Self {
+            exporter: Mutex::new(exporter),
+            ids: RandomState::new(),
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    fn random_u64(&self) -> u64 {
+        self.ids
+            .hash_one(self.next_id.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+struct FieldVisitor<'a>(&'a mut Map<String, Value>);
+
+impl Visit for FieldVisitor<'_> {
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0
+            .insert(field.name().to_string(), format!("{value:?}").into());
+    }
+}
+
+impl<S> Layer<S> for SpanExportLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else { return };
+        let parent = span.parent().and_then(|p| {
+            let ext = p.extensions();
+            ext.get::<SpanData>().map(|d| (d.trace_id, d.span_id))
+        });
+        let (trace_id, parent_id) = match parent {
+            Some((trace_id, parent_id)) => (trace_id, Some(parent_id)),
+            None => (
+                (u128::from(self.random_u64()) << 64) | u128::from(self.random_u64()),
+                None,
+            ),
+        };
+        let mut fields = Map::new();
+        attrs.record(&mut FieldVisitor(&mut fields));
+        span.extensions_mut().insert(SpanData {
+            trace_id,
+            span_id: self.random_u64(),
+            parent_id,
+            start: SystemTime::now(),
+            fields,
+        });
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(data) = span.extensions_mut().get_mut::<SpanData>() {
+                values.record(&mut FieldVisitor(&mut data.fields));
+            }
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else { return };
+        let Some(data) = span.extensions_mut().remove::<SpanData>() else {
+            return;
+        };
+        let finished = FinishedSpan {
+            name: span.metadata().name(),
+            data,
+            end: SystemTime::now(),
+        };
+        let root = finished.data.parent_id.is_none();
+        if let Ok(mut exporter) = self.exporter.lock() {
+            exporter.export(finished, root);
+        }
+    }
+}
+
+impl Exporter {
+    fn export(&mut self, span: FinishedSpan, root: bool) {
+        match self {
+            Exporter::File(out) => {
+                let line = file_json(&span);
+                // The tracing pipeline has nowhere to report its own failures.
+                let _ = writeln!(out, "{line}");
+                if root {
+                    let _ = out.flush();
+                }
+            }
+            Exporter::Otlp(sender) => {
+                // The thread only goes away with the process.
+                let _ = sender.send(OtlpMessage::Span(span, root));
+            }
+        }
+    }
+}
+
+impl OtlpExporter {
+    fn flush(&mut self) {
+        let spans = std::mem::take(&mut self.pending);
+        let body = otlp_json(&self.service, &spans).to_string();
+        let result = self
+            .agent
+            .post(&self.endpoint)
+            .header("Content-Type", "application/json")
+            .send(body);
+        match result {
+            Ok(_) => self.failing = false,
+            Err(e) => {
+                if !self.failing {
+                    eprintln!("Warning: exporting traces to {} failed: {e}", self.endpoint);
+                }
+                self.failing = true;
+            }
+        }
+    }
+}
+
+fn nanos(t: SystemTime) -> u128 {
+    t.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos()
+}
+
+/// One line of the `--trace <file>` output.
+fn file_json(span: &FinishedSpan) -> Value {
+    let d = &span.data;
+    let duration = span.end.duration_since(d.start).unwrap_or_default();
+    json!({
+        "name": span.name,
+        "trace_id": format!("{:032x}", d.trace_id),
+        "span_id": format!("{:016x}", d.span_id),
+        "parent_span_id": d.parent_id.map(|p| format!("{p:016x}")),
+        "start": DateTime::<Utc>::from(d.start).to_rfc3339(),
+        "duration_ms": duration.as_secs_f64() * 1000.0,
+        "fields": d.fields,
+    })
+}
+
+/// An OTLP/HTTP JSON `ExportTraceServiceRequest` for `spans`.
+fn otlp_json(service: &str, spans: &[FinishedSpan]) -> Value {
+    let attribute = |key: &str, value: &Value| {
+        let value = match value {
+            Value::Bool(b) => json!({ "boolValue": b }),
+            Value::Number(n) if n.is_i64() || n.is_u64() => json!({ "intValue": n.to_string() }),
+            Value::Number(n) => json!({ "doubleValue": n.as_f64() }),
+            Value::String(s) => json!({ "stringValue": s }),
+            other => json!({ "stringValue": other.to_string() }),
+        };
+        json!({ "key": key, "value": value })
+    };
+    let spans: Vec<Value> = spans
+        .iter()
+        .map(|span| {
+            let d = &span.data;
+            let mut out = json!({
+                "traceId": format!("{:032x}", d.trace_id),
+                "spanId": format!("{:016x}", d.span_id),
+                "name": span.name,
+                "kind": 1,
+                "startTimeUnixNano": nanos(d.start).to_string(),
+                "endTimeUnixNano": nanos(span.end).to_string(),
+                "attributes": d.fields.iter().map(|(k, v)| attribute(k, v)).collect::<Vec<_>>(),
+            });
+            if let Some(parent) = d.parent_id {
+                out["parentSpanId"] = format!("{parent:016x}").into();
+            }
+            out
+        })
+        .collect();
+    json!({
+        "resourceSpans": [{
+            "resource": {
+                "attributes": [attribute("service.name", &Value::from(service))]
+            },
+            "scopeSpans": [{
+                "scope": { "name": "trep", "version": env!("CARGO_PKG_VERSION") },
+                "spans": spans
+            }]
+        }]
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[test]
+    fn writes_nested_spans_to_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("trace.jsonl");
+        let (layer, _guard) = layer(path.to_str().unwrap()).unwrap();
+        let subscriber = tracing_subscriber::registry().with(layer);
+        tracing::subscriber::with_default(subscriber, || {
+            let tick = tracing::info_span!("tick", job = "cpu", drift_ms = tracing::field::Empty);
+            let _enter = tick.enter();
+            tracing::info_span!("exec", exit_code = 0).in_scope(|| {});
+            tick.record("drift_ms", 12);
+        });
+        let lines: Vec<Value> = std::fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        let (exec, tick) = (&lines[0], &lines[1]);
+        assert_eq!(exec["name"], "exec");
+        assert_eq!(exec["fields"]["exit_code"], 0);
+        assert_eq!(exec["trace_id"], tick["trace_id"]);
+        assert_eq!(exec["parent_span_id"], tick["span_id"]);
+        assert_eq!(tick["parent_span_id"], Value::Null);
+        assert_eq!(tick["fields"]["job"], "cpu");
+        assert_eq!(tick["fields"]["drift_ms"], 12);
+    }
+
+    #[test]
+    fn exports_to_otlp_off_the_job_thread() {
+        let collector = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let endpoint = format!(
+            "http://{}/v1/traces",
+            collector.server_addr().to_ip().unwrap()
+        );
+        let (bodies, received) = mpsc::channel();
+        thread::spawn(move || {
+            for mut request in collector.incoming_requests() {
+                // A slow collector.
+                thread::sleep(Duration::from_millis(500));
+                let mut body = String::new();
+                request.as_reader().read_to_string(&mut body).unwrap();
+                let _ = request.respond(tiny_http::Response::empty(200));
+                let _ = bodies.send(body);
+            }
+        });
+        let (layer, guard) = otlp_layer(endpoint, "trep".to_string()).unwrap();
+        let subscriber = tracing_subscriber::registry().with(layer);
+        let started = std::time::Instant::now();
+        tracing::subscriber::with_default(subscriber, || {
+            for _ in 0..3 {
+                tracing::info_span!("tick", job = "cpu").in_scope(|| {});
+            }
+        });
+        assert!(started.elapsed() < Duration::from_millis(400));
+
+        // The guard waits for the spans still queued.
+        drop(guard);
+        let bodies: Vec<String> = received.try_iter().collect();
+        assert_eq!(bodies.len(), 3);
+        assert!(bodies.iter().all(|b| b.contains("\"name\":\"tick\"")));
+    }
+
+    #[test]
+    fn builds_otlp_request() {
+        let start = UNIX_EPOCH + Duration::from_secs(1);
+        let mut fields = Map::new();
+        fields.insert("exit_code".into(), 1.into());
+        let span = FinishedSpan {
+            name: "exec",
+            data: SpanData {
+                trace_id: 1,
+                span_id: 2,
+                parent_id: Some(3),
+                start,
+                fields,
+            },
+            end: start + Duration::from_millis(5),
+        };
+        let request = otlp_json("trep", &[span]);
+        let resource = &request["resourceSpans"][0];
+        assert_eq!(
+            resource["resource"]["attributes"][0]["value"]["stringValue"],
+            "trep"
+        );
+        let span = &resource["scopeSpans"][0]["spans"][0];
+        assert_eq!(span["traceId"], "00000000000000000000000000000001");
+        assert_eq!(span["parentSpanId"], "0000000000000003");
+        assert_eq!(span["startTimeUnixNano"], "1000000000");
+        assert_eq!(span["endTimeUnixNano"], "1005000000");
+        assert_eq!(span["attributes"][0]["value"]["intValue"], "1");
+    }
+}
@@ -0,0 +1,156 @@
+use std::io;
+use std::path::Path;
+
+use rhai::{Dynamic, Engine, Map, Scope, AST};
+
+use crate::storage::Record;
+
+/// Name of the function a transform script must define.
+const ENTRY_POINT: &str = "transform";
+/// Upper bound on script work per record so a runaway loop cannot stall the job.
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+/// A Rhai script that rewrites or drops records before they are stored.
+///
+/// The script defines `fn transform(record)` where `record` is a map with
+/// `timestamp`, `value`, `exit_code`, `stderr` and any extra fields. It
+/// returns the (modified) map to keep the record, or `()` to drop it. Keys
+/// other than the core ones become extra fields on the stored record.
+pub struct Transform {
+    engine: Engine,
+    ast: AST,
+}
+
+impl Transform {
+    pub fn from_file(path: &Path) -> io::Result<Self> {
+        let script = std::fs::read_to_string(path).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("cannot read transform script {}: {e}", path.display()),
+            )
+        })?;
+        Self::from_source(&script).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("transform script {}: {e}", path.display()),
+            )
+        })
+    }
+
+    pub fn from_source(script: &str) -> io::Result<Self> {
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        let ast = engine
+            .compile(script)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?;
+        if !ast.iter_functions().any(|f| f.name == ENTRY_POINT) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("script must define `fn {ENTRY_POINT}(record)`"),
+            ));
+        }
+        Ok(Self { engine, ast })
+    }
+
+    /// Run the script on `record`. Returns `Ok(None)` when the script drops it.
+    pub fn apply(&self, record: Record, stderr: &str) -> io::Result<Option<Record>> {
+        let mut input = Map::new();
+        input.insert("timestamp".into(), record.timestamp.clone().into());
+        input.insert("value".into(), record.value.clone().into());
+        input.insert(
+            "exit_code".into(),
+            Dynamic::from_int(record.exit_code.into()),
+        );
+        input.insert("stderr".into(), stderr.into());
+        for (key, value) in &record.fields {
+            let value = rhai::serde::to_dynamic(value).map_err(script_error)?;
+            input.insert(key.as_str().into(), value);
+        }
+
+        let mut scope = Scope::new();
+        let output: Dynamic = self
+            .engine
+            .call_fn(&mut scope, &self.ast, ENTRY_POINT, (input,))
+            .map_err(script_error)?;
+        if output.is_unit() {
+            return Ok(None);
+        }
+        let Some(map) = output.try_cast::<Map>() else {
+            return Err(io::Error::other(
+                "transform must return the record map or () to drop it",
+            ));
+        };
+        into_record(record, map).map(Some)
+    }
+}
+
+fn into_record(mut record: Record, map: Map) -> io::Result<Record> {
+    record.fields.clear();
+    for (key, value) in map {
+        match key.as_str() {
+            "stderr" => {}
+            "timestamp" => record.timestamp = value.to_string(),
+            "value" => record.value = value.to_string(),
+            "exit_code" => {
+                let code = value
+                    .as_int()
+                    .ok()
+                    .and_then(|c| i32::try_from(c).ok())
+                    .ok_or_else(|| io::Error::other("transform set a non-integer exit_code"))?;
+                record.exit_code = code;
+            }
+            _ => {
+                let value: serde_json::Value =
+                    rhai::serde::from_dynamic(&value).map_err(script_error)?;
+                record.fields.insert(key.to_string(), value);
+            }
+        }
+    }
+    Ok(record)
+}
+
+fn script_error(e: Box<rhai::EvalAltResult>) -> io::Error {
+    io::Error::other(format!("transform script: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rewrites_value_and_adds_fields() {
+        let t = Transform::from_source(
+            r#"
+            fn transform(r) {
+                r.value = (parse_float(r.value) * 2.0).to_string();
+                r.source = r.stderr;
+                r
+            }
+            "#,
+        )
+        .unwrap();
+        let out = t
+            .apply(Record::new("2025-01-01T00:00:00Z", "21.5", 0), "sensor-a")
+            .unwrap()
+            .unwrap();
+        assert_eq!(out.value, "43.0");
+        assert_eq!(out.fields["source"], "sensor-a");
+        assert!(!out.fields.contains_key("stderr"));
+    }
+
+    #[test]
+    fn unit_return_drops_record() {
+        let t = Transform::from_source("fn transform(r) { if r.exit_code != 0 { return (); } r }")
+            .unwrap();
+        let ok = Record::new("t", "v", 0);
+        let failed = Record::new("t", "v", 1);
+        assert!(t.apply(ok, "").unwrap().is_some());
+        assert!(t.apply(failed, "").unwrap().is_none());
+    }
+
+    #[test]
+    fn requires_transform_function() {
+        assert!(Transform::from_source("fn other(r) { r }").is_err());
+        assert!(Transform::from_source("fn transform(r) {").is_err());
+    }
+}
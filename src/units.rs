@@ -0,0 +1,108 @@
+/// Parse a value with a unit suffix (`1.2G`, `350ms`, `85%`) into its
+/// canonical number and unit: bytes (`B`), seconds (`s`) or percent (`%`).
+///
+/// Single-letter and IEC size suffixes (`K`, `M`, `Gi`, `KiB`, ...) are
+/// binary, as printed by `df -h` and `free -h`; SI suffixes spelled with a
+/// trailing `B` (`kB`, `MB`, `GB`, ...) are decimal. Lowercase `m` means
+/// minutes, uppercase `M` means mebi. Returns `None` if `raw` is not a
+/// number followed by a known suffix; a bare number yields an empty unit.
+pub fn normalize(raw: &str) -> Option<(f64, &'static str)> {
+    let s = raw.trim();
+    let split = s
+        .char_indices()
+        .find(|&(i, c)| {
+            !(c.is_ascii_digit()
+                || c == '.'
+                || ((c == '-' || c == '+') && i == 0)
+                || ((c == 'e' || c == 'E') && i > 0 && is_exponent(s, i)))
+        })
+        .map(|(i, _)| i)
+        .unwrap_or(s.len());
+    let (number, suffix) = s.split_at(split);
+    let number: f64 = number.parse().ok()?;
+    let (mul, div, unit) = unit_factor(suffix.trim())?;
+    // Dividing for sub-unit suffixes keeps e.g. 350ms at exactly 0.35.
+    Some((number * mul / div, unit))
+}
+
+/// Whether the `e`/`E` at byte `i` starts an exponent, as in `1e3`.
+fn is_exponent(s: &str, i: usize) -> bool {
+    let rest = &s[i + 1..];
+    let rest = rest.strip_prefix(['-', '+']).unwrap_or(rest);
+    rest.starts_with(|c: char| c.is_ascii_digit())
+}
+
+/// Returns (multiplier, divisor, canonical unit) for a suffix.
+fn unit_factor(suffix: &str) -> Option<(f64, f64, &'static str)> {
+    const KI: f64 = 1024.0;
+    let bytes = |exp: i32| Some((KI.powi(exp), 1.0, "B"));
+    let si_bytes = |exp: i32| Some((1000f64.powi(exp), 1.0, "B"));
+    let secs = |factor: f64| Some((factor, 1.0, "s"));
+    let sub_secs = |divisor: f64| Some((1.0, divisor, "s"));
+    match suffix {
+        "" => Some((1.0, 1.0, "")),
+        "%" => Some((1.0, 1.0, "%")),
+        "B" | "b" => bytes(0),
+        "K" | "k" | "Ki" | "KiB" => bytes(1),
+        "M" | "Mi" | "MiB" => bytes(2),
+        "G" | "g" | "Gi" | "GiB" => bytes(3),
+        "T" | "t" | "Ti" | "TiB" => bytes(4),
+        "P" | "Pi" | "PiB" => bytes(5),
+        "kB" | "KB" => si_bytes(1),
+        "MB" => si_bytes(2),
+        "GB" => si_bytes(3),
+        "TB" => si_bytes(4),
+        "PB" => si_bytes(5),
+        "ns" => sub_secs(1e9),
+        "us" | "µs" => sub_secs(1e6),
+        "ms" => sub_secs(1e3),
+        "s" | "sec" => secs(1.0),
+        "m" | "min" => secs(60.0),
+        "h" => secs(3600.0),
+        "d" => secs(86400.0),
+        _ => None,
+    }
+}
+
+/// Format a normalized number without a trailing `.0` for whole values.
+pub fn format_number(n: f64) -> String {
+    if n.fract() == 0.0 && n.abs() < 1e15 {
+        format!("{}", n as i64)
+    } else {
+        format!("{n}")
+    }
+}
+
+/// A number as a JSON field value, using an integer when it is whole.
+pub fn number_value(n: f64) -> serde_json::Value {
+    if n.fract() == 0.0 && n.abs() < 1e15 {
+        serde_json::Value::from(n as i64)
+    } else {
+        serde_json::Value::from(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_common_suffixes() {
+        assert_eq!(normalize("1.5G"), Some((1.5 * 1024f64.powi(3), "B")));
+        assert_eq!(normalize("512Mi"), Some((512.0 * 1024f64.powi(2), "B")));
+        assert_eq!(normalize("2 kB"), Some((2000.0, "B")));
+        assert_eq!(normalize("350ms"), Some((0.35, "s")));
+        assert_eq!(normalize("5m"), Some((300.0, "s")));
+        assert_eq!(normalize("85%"), Some((85.0, "%")));
+        assert_eq!(normalize("-3"), Some((-3.0, "")));
+        assert_eq!(normalize("1e3ms"), Some((1.0, "s")));
+        assert_eq!(normalize("12 apples"), None);
+        assert_eq!(normalize("ms"), None);
+    }
+
+    #[test]
+    fn formats_whole_numbers_without_fraction() {
+        assert_eq!(format_number(1024.0), "1024");
+        assert_eq!(format_number(0.35), "0.35");
+    }
+}
@@ -1,4 +1,5 @@
 use chrono::NaiveDate;
+use std::io;
 use std::path::{Path, PathBuf};
 use std::time::Duration;
 
@@ -6,12 +7,29 @@ pub fn parse_duration_str(s: &str) -> Result<Duration, humantime::DurationError>
     humantime::parse_duration(s)
 }
 
+/// Parse a duration option, naming it as `what` in the error.
+pub fn duration_arg(s: &str, what: &str) -> io::Result<Duration> {
+    parse_duration_str(s).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid {what} '{s}': {e}"),
+        )
+    })
+}
+
 pub fn record_file_path(data_dir: &Path, date: &NaiveDate, fmt: &str) -> PathBuf {
     let ext = if fmt == "csv" { "csv" } else { "jsonl" };
     let date_str = date.format("%Y-%m-%d").to_string();
     data_dir.join(format!("{date_str}.{ext}"))
 }
 
+/// Path of the file holding records that failed validation on `date`.
+pub fn rejects_file_path(data_dir: &Path, date: &NaiveDate, fmt: &str) -> PathBuf {
+    let ext = if fmt == "csv" { "csv" } else { "jsonl" };
+    let date_str = date.format("%Y-%m-%d").to_string();
+    data_dir.join(format!("{date_str}.rejects.{ext}"))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -27,5 +45,22 @@ mod tests {
             record_file_path(&base, &date, "jsonl"),
             PathBuf::from("/tmp/data/2025-01-02.jsonl")
         );
+        assert_eq!(
+            rejects_file_path(&base, &date, "csv"),
+            PathBuf::from("/tmp/data/2025-01-02.rejects.csv")
+        );
+    }
+
+    #[test]
+    fn duration_parse_valid() {
+        assert_eq!(parse_duration_str("1s").unwrap(), Duration::from_secs(1));
+        assert_eq!(parse_duration_str("2m").unwrap(), Duration::from_secs(120));
+        assert!(parse_duration_str("500ms").unwrap() <= Duration::from_millis(500));
+    }
+
+    #[test]
+    fn duration_parse_invalid() {
+        assert!(parse_duration_str("").is_err());
+        assert!(parse_duration_str("notaduration").is_err());
     }
 }
@@ -0,0 +1,102 @@
+use std::io;
+use std::str::FromStr;
+
+use regex::Regex;
+
+/// A check applied to the value of each successful record.
+#[derive(Debug, Clone)]
+pub enum Rule {
+    /// `regex:<pattern>`: the value must match the pattern.
+    Regex(Regex),
+    /// `range:<min>..<max>`: the value must be a number within the inclusive
+    /// bounds. Either bound may be omitted (`range:0..`, `range:..100`).
+    Range(Option<f64>, Option<f64>),
+}
+
+fn invalid(rule: &str, msg: impl std::fmt::Display) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("invalid validation rule '{rule}': {msg}"),
+    )
+}
+
+impl FromStr for Rule {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> io::Result<Self> {
+        if let Some(pattern) = s.strip_prefix("regex:") {
+            let re = Regex::new(pattern).map_err(|e| invalid(s, e))?;
+            return Ok(Rule::Regex(re));
+        }
+        if let Some(range) = s.strip_prefix("range:") {
+            let (min, max) = range
+                .split_once("..")
+                .ok_or_else(|| invalid(s, "expected <min>..<max>"))?;
+            let bound = |b: &str| -> io::Result<Option<f64>> {
+                let b = b.trim();
+                if b.is_empty() {
+                    return Ok(None);
+                }
+                b.parse()
+                    .map(Some)
+                    .map_err(|_| invalid(s, format!("'{b}' is not a number")))
+            };
+            let (min, max) = (bound(min)?, bound(max)?);
+            if let (Some(lo), Some(hi)) = (min, max) {
+                if lo > hi {
+                    return Err(invalid(s, "min is greater than max"));
+                }
+            }
+            return Ok(Rule::Range(min, max));
+        }
+        Err(invalid(
+            s,
+            "expected 'regex:<pattern>' or 'range:<min>..<max>'",
+        ))
+    }
+}
+
+impl Rule {
+    pub fn check(&self, value: &str) -> bool {
+        match self {
+            Rule::Regex(re) => re.is_match(value),
+            Rule::Range(min, max) => match value.trim().parse::<f64>() {
+                Ok(n) if !n.is_nan() => {
+                    min.is_none_or(|lo| n >= lo) && max.is_none_or(|hi| n <= hi)
+                }
+                _ => false,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_and_checks_rules() {
+        let digits: Rule = r"regex:^\d+$".parse().unwrap();
+        assert!(digits.check("42"));
+        assert!(!digits.check("4.2"));
+
+        let pct: Rule = "range:0..100".parse().unwrap();
+        assert!(pct.check("0"));
+        assert!(pct.check("100"));
+        assert!(!pct.check("100.1"));
+        assert!(!pct.check("n/a"));
+
+        let positive: Rule = "range:0..".parse().unwrap();
+        assert!(positive.check("1e9"));
+        assert!(!positive.check("-1"));
+    }
+
+    #[test]
+    fn rejects_malformed_rules() {
+        assert!("regex:(".parse::<Rule>().is_err());
+        assert!("range:5".parse::<Rule>().is_err());
+        assert!("range:10..1".parse::<Rule>().is_err());
+        assert!("range:a..b".parse::<Rule>().is_err());
+        assert!("length:3".parse::<Rule>().is_err());
+    }
+}
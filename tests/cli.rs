@@ -0,0 +1,189 @@
+//! Runs the `trep` binary itself, for what only shows once the process
+//! exits.
+
+use std::path::Path;
+use std::process::Command;
+
+fn trep(home: &Path) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_trep"));
+    command
+        .env("HOME", home)
+        .env("USERPROFILE", home)
+        .current_dir(home);
+    command
+}
+
+#[test]
+fn delivers_the_alert_of_the_last_run_before_exiting() {
+    let home = tempfile::tempdir().unwrap();
+    let status = trep(home.path())
+        .args([
+            "run",
+            "--as",
+            "f",
+            "--alert-command",
+            "echo fired > out",
+            "--",
+            "exit 3",
+        ])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let out = std::fs::read_to_string(home.path().join("out")).unwrap();
+    assert_eq!(out.trim(), "fired");
+}
+
+#[cfg(unix)]
+#[test]
+fn reads_past_the_stop_marker() {
+    use std::time::{Duration, Instant};
+
+    let home = tempfile::tempdir().unwrap();
+    let mut job = trep(home.path())
+        .args(["run", "--as", "f", "--every", "100ms", "--", "exit 3"])
+        .spawn()
+        .unwrap();
+    let output = |args: &[&str]| {
+        let out = trep(home.path()).args(args).output().unwrap();
+        String::from_utf8(out.stdout).unwrap()
+    };
+    let deadline = Instant::now() + Duration::from_secs(20);
+    while output(&["last", "f", "-n", "2"]).lines().count() < 2 {
+        assert!(Instant::now() < deadline, "the job stored no records");
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    let stop = trep(home.path())
+        .args(["stop", "--as", "f"])
+        .output()
+        .unwrap();
+    assert!(stop.status.success());
+    job.wait().unwrap();
+
+    assert_eq!(output(&["last", "f", "--field", "exit_code"]).trim(), "3");
+    let sla = output(&["sla", "f"]);
+    assert!(sla.contains("Outages:"), "{sla}");
+}
+
+#[cfg(unix)]
+#[test]
+fn names_every_job_a_stop_ends() {
+    use std::time::{Duration, Instant};
+
+    let home = tempfile::tempdir().unwrap();
+    let jobs = home.path().join("jobs.toml");
+    std::fs::write(
+        &jobs,
+        "[[job]]\nname = \"cpu\"\ncommand = \"echo 1\"\nevery = \"100ms\"\n\n\
+         [[job]]\nname = \"mem\"\ncommand = \"echo 2\"\nevery = \"100ms\"\n",
+    )
+    .unwrap();
+    let mut start = trep(home.path())
+        .arg("start")
+        .arg("--config")
+        .arg(&jobs)
+        .stdout(std::process::Stdio::null())
+        .spawn()
+        .unwrap();
+    let running = |name: &str| {
+        let out = trep(home.path()).args(["status", "--as", name]).output();
+        String::from_utf8(out.unwrap().stdout)
+            .unwrap()
+            .contains("running (pid")
+    };
+    let deadline = Instant::now() + Duration::from_secs(20);
+    while !(running("cpu") && running("mem")) {
+        assert!(Instant::now() < deadline, "the jobs did not start");
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    let stop = trep(home.path())
+        .args(["stop", "--as", "cpu"])
+        .output()
+        .unwrap();
+    assert!(stop.status.success());
+    let said = String::from_utf8(stop.stdout).unwrap();
+    assert!(said.starts_with("Stopped 'cpu', 'mem' (pid "), "{said}");
+    start.wait().unwrap();
+    assert!(!running("mem"));
+}
+
+#[test]
+fn exports_parquet_that_a_reader_takes() {
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use parquet::record::Field;
+
+    let home = tempfile::tempdir().unwrap();
+    let base = home.path().join(".tiny-reporter");
+    // Enough fields for more than 15 columns, which Thrift writes with a
+    // longer list header; the second record lacks most of them.
+    let fields: Vec<String> = (1..=12).map(|i| format!("\"f{i:02}\":{i}")).collect();
+    std::fs::create_dir_all(base.join("a")).unwrap();
+    std::fs::write(
+        base.join("a").join("2025-01-01.jsonl"),
+        format!(
+            "{{\"timestamp\":\"2025-01-01T00:00:00Z\",\"value\":\"1.5\",\"exit_code\":0,\"ok\":true,\"x\":1,{}}}\n\
+             {{\"timestamp\":\"2025-01-01T00:01:00+09:00\",\"value\":\"oops\",\"exit_code\":2,\"ok\":false,\"x\":2.5}}\n",
+            fields.join(",")
+        ),
+    )
+    .unwrap();
+    std::fs::create_dir_all(base.join("b")).unwrap();
+    std::fs::write(
+        base.join("b").join("2025-01-01.csv"),
+        "2025-01-01T00:00:30Z,b1,1\n",
+    )
+    .unwrap();
+    let out = home.path().join("out.parquet");
+    let status = trep(home.path())
+        .args([
+            "export", "--as", "a", "--as", "b", "--to", "parquet", "--out",
+        ])
+        .arg(&out)
+        .stdout(std::process::Stdio::null())
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let reader = SerializedFileReader::new(std::fs::File::open(&out).unwrap()).unwrap();
+    let schema = reader.metadata().file_metadata().schema_descr_ptr();
+    assert_eq!(schema.num_columns(), 18);
+    let rows: Vec<Vec<(String, Field)>> = reader
+        .get_row_iter(None)
+        .unwrap()
+        .map(|row| {
+            row.unwrap()
+                .get_column_iter()
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect()
+        })
+        .collect();
+    let cell = |row: usize, name: &str| {
+        rows[row]
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.clone())
+            .unwrap()
+    };
+    assert_eq!(rows.len(), 3);
+    // In timestamp order: 2024-12-31T15:01Z, then the two on 2025-01-01.
+    assert_eq!(
+        cell(0, "timestamp"),
+        Field::TimestampMicros(1_735_657_260_000_000)
+    );
+    assert_eq!(
+        cell(1, "timestamp"),
+        Field::TimestampMicros(1_735_689_600_000_000)
+    );
+    let values: Vec<Field> = (0..3).map(|row| cell(row, "value")).collect();
+    assert_eq!(
+        values,
+        ["oops", "1.5", "b1"].map(|v| Field::Str(v.to_string()))
+    );
+    assert_eq!(cell(0, "exit_code"), Field::Int(2));
+    assert_eq!(cell(2, "job"), Field::Str("b".to_string()));
+    assert_eq!(cell(0, "ok"), Field::Bool(false));
+    assert_eq!(cell(2, "ok"), Field::Null);
+    assert_eq!(cell(0, "x"), Field::Double(2.5));
+    assert_eq!(cell(1, "x"), Field::Double(1.0));
+    assert_eq!(cell(0, "f12"), Field::Null);
+    assert_eq!(cell(1, "f12"), Field::Long(12));
+}
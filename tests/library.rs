@@ -0,0 +1,329 @@
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex, Once};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chrono::NaiveDate;
+
+use trep::alert::AlertOptions;
+use trep::exec::{ExecSpec, Shell};
+use trep::job::Retry;
+use trep::storage::{self, Record};
+use trep::{exec, Error, Job, RecordStore, Scheduler, Sink, Stop};
+
+/// A sink that keeps what it receives, for the test to look at.
+#[derive(Clone, Default)]
+struct Collect(Arc<Mutex<Vec<Record>>>);
+
+impl Collect {
+    fn values(&self) -> Vec<String> {
+        self.0
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|r| r.value.clone())
+            .collect()
+    }
+
+    fn wait_for(&self, count: usize) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while self.0.lock().unwrap().len() < count {
+            assert!(Instant::now() < deadline, "got {:?}", self.values());
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+}
+
+impl Sink for Collect {
+    fn name(&self) -> &str {
+        "collect"
+    }
+
+    fn send(&mut self, record: &Record) -> io::Result<()> {
+        self.0.lock().unwrap().push(record.clone());
+        Ok(())
+    }
+}
+
+/// A store whose every write fails.
+struct Broken;
+
+impl RecordStore for Broken {
+    fn write(&mut self, _date: NaiveDate, _record: &Record) -> io::Result<()> {
+        Err(io::Error::other("disk full"))
+    }
+
+    fn write_marker(&mut self, _date: NaiveDate, _record: Record) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn reopen(&mut self) {}
+}
+
+/// Jobs with a data directory of their own record it under the base
+/// directory; keep that out of the real home.
+fn isolate_base_dir() {
+    static ONCE: Once = Once::new();
+    ONCE.call_once(|| {
+        std::env::set_var(
+            "TREP_HOME",
+            Path::new(env!("CARGO_TARGET_TMPDIR")).join("trep-home"),
+        );
+    });
+}
+
+fn stored_values(dir: &Path) -> Vec<String> {
+    storage::list_data_files(dir)
+        .unwrap()
+        .iter()
+        .flat_map(|file| storage::read_records(&file.path).unwrap())
+        .map(|r| r.value)
+        .collect()
+}
+
+#[test]
+fn runs_a_command_once() {
+    isolate_base_dir();
+    let dir = tempfile::tempdir().unwrap();
+    Job::command("hello", "echo hello")
+        .data_dir(dir.path())
+        .run(&Stop::new())
+        .unwrap();
+    assert_eq!(stored_values(dir.path()), ["hello"]);
+    assert!(!dir.path().join("hello.meta.json").exists());
+}
+
+#[test]
+fn finds_the_records_of_a_job_with_its_own_data_dir() {
+    isolate_base_dir();
+    let dir = tempfile::tempdir().unwrap();
+    Job::command("elsewhere", "echo here")
+        .data_dir(dir.path())
+        .run(&Stop::new())
+        .unwrap();
+    let files = storage::job_data_files("elsewhere").unwrap();
+    assert!(files.iter().all(|f| f.path.starts_with(dir.path())));
+    let records = storage::read_job_records("elsewhere").unwrap();
+    assert_eq!(records.last().unwrap().value, "here");
+}
+
+#[test]
+fn stops_a_repeating_job_with_a_marker() {
+    isolate_base_dir();
+    let dir = tempfile::tempdir().unwrap();
+    let seen = Collect::default();
+    let stop = Stop::new();
+    let job = Job::command("tick", "echo tick")
+        .every(Duration::from_millis(50))
+        .format("jsonl")
+        .data_dir(dir.path())
+        .sink(Box::new(seen.clone()));
+    thread::scope(|scope| {
+        let running = scope.spawn(|| job.run(&stop));
+        seen.wait_for(2);
+        assert!(dir.path().join("tick.meta.json").exists());
+
+        // The lock keeps a second copy of the job from running.
+        let again = Job::command("tick", "echo tick").data_dir(dir.path());
+        match again.run(&Stop::new()) {
+            Err(Error::Locked { job }) => assert_eq!(job, "tick"),
+            other => panic!("expected the job to be locked, got {other:?}"),
+        }
+
+        stop.stop();
+        running.join().unwrap().unwrap();
+    });
+    let values = seen.values();
+    assert_eq!(values.last().unwrap(), "reporter_stopped: stopped");
+    assert!(seen.0.lock().unwrap().last().unwrap().is_marker());
+    assert_eq!(stored_values(dir.path()), values);
+}
+
+#[test]
+fn schedules_several_jobs() {
+    isolate_base_dir();
+    let dirs = [tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap()];
+    let mut scheduler = Scheduler::new();
+    scheduler
+        .add(Job::command("a", "echo a").data_dir(dirs[0].path()))
+        .add(
+            Job::command("b", "echo b")
+                .format("sqlite")
+                .data_dir(dirs[1].path()),
+        )
+        .add(Job::command("c", "true").format("xml"));
+    let results = scheduler.run(&Stop::new());
+    let names: Vec<&str> = results.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(names, ["a", "b", "c"]);
+    assert!(results[0].1.is_ok() && results[1].1.is_ok());
+    assert!(matches!(
+        results[2].1,
+        Err(Error::InvalidOption {
+            option: "--format",
+            ..
+        })
+    ));
+    assert_eq!(stored_values(dirs[0].path()), ["a"]);
+    assert_eq!(stored_values(dirs[1].path()), ["b"]);
+}
+
+#[test]
+fn alerts_on_a_failed_run() {
+    isolate_base_dir();
+    let dir = tempfile::tempdir().unwrap();
+    let out = dir.path().join("alert.txt");
+    let alert = AlertOptions {
+        command: Some(format!(
+            "echo \"$TREP_REASON $TREP_EXIT_CODE\" > '{}'",
+            out.display()
+        )),
+        ..AlertOptions::default()
+    };
+    Job::command("failing", "exit 4")
+        .data_dir(dir.path())
+        .alert(alert)
+        .run(&Stop::new())
+        .unwrap();
+    // Delivered in the background, but before the job returns.
+    let alert = std::fs::read_to_string(&out).unwrap();
+    assert_eq!(alert.trim(), "failed 4");
+}
+
+#[test]
+fn retries_a_failing_command() {
+    isolate_base_dir();
+    let dir = tempfile::tempdir().unwrap();
+    let count = dir.path().join("count");
+    // Fails twice, then succeeds.
+    let command = format!(
+        "echo x >> '{0}'; test $(wc -l < '{0}') -ge 3",
+        count.display()
+    );
+    let retry = Retry {
+        retries: 3,
+        delay: Duration::from_millis(10),
+        backoff: 2.0,
+    };
+    let seen = Collect::default();
+    Job::command("flaky", command)
+        .data_dir(dir.path())
+        .retry(retry)
+        .sink(Box::new(seen.clone()))
+        .run(&Stop::new())
+        .unwrap();
+    let record = seen.0.lock().unwrap()[0].clone();
+    assert_eq!(record.exit_code, 0);
+    assert_eq!(record.fields["attempts"], 3);
+    assert_eq!(record.fields["succeeded"], true);
+
+    // A stop cuts a retry delay short; the failed attempt is still stored.
+    let stop = Stop::new();
+    let slow = Retry {
+        delay: Duration::from_secs(60),
+        ..retry
+    };
+    let started = Instant::now();
+    thread::scope(|scope| {
+        let running = scope.spawn(|| {
+            Job::command("down", "exit 7")
+                .data_dir(dir.path())
+                .retry(slow)
+                .run(&stop)
+        });
+        thread::sleep(Duration::from_millis(500));
+        stop.stop();
+        running.join().unwrap().unwrap();
+    });
+    assert!(started.elapsed() < Duration::from_secs(10));
+    let records: Vec<Record> = storage::list_data_files(dir.path())
+        .unwrap()
+        .iter()
+        .flat_map(|file| storage::read_records(&file.path).unwrap())
+        .filter(|r| r.exit_code == 7)
+        .collect();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].fields["attempts"], 1);
+    assert_eq!(records[0].fields["succeeded"], false);
+}
+
+#[test]
+fn stores_only_changes() {
+    isolate_base_dir();
+    let dir = tempfile::tempdir().unwrap();
+    let value = dir.path().join("value");
+    let command = format!("cat '{}'", value.display());
+    let run = || {
+        Job::command("version", &command)
+            .data_dir(dir.path())
+            .on_change(None)
+            .run(&Stop::new())
+            .unwrap();
+    };
+    // Each run is a fresh job, as with cron, so the last value comes back
+    // from the state file.
+    for v in ["1.0", "1.0", "1.1", "1.1", "1.0"] {
+        std::fs::write(&value, v).unwrap();
+        run();
+    }
+    assert_eq!(stored_values(dir.path()), ["1.0", "1.1", "1.0"]);
+
+    // A record that could not be stored is not what later ones are
+    // compared with.
+    std::fs::write(&value, "2.0").unwrap();
+    let failed = Job::command("version", &command)
+        .data_dir(dir.path())
+        .on_change(None)
+        .recorder(Box::new(Broken))
+        .run(&Stop::new());
+    assert!(failed.is_err());
+    run();
+    assert_eq!(stored_values(dir.path()), ["1.0", "1.1", "1.0", "2.0"]);
+}
+
+#[test]
+fn runs_commands_with_a_spec() {
+    let dir = tempfile::tempdir().unwrap();
+    // Without a shell, `$HOME` and `;` reach the program as they are.
+    let spec = ExecSpec::direct(["echo", "$HOME;", "x"]);
+    assert_eq!(exec::run_command(&spec, None).unwrap().stdout, "$HOME; x");
+
+    let spec = ExecSpec {
+        shell: Some(Shell::Sh),
+        ..ExecSpec::shell("echo \"$GREETING from $(basename \"$PWD\")\"")
+    }
+    .cwd(dir.path())
+    .env("GREETING", "hello");
+    let name = dir.path().file_name().unwrap().to_str().unwrap();
+    assert_eq!(
+        exec::run_command(&spec, None).unwrap().stdout,
+        format!("hello from {name}")
+    );
+
+    let err = exec::run_command(&ExecSpec::direct(["/nonexistent/program"]), None).unwrap_err();
+    assert!(matches!(err, Error::Spawn { .. }), "{err}");
+}
+
+#[test]
+fn runs_shell_commands() {
+    let out = exec::run_command(&ExecSpec::shell("echo out; echo err >&2; exit 3"), None).unwrap();
+    assert_eq!(out.stdout, "out");
+    // A login shell's profile may write to stderr too.
+    assert_eq!(out.stderr.lines().last(), Some("err"));
+    assert_eq!(out.exit_code, 3);
+
+    let started = Instant::now();
+    let err = exec::run_command(
+        &ExecSpec::shell("sleep 5"),
+        Some(Duration::from_millis(200)),
+    )
+    .unwrap_err();
+    assert!(started.elapsed() < Duration::from_secs(4));
+    match err {
+        Error::Timeout { command, after } => {
+            assert_eq!(command, "sleep 5");
+            assert_eq!(after, Duration::from_millis(200));
+        }
+        other => panic!("expected a timeout, got {other}"),
+    }
+}
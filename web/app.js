@@ -0,0 +1,150 @@
+// Polls the trep JSON API and draws the job list, a chart of the selected
+// job's numeric values and its failed runs.
+"use strict";
+
+const REFRESH_MS = 10000;
+let selected = null;
+
+function cell(text, className) {
+  const td = document.createElement("td");
+  td.textContent = text;
+  if (className) td.className = className;
+  return td;
+}
+
+function formatTime(timestamp) {
+  const t = new Date(timestamp);
+  return isNaN(t) ? timestamp : t.toLocaleString();
+}
+
+// The API token, when the server asks for one; kept in this browser.
+let token = localStorage.getItem("trep-token");
+
+async function getJson(url) {
+  const headers = token ? { Authorization: `Bearer ${token}` } : {};
+  const response = await fetch(url, { headers });
+  if (response.status === 401) {
+    const entered = prompt("API token");
+    if (entered) {
+      token = entered;
+      localStorage.setItem("trep-token", token);
+      return getJson(url);
+    }
+  }
+  if (!response.ok) throw new Error(`${url}: ${response.status}`);
+  return response.json();
+}
+
+async function loadJobs() {
+  const jobs = await getJson("api/jobs");
+  if (selected === null && jobs.length > 0) selected = jobs[0].name;
+  const body = document.querySelector("#jobs tbody");
+  body.replaceChildren();
+  for (const job of jobs) {
+    const tr = document.createElement("tr");
+    if (job.latest && job.latest.exit_code !== 0) tr.className = "failed";
+    if (job.name === selected) tr.classList.add("selected");
+    tr.append(
+      cell(job.name),
+      cell(job.latest ? job.latest.value : "-", "number"),
+      cell(job.next_run ? formatTime(job.next_run) : "-"),
+    );
+    tr.addEventListener("click", () => select(job.name));
+    body.append(tr);
+  }
+}
+
+// Every record of a job, following the API's pages.
+async function getRecords(name) {
+  const base = `api/jobs/${encodeURIComponent(name)}/records?limit=10000`;
+  let records = [];
+  let cursor = null;
+  do {
+    const url = cursor === null ? base : `${base}&cursor=${encodeURIComponent(cursor)}`;
+    const page = await getJson(url);
+    records = records.concat(page.records);
+    cursor = page.next_cursor;
+  } while (cursor !== null);
+  return records;
+}
+
+async function loadJob(name) {
+  const records = await getRecords(name);
+  document.getElementById("job-name").textContent = name;
+  drawChart(records);
+  const body = document.querySelector("#failures tbody");
+  body.replaceChildren();
+  for (const record of records.filter((r) => r.exit_code !== 0).reverse()) {
+    const tr = document.createElement("tr");
+    tr.append(
+      cell(formatTime(record.timestamp)),
+      cell(String(record.exit_code), "number"),
+      cell(record.value),
+    );
+    body.append(tr);
+  }
+  document.getElementById("job").hidden = false;
+}
+
+// A line of the numeric values over time, with failed runs marked in red.
+function drawChart(records) {
+  const svg = document.getElementById("chart");
+  const ns = "http://www.w3.org/2000/svg";
+  const points = records
+    .map((r) => ({ t: Date.parse(r.timestamp), v: Number(r.value), failed: r.exit_code !== 0 }))
+    .filter((p) => !isNaN(p.t) && isNumber(p.v));
+  svg.replaceChildren();
+  const note = document.getElementById("chart-note");
+  if (points.length === 0) {
+    note.textContent = "No numeric values to chart.";
+    return;
+  }
+  const [w, h, pad] = [800, 240, 10];
+  const t0 = Math.min(...points.map((p) => p.t));
+  const t1 = Math.max(...points.map((p) => p.t));
+  const v0 = Math.min(...points.map((p) => p.v));
+  const v1 = Math.max(...points.map((p) => p.v));
+  const x = (t) => (t1 === t0 ? w / 2 : pad + ((t - t0) / (t1 - t0)) * (w - 2 * pad));
+  const y = (v) => (v1 === v0 ? h / 2 : h - pad - ((v - v0) / (v1 - v0)) * (h - 2 * pad));
+  const line = document.createElementNS(ns, "polyline");
+  line.setAttribute("points", points.map((p) => `${x(p.t)},${y(p.v)}`).join(" "));
+  svg.append(line);
+  for (const p of points.filter((p) => p.failed)) {
+    const dot = document.createElementNS(ns, "circle");
+    dot.setAttribute("cx", x(p.t));
+    dot.setAttribute("cy", y(p.v));
+    dot.setAttribute("r", 3);
+    svg.append(dot);
+  }
+  note.textContent = `${points.length} values from ${formatTime(t0)} to ${formatTime(t1)}, ` +
+    `between ${v0} and ${v1}`;
+}
+
+function isNumber(v) {
+  return typeof v === "number" && isFinite(v);
+}
+
+function select(name) {
+  selected = name;
+  for (const tr of document.querySelectorAll("#jobs tbody tr")) {
+    tr.classList.toggle("selected", tr.firstChild.textContent === name);
+  }
+  loadJob(name).catch(showError);
+}
+
+function showError(e) {
+  document.getElementById("updated").textContent = `Error: ${e.message}`;
+}
+
+async function refresh() {
+  try {
+    await loadJobs();
+    if (selected !== null) await loadJob(selected);
+    document.getElementById("updated").textContent = `Updated ${new Date().toLocaleTimeString()}`;
+  } catch (e) {
+    showError(e);
+  }
+}
+
+refresh();
+setInterval(refresh, REFRESH_MS);